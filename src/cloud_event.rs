@@ -0,0 +1,139 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use http::{
+    header::{HeaderName, CONTENT_TYPE},
+    HeaderMap, HeaderValue,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    errors::{ErrorReason, IpError},
+    ip_event::IpEvent,
+};
+
+pub const SPEC_VERSION: &str = "1.0";
+pub const STRUCTURED_CONTENT_TYPE: &str = "application/cloudevents+json; charset=utf-8";
+pub const DATA_CONTENT_TYPE: &str = "application/json";
+
+const EVENT_TYPE_PREFIX: &str = "findip.";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CloudEvent<'a> {
+    pub specversion: &'static str,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub source: String,
+    pub id: String,
+    pub time: String,
+    pub datacontenttype: &'static str,
+    pub data: &'a IpEvent,
+}
+
+impl<'a> CloudEvent<'a> {
+    pub fn new(event: &'a IpEvent, source: String) -> CloudEvent<'a> {
+        CloudEvent {
+            specversion: SPEC_VERSION,
+            event_type: format!("{}{}", EVENT_TYPE_PREFIX, event.event_type),
+            source,
+            id: Uuid::new_v4().to_string(),
+            time: format_time(event.timestamp),
+            datacontenttype: DATA_CONTENT_TYPE,
+            data: event,
+        }
+    }
+
+    // Fails rather than leaving out an attribute that isn't a valid header value.
+    pub fn binary_headers(&self) -> Result<HeaderMap, IpError> {
+        let mut headers = HeaderMap::new();
+        let attributes = [
+            ("ce-specversion", self.specversion.to_string()),
+            ("ce-type", self.event_type.clone()),
+            ("ce-source", self.source.clone()),
+            ("ce-id", self.id.clone()),
+            ("ce-time", self.time.clone()),
+        ];
+
+        for (name, value) in attributes.iter() {
+            let header_value = HeaderValue::from_str(value).map_err(|_| {
+                IpError::new(ErrorReason::InvalidInput(format!(
+                    "{:?} can't be sent as the {} header",
+                    value, name
+                )))
+            })?;
+            headers.insert(HeaderName::from_static(name), header_value);
+        }
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static(DATA_CONTENT_TYPE));
+
+        Ok(headers)
+    }
+}
+
+pub fn default_source(hostname: &str) -> String {
+    format!("/findip/{}", hostname)
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+
+    fn test_event() -> IpEvent {
+        IpEvent::new(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)),
+            Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
+        )
+    }
+
+    #[test]
+    fn test_structured_cloud_event() {
+        let event = test_event();
+        let cloud_event = CloudEvent::new(&event, default_source("node1"));
+        let json = serde_json::to_value(&cloud_event).unwrap();
+
+        assert_eq!(json["specversion"], "1.0");
+        assert_eq!(json["type"], "findip.ip.changed");
+        assert_eq!(json["source"], "/findip/node1");
+        assert_eq!(json["datacontenttype"], "application/json");
+        assert_eq!(json["data"]["ip"], "127.0.0.2");
+        assert_eq!(json["data"]["previousIp"], "127.0.0.1");
+        assert!(json["time"].as_str().unwrap().ends_with('Z'));
+    }
+
+    #[test]
+    fn test_cloud_event_ids_are_unique() {
+        let event = test_event();
+        let first = CloudEvent::new(&event, default_source("node1"));
+        let second = CloudEvent::new(&event, default_source("node1"));
+
+        assert_ne!(first.id, second.id);
+    }
+
+    #[test]
+    fn test_binary_headers() {
+        let event = test_event();
+        let cloud_event = CloudEvent::new(&event, "urn:findip:node1".to_string());
+        let headers = cloud_event.binary_headers().unwrap();
+
+        assert_eq!(headers.get("ce-specversion").unwrap(), "1.0");
+        assert_eq!(headers.get("ce-type").unwrap(), "findip.ip.changed");
+        assert_eq!(headers.get("ce-source").unwrap(), "urn:findip:node1");
+        assert_eq!(
+            headers.get("ce-id").unwrap().to_str().unwrap(),
+            cloud_event.id
+        );
+        assert_eq!(headers.get(CONTENT_TYPE).unwrap(), "application/json");
+    }
+
+    #[test]
+    fn test_binary_headers_with_invalid_source() {
+        let event = test_event();
+        let cloud_event = CloudEvent::new(&event, "/findip/node1\r\nX-Injected: 1".to_string());
+
+        assert!(cloud_event.binary_headers().is_err());
+    }
+}
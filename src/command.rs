@@ -0,0 +1,213 @@
+use std::{collections::HashMap, process::Stdio, time::Duration};
+
+use tokio::{io::AsyncWriteExt, process::Command, time::timeout};
+
+use crate::errors::{ErrorReason, IpError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandSpec {
+    pub program: String,
+    pub args: Vec<String>,
+    pub timeout: Duration,
+    pub env: HashMap<String, String>,
+    pub clear_env: bool,
+    pub working_directory: Option<String>,
+}
+
+impl CommandSpec {
+    pub fn new(program: String, args: Vec<String>, timeout: Duration) -> CommandSpec {
+        CommandSpec {
+            program,
+            args,
+            timeout,
+            env: HashMap::new(),
+            clear_env: false,
+            working_directory: None,
+        }
+    }
+
+    pub fn with_env(mut self, env: HashMap<String, String>) -> CommandSpec {
+        self.env = env;
+        self
+    }
+
+    pub fn with_clear_env(mut self, clear_env: bool) -> CommandSpec {
+        self.clear_env = clear_env;
+        self
+    }
+
+    pub fn with_working_directory(mut self, working_directory: String) -> CommandSpec {
+        self.working_directory = Some(working_directory);
+        self
+    }
+
+    // Takes the first element as the program and the rest as its arguments.
+    pub fn from_argv(argv: &[String], timeout: Duration) -> Option<CommandSpec> {
+        let (program, args) = argv.split_first()?;
+        Some(CommandSpec::new(program.clone(), args.to_vec(), timeout))
+    }
+
+    pub fn run(&self) -> Result<String, IpError> {
+        self.run_with_input(None, &HashMap::new())
+    }
+
+    // Runs the command with `extra_env` layered over the configured environment and, when given,
+    // `input` written to its stdin.
+    pub fn run_with_input(
+        &self,
+        input: Option<&[u8]>,
+        extra_env: &HashMap<String, String>,
+    ) -> Result<String, IpError> {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(self.run_async(input, extra_env))
+    }
+
+    async fn run_async(
+        &self,
+        input: Option<&[u8]>,
+        extra_env: &HashMap<String, String>,
+    ) -> Result<String, IpError> {
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .stdin(if input.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        if self.clear_env {
+            command.env_clear();
+        }
+        command.envs(&self.env).envs(extra_env);
+        if let Some(working_directory) = &self.working_directory {
+            command.current_dir(working_directory);
+        }
+
+        let mut child = command.spawn().map_err(|e| {
+            IpError::new(ErrorReason::CommandFailed(format!(
+                "could not start {}: {}",
+                self.program, e
+            )))
+        })?;
+
+        let stdin = child.stdin.take();
+        let completion = async move {
+            if let (Some(input), Some(mut stdin)) = (input, stdin) {
+                // A command that exits without reading its input shouldn't count as a failure
+                // here, its exit status decides that.
+                let _ = stdin.write_all(input).await;
+            }
+            child.wait_with_output().await
+        };
+
+        // Dropping the child when the timeout fires kills it.
+        let output = timeout(self.timeout, completion)
+            .await
+            .map_err(|_| {
+                IpError::new(ErrorReason::CommandTimedOut(format!(
+                    "{} did not finish within {}s",
+                    self.program,
+                    self.timeout.as_secs_f32()
+                )))
+            })?
+            .map_err(|e| IpError::new(ErrorReason::CommandFailed(e.to_string())))?;
+
+        if output.status.success() {
+            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        } else {
+            Err(IpError::new(ErrorReason::CommandFailed(format!(
+                "{} exited with {}: {}",
+                self.program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))))
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str, timeout: Duration) -> CommandSpec {
+        CommandSpec::new(
+            "sh".to_string(),
+            vec!["-c".to_string(), script.to_string()],
+            timeout,
+        )
+    }
+
+    #[test]
+    fn test_run_returns_stdout() {
+        let output = sh("echo hello", Duration::from_secs(5)).run().unwrap();
+        assert_eq!(output, "hello\n");
+    }
+
+    #[test]
+    fn test_run_with_non_zero_exit() {
+        let result = sh("echo oops >&2; exit 3", Duration::from_secs(5)).run();
+        assert!(result.unwrap_err().to_string().contains("oops"));
+    }
+
+    #[test]
+    fn test_run_with_timeout() {
+        let result = sh("sleep 5", Duration::from_millis(100)).run();
+        assert!(matches!(
+            result.unwrap_err().reason(),
+            ErrorReason::CommandTimedOut(_)
+        ));
+    }
+
+    #[test]
+    fn test_run_with_missing_program() {
+        let spec = CommandSpec::new(
+            "findip-no-such-program".to_string(),
+            vec![],
+            Duration::from_secs(5),
+        );
+        assert!(spec.run().is_err());
+    }
+
+    #[test]
+    fn test_run_with_input_and_env() {
+        let mut env = HashMap::new();
+        env.insert("FINDIP_TEST".to_string(), "from env".to_string());
+
+        let output = sh("cat; echo \" $FINDIP_TEST\"", Duration::from_secs(5))
+            .run_with_input(Some(b"from stdin"), &env)
+            .unwrap();
+        assert_eq!(output, "from stdin from env\n");
+    }
+
+    #[test]
+    fn test_run_with_clear_env_and_working_directory() {
+        let mut env = HashMap::new();
+        env.insert("KEPT".to_string(), "yes".to_string());
+
+        let output = CommandSpec::new(
+            "/bin/sh".to_string(),
+            vec!["-c".to_string(), "echo \"$HOME|$KEPT|$(pwd)\"".to_string()],
+            Duration::from_secs(5),
+        )
+        .with_env(env)
+        .with_clear_env(true)
+        .with_working_directory("/".to_string())
+        .run()
+        .unwrap();
+        assert_eq!(output, "|yes|/\n");
+    }
+
+    #[test]
+    fn test_from_argv() {
+        let argv = vec!["nginx".to_string(), "-s".to_string(), "reload".to_string()];
+        let spec = CommandSpec::from_argv(&argv, Duration::from_secs(1)).unwrap();
+
+        assert_eq!(spec.program, "nginx");
+        assert_eq!(spec.args, vec!["-s".to_string(), "reload".to_string()]);
+        assert!(CommandSpec::from_argv(&[], Duration::from_secs(1)).is_none());
+    }
+}
@@ -7,6 +7,8 @@ use serde::{Deserialize, Deserializer, Serialize};
 use std::{collections::HashMap, error::Error, fmt, fs::read_to_string, str::FromStr};
 use validator::Validate;
 
+use crate::ip_event::IpEventType;
+
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all(deserialize = "camelCase"))]
 #[serde(tag = "notifierType", content = "properties")]
@@ -15,6 +17,10 @@ pub enum Notifier {
     File {
         overwrite: bool,
         file_path: String,
+        #[serde(default = "get_default_file_format")]
+        format: FileFormat,
+        #[serde(default, deserialize_with = "deserialize_file_mode_from_string")]
+        permissions: Option<u32>,
     },
     #[serde(rename_all(deserialize = "camelCase"))]
     S3 {
@@ -31,9 +37,260 @@ pub enum Notifier {
         url: String,
         #[serde(with = "http_serde::method")]
         method: Method,
+        #[serde(default)]
         body: HashMap<String, String>,
         #[serde(with = "http_serde::header_map")]
         headers: HeaderMap,
+        #[serde(default)]
+        tls: Option<TlsConfig>,
+        #[serde(default)]
+        cloud_events: Option<CloudEventsConfig>,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Template {
+        template_path: String,
+        destination_path: String,
+        #[serde(default, deserialize_with = "deserialize_file_mode_from_string")]
+        permissions: Option<u32>,
+        #[serde(default)]
+        post_render_command: Vec<String>,
+        #[serde(default = "get_default_command_timeout_seconds")]
+        command_timeout_seconds: u64,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Exec {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        env: HashMap<String, String>,
+        #[serde(default)]
+        clear_env: bool,
+        working_directory: Option<String>,
+        #[serde(default = "get_default_command_timeout_seconds")]
+        timeout_seconds: u64,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Cloudflare {
+        api_token: String,
+        zone_name: String,
+        record_names: Vec<String>,
+        #[serde(default)]
+        create_missing: bool,
+        proxied: Option<bool>,
+        ttl: Option<u32>,
+        api_base_url: Option<String>,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Route53 {
+        access_key_id: String,
+        secret_access_key: String,
+        #[serde(deserialize_with = "deserialize_arn_from_string")]
+        assume_role_arn: ARN,
+        #[serde(deserialize_with = "deserialize_region_from_string")]
+        region: Region,
+        hosted_zone_id: String,
+        record_names: Vec<String>,
+        #[serde(default = "get_default_dns_ttl")]
+        ttl: u32,
+        #[serde(default)]
+        wait_for_sync: bool,
+        #[serde(default = "get_default_sync_timeout_seconds")]
+        sync_timeout_seconds: u64,
+        endpoint: Option<String>,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Rfc2136 {
+        server: String,
+        zone: String,
+        record_names: Vec<String>,
+        #[serde(default = "get_default_dns_ttl")]
+        ttl: u32,
+        key_file: String,
+        #[serde(default = "get_default_dns_timeout_seconds")]
+        timeout_seconds: u64,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Dyndns2 {
+        url: String,
+        username: String,
+        password: SecretRef,
+        hostnames: Vec<String>,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    PowerDns {
+        api_url: String,
+        api_key: String,
+        #[serde(default = "get_default_powerdns_server_id")]
+        server_id: String,
+        zone: String,
+        record_names: Vec<String>,
+        #[serde(default = "get_default_dns_ttl")]
+        ttl: u32,
+        comment: Option<String>,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    DigitalOcean {
+        token: SecretRef,
+        domain: String,
+        record_names: Vec<String>,
+        #[serde(default)]
+        create_missing: bool,
+        #[serde(default)]
+        remove_others: bool,
+        ttl: Option<u32>,
+        api_base_url: Option<String>,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    GoogleCloudDns {
+        key_file: String,
+        project: Option<String>,
+        managed_zone: String,
+        record_names: Vec<String>,
+        #[serde(default = "get_default_dns_ttl")]
+        ttl: u32,
+        token_url: Option<String>,
+        api_base_url: Option<String>,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    ZoneFile {
+        zone_file_path: String,
+        zone: String,
+        record_names: Vec<String>,
+        #[serde(default = "get_default_serial_format")]
+        serial_format: SerialFormat,
+        #[serde(default)]
+        rndc_reload: bool,
+        #[serde(default = "get_default_command_timeout_seconds")]
+        command_timeout_seconds: u64,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Slack {
+        webhook_url: Option<String>,
+        bot_token: Option<SecretRef>,
+        channel: Option<String>,
+        api_base_url: Option<String>,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Discord {
+        webhook_url: String,
+        #[serde(default)]
+        mention_users: Vec<String>,
+        #[serde(default)]
+        mention_roles: Vec<String>,
+        #[serde(default = "get_default_max_retries")]
+        max_retries: u32,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Teams {
+        webhook_url: String,
+        #[serde(default)]
+        templates: HashMap<IpEventType, String>,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Telegram {
+        bot_token: SecretRef,
+        #[serde(deserialize_with = "deserialize_chat_ids")]
+        chat_ids: Vec<String>,
+        #[serde(default = "get_default_telegram_parse_mode")]
+        parse_mode: TelegramParseMode,
+        api_base_url: Option<String>,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Matrix {
+        homeserver_url: String,
+        access_token: SecretRef,
+        room_id: String,
+        #[serde(default = "get_default_max_retries")]
+        max_retries: u32,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Ntfy {
+        topic_url: String,
+        #[serde(default)]
+        tags: Vec<String>,
+        title: Option<String>,
+        token: Option<SecretRef>,
+        username: Option<String>,
+        password: Option<SecretRef>,
+        #[serde(default, deserialize_with = "deserialize_ntfy_priorities")]
+        priorities: HashMap<IpEventType, u8>,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Gotify {
+        server_url: String,
+        app_token: SecretRef,
+        #[serde(default, deserialize_with = "deserialize_gotify_priorities")]
+        priorities: HashMap<IpEventType, u8>,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Pushover {
+        app_token: SecretRef,
+        user_key: SecretRef,
+        #[serde(default)]
+        devices: Vec<String>,
+        emergency_after_failures: Option<u32>,
+        #[serde(default = "get_default_pushover_retry_seconds")]
+        retry_seconds: u32,
+        #[serde(default = "get_default_pushover_expire_seconds")]
+        expire_seconds: u32,
+        status_url: Option<String>,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Email {
+        smtp_host: String,
+        smtp_port: Option<u16>,
+        #[serde(default = "get_default_smtp_tls")]
+        tls: SmtpTls,
+        username: Option<SecretRef>,
+        password: Option<SecretRef>,
+        auth_mechanism: Option<SmtpAuthMechanism>,
+        from: String,
+        to: Vec<String>,
+        subject: Option<String>,
+        text_template_path: Option<String>,
+        html_template_path: Option<String>,
+        #[serde(default = "get_default_command_timeout_seconds")]
+        timeout_seconds: u64,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Mqtt {
+        host: String,
+        #[serde(default = "get_default_mqtt_port")]
+        port: u16,
+        topic: String,
+        #[serde(default = "get_default_mqtt_qos", deserialize_with = "deserialize_mqtt_qos")]
+        qos: u8,
+        client_id: Option<String>,
+        username: Option<String>,
+        password: Option<SecretRef>,
+        #[serde(default)]
+        tls: bool,
+        ca_certificate: Option<String>,
+        #[serde(default)]
+        home_assistant_discovery: bool,
+        #[serde(default = "get_default_discovery_prefix")]
+        discovery_prefix: String,
+        availability_topic: Option<String>,
+    },
+    #[serde(rename_all(deserialize = "camelCase"))]
+    Redis {
+        host: String,
+        #[serde(default = "get_default_redis_port")]
+        port: u16,
+        key: String,
+        ttl_seconds: Option<u64>,
+        #[serde(default = "get_default_redis_value_format")]
+        value_format: RedisValueFormat,
+        channel: Option<String>,
+        username: Option<String>,
+        password: Option<SecretRef>,
+        #[serde(default)]
+        tls: bool,
+        #[serde(default)]
+        database: i64,
+        #[serde(default = "get_default_dns_timeout_seconds")]
+        timeout_seconds: u64,
     },
     Stdout,
 }
@@ -44,6 +301,110 @@ impl fmt::Display for Notifier {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FileFormat {
+    Plain,
+    JsonLines,
+    Csv,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SerialFormat {
+    Date,
+    Counter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TelegramParseMode {
+    MarkdownV2,
+    Html,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SmtpTls {
+    Implicit,
+    StartTls,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SmtpAuthMechanism {
+    Plain,
+    Login,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RedisValueFormat {
+    Ip,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct TlsConfig {
+    pub client_certificate: Option<String>,
+    pub client_key: Option<String>,
+    pub client_pkcs12: Option<String>,
+    pub client_pkcs12_password: Option<String>,
+    #[serde(default)]
+    pub ca_certificates: Vec<String>,
+    #[serde(default)]
+    pub spki_pins: Vec<String>,
+    pub min_tls_version: Option<TlsVersion>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum TlsVersion {
+    #[serde(rename = "1.0")]
+    Tls10,
+    #[serde(rename = "1.1")]
+    Tls11,
+    #[serde(rename = "1.2")]
+    Tls12,
+    #[serde(rename = "1.3")]
+    Tls13,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct CloudEventsConfig {
+    #[serde(default = "get_default_cloud_events_mode")]
+    pub mode: CloudEventsMode,
+    pub source: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CloudEventsMode {
+    Structured,
+    Binary,
+}
+
+// Either the secret itself or where to read it from, e.g. `token: { env: DO_TOKEN }`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum SecretRef {
+    Value(String),
+    Env { env: String },
+    File { file: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all(deserialize = "camelCase"))]
+pub struct DnsDriftConfig {
+    pub hostnames: Vec<String>,
+    #[serde(default)]
+    pub resolvers: Vec<String>,
+    #[serde(default = "get_default_dns_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, Validate)]
 #[serde(rename_all(deserialize = "camelCase"))]
 pub struct LoggingConfig {
@@ -71,6 +432,10 @@ pub struct ConfigFile {
     pub notifiers: Vec<Notifier>,
     #[serde(default = "get_default_logging_config")]
     pub logging_config: LoggingConfig,
+    #[serde(default)]
+    pub dns_drift: Option<DnsDriftConfig>,
+    #[serde(default = "get_default_state_directory")]
+    pub state_directory: String,
 }
 
 pub fn get_default_services() -> Vec<String> {
@@ -80,6 +445,78 @@ pub fn get_default_services() -> Vec<String> {
     ]
 }
 
+pub fn get_default_file_format() -> FileFormat {
+    FileFormat::Plain
+}
+
+pub fn get_default_serial_format() -> SerialFormat {
+    SerialFormat::Date
+}
+
+pub fn get_default_command_timeout_seconds() -> u64 {
+    30
+}
+
+pub fn get_default_dns_ttl() -> u32 {
+    300
+}
+
+pub fn get_default_sync_timeout_seconds() -> u64 {
+    300
+}
+
+pub fn get_default_dns_timeout_seconds() -> u64 {
+    5
+}
+
+pub fn get_default_telegram_parse_mode() -> TelegramParseMode {
+    TelegramParseMode::Html
+}
+
+pub fn get_default_pushover_retry_seconds() -> u32 {
+    60
+}
+
+pub fn get_default_pushover_expire_seconds() -> u32 {
+    3600
+}
+
+pub fn get_default_smtp_tls() -> SmtpTls {
+    SmtpTls::StartTls
+}
+
+pub fn get_default_mqtt_port() -> u16 {
+    1883
+}
+
+pub fn get_default_mqtt_qos() -> u8 {
+    1
+}
+
+pub fn get_default_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+
+pub fn get_default_redis_port() -> u16 {
+    6379
+}
+
+pub fn get_default_redis_value_format() -> RedisValueFormat {
+    RedisValueFormat::Ip
+}
+
+pub fn get_default_max_retries() -> u32 {
+    3
+}
+
+pub fn get_default_powerdns_server_id() -> String {
+    "localhost".to_string()
+}
+
+pub fn get_default_cloud_events_mode() -> CloudEventsMode {
+    CloudEventsMode::Structured
+}
+
 pub fn get_default_logging_config() -> LoggingConfig {
     let log_path = "/tmp/ip_notifier.log";
 
@@ -90,6 +527,10 @@ pub fn get_default_logging_config() -> LoggingConfig {
     }
 }
 
+pub fn get_default_state_directory() -> String {
+    "/var/lib/findip".to_string()
+}
+
 pub fn get_default_log_path() -> String {
     get_default_logging_config().log_file
 }
@@ -117,6 +558,90 @@ where
     ARN::from_str(&input).map_err(serde::de::Error::custom)
 }
 
+fn deserialize_file_mode_from_string<'de, D>(deserializer: D) -> Result<Option<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let input = String::deserialize(deserializer)?;
+    u32::from_str_radix(&input, 8)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+// Chat IDs are numbers, but channels can also be given by their `@name`.
+fn deserialize_chat_ids<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ChatId {
+        Id(i64),
+        Name(String),
+    }
+
+    let input = Vec::<ChatId>::deserialize(deserializer)?;
+    Ok(input
+        .into_iter()
+        .map(|chat_id| match chat_id {
+            ChatId::Id(id) => id.to_string(),
+            ChatId::Name(name) => name,
+        })
+        .collect())
+}
+
+fn deserialize_mqtt_qos<'de, D>(deserializer: D) -> Result<u8, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let qos = u8::deserialize(deserializer)?;
+    if qos > 2 {
+        return Err(serde::de::Error::custom(format!(
+            "QoS must be 0, 1 or 2, not {}",
+            qos
+        )));
+    }
+    Ok(qos)
+}
+
+fn deserialize_ntfy_priorities<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<IpEventType, u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_priorities(deserializer, 1, 5)
+}
+
+fn deserialize_gotify_priorities<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<IpEventType, u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    deserialize_priorities(deserializer, 0, 10)
+}
+
+fn deserialize_priorities<'de, D>(
+    deserializer: D,
+    min: u8,
+    max: u8,
+) -> Result<HashMap<IpEventType, u8>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let priorities = HashMap::<IpEventType, u8>::deserialize(deserializer)?;
+    for (event_type, priority) in priorities.iter() {
+        if !(min..=max).contains(priority) {
+            return Err(serde::de::Error::custom(format!(
+                "the priority of {} must be between {} and {}, not {}",
+                event_type, min, max, priority
+            )));
+        }
+    }
+    Ok(priorities)
+}
+
 fn deserialize_region_from_string<'de, D>(deserializer: D) -> Result<Region, D::Error>
 where
     D: Deserializer<'de>,
@@ -203,6 +728,8 @@ mod tests {
             method,
             body,
             headers,
+            tls,
+            cloud_events,
         } = &config_file.notifiers[0]
         {
             assert_eq!(url, "https://something.com/some/api");
@@ -216,6 +743,8 @@ mod tests {
                 *headers.get("Content-Type").unwrap(),
                 "application/json".to_owned()
             );
+            assert_eq!(*tls, None);
+            assert_eq!(*cloud_events, None);
             Ok(())
         } else {
             Err(Box::new(UnexpectedNotifierError {
@@ -224,6 +753,8 @@ mod tests {
                     method: Method::GET,
                     body: HashMap::new(),
                     headers: HeaderMap::new(),
+                    tls: None,
+                    cloud_events: None,
                 },
             }))
         }
@@ -238,6 +769,8 @@ mod tests {
             method: _,
             body,
             headers: _,
+            tls: _,
+            cloud_events: _,
         } = &config_file.notifiers[0]
         {
             assert_eq!(
@@ -252,6 +785,74 @@ mod tests {
                     method: Method::GET,
                     body: HashMap::new(),
                     headers: HeaderMap::new(),
+                    tls: None,
+                    cloud_events: None,
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_rest_notifier_tls_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/rest_tls.yml".to_string())?;
+
+        if let Notifier::RestApi { tls: Some(tls), .. } = &config_file.notifiers[0] {
+            assert_eq!(
+                tls.client_certificate,
+                Some("testfiles/tls/client.pem".to_owned())
+            );
+            assert_eq!(tls.client_key, Some("testfiles/tls/client.key".to_owned()));
+            assert_eq!(tls.client_pkcs12, None);
+            assert_eq!(
+                tls.ca_certificates,
+                vec!["testfiles/tls/client.pem".to_owned()]
+            );
+            assert_eq!(
+                tls.spki_pins,
+                vec!["sha256/ZTFyAK+VQCmYIpf6EYe+t/R868KQ/f9hQnC+J8KphTE=".to_owned()]
+            );
+            assert_eq!(tls.min_tls_version, Some(TlsVersion::Tls12));
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::RestApi {
+                    url: "".to_owned(),
+                    method: Method::PUT,
+                    body: HashMap::new(),
+                    headers: HeaderMap::new(),
+                    tls: None,
+                    cloud_events: None,
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_rest_notifier_cloud_events_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/rest_cloud_events.yml".to_string())?;
+
+        if let Notifier::RestApi {
+            body,
+            cloud_events: Some(cloud_events),
+            ..
+        } = &config_file.notifiers[0]
+        {
+            assert!(body.is_empty());
+            assert_eq!(cloud_events.mode, CloudEventsMode::Binary);
+            assert_eq!(cloud_events.source, Some("urn:findip:edge-1".to_owned()));
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::RestApi {
+                    url: "".to_owned(),
+                    method: Method::POST,
+                    body: HashMap::new(),
+                    headers: HeaderMap::new(),
+                    tls: None,
+                    cloud_events: Some(CloudEventsConfig {
+                        mode: CloudEventsMode::Binary,
+                        source: None,
+                    }),
                 },
             }))
         }
@@ -264,21 +865,981 @@ mod tests {
         if let Notifier::File {
             overwrite,
             file_path,
+            format,
+            permissions,
         } = &config_file.notifiers[0]
         {
             assert_eq!(*overwrite, false);
             assert_eq!(*file_path, "testfile.log".to_owned());
+            assert_eq!(*format, FileFormat::Plain);
+            assert_eq!(*permissions, None);
             Ok(())
         } else {
             Err(Box::new(UnexpectedNotifierError {
                 expected: Notifier::File {
                     overwrite: false,
                     file_path: "".to_owned(),
+                    format: FileFormat::Plain,
+                    permissions: None,
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_file_notifier_with_format_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/textfile_csv.yml".to_string())?;
+
+        if let Notifier::File {
+            overwrite,
+            format,
+            permissions,
+            ..
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(*overwrite, true);
+            assert_eq!(*format, FileFormat::Csv);
+            assert_eq!(*permissions, Some(0o640));
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::File {
+                    overwrite: true,
+                    file_path: "".to_owned(),
+                    format: FileFormat::Csv,
+                    permissions: Some(0o640),
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_template_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/template.yml".to_string())?;
+
+        if let Notifier::Template {
+            template_path,
+            destination_path,
+            permissions,
+            post_render_command,
+            command_timeout_seconds,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(template_path, "/etc/findip/allow.conf.tmpl");
+            assert_eq!(destination_path, "/etc/nginx/conf.d/allow.conf");
+            assert_eq!(*permissions, Some(0o644));
+            assert_eq!(
+                *post_render_command,
+                vec!["nginx".to_owned(), "-s".to_owned(), "reload".to_owned()]
+            );
+            assert_eq!(*command_timeout_seconds, 10);
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Template {
+                    template_path: "".to_owned(),
+                    destination_path: "".to_owned(),
+                    permissions: None,
+                    post_render_command: vec![],
+                    command_timeout_seconds: get_default_command_timeout_seconds(),
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_exec_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/exec.yml".to_string())?;
+
+        if let Notifier::Exec {
+            command,
+            args,
+            env,
+            clear_env,
+            working_directory,
+            timeout_seconds,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(command, "/usr/local/bin/on-ip-change");
+            assert_eq!(*args, vec!["--notify".to_owned()]);
+            assert_eq!(*env.get("PATH").unwrap(), "/usr/bin:/bin".to_owned());
+            assert_eq!(*clear_env, true);
+            assert_eq!(*working_directory, Some("/var/lib/findip".to_owned()));
+            assert_eq!(*timeout_seconds, get_default_command_timeout_seconds());
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Exec {
+                    command: "".to_owned(),
+                    args: vec![],
+                    env: HashMap::new(),
+                    clear_env: false,
+                    working_directory: None,
+                    timeout_seconds: get_default_command_timeout_seconds(),
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_cloudflare_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/cloudflare.yml".to_string())?;
+
+        if let Notifier::Cloudflare {
+            api_token,
+            zone_name,
+            record_names,
+            create_missing,
+            proxied,
+            ttl,
+            api_base_url,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(api_token, "mysecrettoken");
+            assert_eq!(zone_name, "example.com");
+            assert_eq!(
+                *record_names,
+                vec!["example.com".to_owned(), "home.example.com".to_owned()]
+            );
+            assert_eq!(*create_missing, true);
+            assert_eq!(*proxied, Some(false));
+            assert_eq!(*ttl, Some(300));
+            assert_eq!(*api_base_url, None);
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Cloudflare {
+                    api_token: "".to_owned(),
+                    zone_name: "".to_owned(),
+                    record_names: vec![],
+                    create_missing: false,
+                    proxied: None,
+                    ttl: None,
+                    api_base_url: None,
                 },
             }))
         }
     }
 
+    #[test]
+    fn test_route53_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/route53.yml".to_string())?;
+
+        if let Notifier::Route53 {
+            assume_role_arn,
+            region,
+            hosted_zone_id,
+            record_names,
+            ttl,
+            wait_for_sync,
+            sync_timeout_seconds,
+            endpoint,
+            ..
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(
+                assume_role_arn.to_string(),
+                "arn:aws:iam::123456789012:role/namespace/assume-role"
+            );
+            assert_eq!(*region, Region::UsEast1);
+            assert_eq!(hosted_zone_id, "Z1PA6795UKMFR9");
+            assert_eq!(*record_names, vec!["home.example.com".to_owned()]);
+            assert_eq!(*ttl, 60);
+            assert_eq!(*wait_for_sync, true);
+            assert_eq!(*sync_timeout_seconds, get_default_sync_timeout_seconds());
+            assert_eq!(*endpoint, None);
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Route53 {
+                    access_key_id: "".to_owned(),
+                    secret_access_key: "".to_owned(),
+                    assume_role_arn: ARN::from_str(
+                        "arn:aws:iam::123456789012:role/namespace/assume-role",
+                    )
+                    .unwrap(),
+                    region: Region::UsEast1,
+                    hosted_zone_id: "".to_owned(),
+                    record_names: vec![],
+                    ttl: get_default_dns_ttl(),
+                    wait_for_sync: false,
+                    sync_timeout_seconds: get_default_sync_timeout_seconds(),
+                    endpoint: None,
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_rfc2136_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/rfc2136.yml".to_string())?;
+
+        if let Notifier::Rfc2136 {
+            server,
+            zone,
+            record_names,
+            ttl,
+            key_file,
+            timeout_seconds,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(server, "ns1.example.com:53");
+            assert_eq!(zone, "example.com");
+            assert_eq!(*record_names, vec!["home.example.com".to_owned()]);
+            assert_eq!(*ttl, get_default_dns_ttl());
+            assert_eq!(key_file, "/etc/findip/findip-key.conf");
+            assert_eq!(*timeout_seconds, 10);
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Rfc2136 {
+                    server: "".to_owned(),
+                    zone: "".to_owned(),
+                    record_names: vec![],
+                    ttl: get_default_dns_ttl(),
+                    key_file: "".to_owned(),
+                    timeout_seconds: get_default_dns_timeout_seconds(),
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_dyndns2_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/dyndns2.yml".to_string())?;
+
+        if let Notifier::Dyndns2 {
+            url,
+            username,
+            password,
+            hostnames,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(url, "https://dynupdate.no-ip.com/nic/update");
+            assert_eq!(username, "user");
+            assert_eq!(
+                *password,
+                SecretRef::Env {
+                    env: "DYNDNS_PASSWORD".to_owned()
+                }
+            );
+            assert_eq!(
+                *hostnames,
+                vec!["home.example.com".to_owned(), "vpn.example.com".to_owned()]
+            );
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Dyndns2 {
+                    url: "".to_owned(),
+                    username: "".to_owned(),
+                    password: SecretRef::Value("".to_owned()),
+                    hostnames: vec![],
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_powerdns_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/powerdns.yml".to_string())?;
+
+        if let Notifier::PowerDns {
+            api_url,
+            api_key,
+            server_id,
+            zone,
+            record_names,
+            ttl,
+            comment,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(api_url, "http://ns1.example.com:8081");
+            assert_eq!(api_key, "mysecretkey");
+            assert_eq!(*server_id, get_default_powerdns_server_id());
+            assert_eq!(zone, "example.com");
+            assert_eq!(*record_names, vec!["home.example.com".to_owned()]);
+            assert_eq!(*ttl, 60);
+            assert_eq!(
+                *comment,
+                Some("Updated by findip to {{TOKEN_IP_ADDRESS}}".to_owned())
+            );
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::PowerDns {
+                    api_url: "".to_owned(),
+                    api_key: "".to_owned(),
+                    server_id: get_default_powerdns_server_id(),
+                    zone: "".to_owned(),
+                    record_names: vec![],
+                    ttl: get_default_dns_ttl(),
+                    comment: None,
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_digitalocean_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/digitalocean.yml".to_string())?;
+
+        if let Notifier::DigitalOcean {
+            token,
+            domain,
+            record_names,
+            create_missing,
+            remove_others,
+            ttl,
+            api_base_url,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(
+                *token,
+                SecretRef::Env {
+                    env: "DIGITALOCEAN_TOKEN".to_owned()
+                }
+            );
+            assert_eq!(domain, "example.com");
+            assert_eq!(*record_names, vec!["@".to_owned(), "home".to_owned()]);
+            assert_eq!(*create_missing, true);
+            assert_eq!(*remove_others, false);
+            assert_eq!(*ttl, None);
+            assert_eq!(*api_base_url, None);
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::DigitalOcean {
+                    token: SecretRef::Value("".to_owned()),
+                    domain: "".to_owned(),
+                    record_names: vec![],
+                    create_missing: false,
+                    remove_others: false,
+                    ttl: None,
+                    api_base_url: None,
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_google_cloud_dns_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/google_cloud_dns.yml".to_string())?;
+
+        if let Notifier::GoogleCloudDns {
+            key_file,
+            project,
+            managed_zone,
+            record_names,
+            ttl,
+            token_url,
+            api_base_url,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(key_file, "/etc/findip/service-account.json");
+            assert_eq!(*project, None);
+            assert_eq!(managed_zone, "example-com");
+            assert_eq!(*record_names, vec!["home.example.com".to_owned()]);
+            assert_eq!(*ttl, 60);
+            assert_eq!(*token_url, None);
+            assert_eq!(*api_base_url, None);
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::GoogleCloudDns {
+                    key_file: "".to_owned(),
+                    project: None,
+                    managed_zone: "".to_owned(),
+                    record_names: vec![],
+                    ttl: get_default_dns_ttl(),
+                    token_url: None,
+                    api_base_url: None,
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_zone_file_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/zone_file.yml".to_string())?;
+
+        if let Notifier::ZoneFile {
+            zone_file_path,
+            zone,
+            record_names,
+            serial_format,
+            rndc_reload,
+            command_timeout_seconds,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(zone_file_path, "/etc/bind/zones/db.example.com");
+            assert_eq!(zone, "example.com");
+            assert_eq!(*record_names, vec!["home.example.com".to_owned()]);
+            assert_eq!(*serial_format, SerialFormat::Counter);
+            assert_eq!(*rndc_reload, true);
+            assert_eq!(
+                *command_timeout_seconds,
+                get_default_command_timeout_seconds()
+            );
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::ZoneFile {
+                    zone_file_path: "".to_owned(),
+                    zone: "".to_owned(),
+                    record_names: vec![],
+                    serial_format: get_default_serial_format(),
+                    rndc_reload: false,
+                    command_timeout_seconds: get_default_command_timeout_seconds(),
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_dns_drift_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/dns_drift.yml".to_string())?;
+
+        assert_eq!(
+            config_file.dns_drift,
+            Some(DnsDriftConfig {
+                hostnames: vec!["home.example.com".to_owned(), "vpn.example.com".to_owned()],
+                resolvers: vec!["1.1.1.1".to_owned(), "9.9.9.9:53".to_owned()],
+                timeout_seconds: get_default_dns_timeout_seconds(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_dns_drift_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/stdout.yml".to_string())?;
+
+        assert_eq!(config_file.dns_drift, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_directory_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/dyndns2.yml".to_string())?;
+
+        assert_eq!(config_file.state_directory, "/var/lib/findip-home");
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_state_directory_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/stdout.yml".to_string())?;
+
+        assert_eq!(config_file.state_directory, "/var/lib/findip");
+        Ok(())
+    }
+
+    #[test]
+    fn test_slack_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/slack.yml".to_string())?;
+
+        if let Notifier::Slack {
+            webhook_url: None,
+            bot_token: Some(bot_token),
+            channel: Some(channel),
+            api_base_url: None,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(
+                *bot_token,
+                SecretRef::Env {
+                    env: "SLACK_BOT_TOKEN".to_owned()
+                }
+            );
+            assert_eq!(channel, "#ops");
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Slack {
+                    webhook_url: None,
+                    bot_token: None,
+                    channel: None,
+                    api_base_url: None,
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_discord_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/discord.yml".to_string())?;
+
+        if let Notifier::Discord {
+            webhook_url,
+            mention_users,
+            mention_roles,
+            max_retries,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(
+                webhook_url,
+                "https://discord.com/api/webhooks/123456/abcdef"
+            );
+            assert!(mention_users.is_empty());
+            assert_eq!(*mention_roles, vec!["41771983423143936".to_owned()]);
+            assert_eq!(*max_retries, get_default_max_retries());
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Discord {
+                    webhook_url: "".to_owned(),
+                    mention_users: vec![],
+                    mention_roles: vec![],
+                    max_retries: get_default_max_retries(),
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_teams_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/teams.yml".to_string())?;
+
+        if let Notifier::Teams {
+            webhook_url,
+            templates,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(
+                webhook_url,
+                "https://prod-00.westus.logic.azure.com/workflows/abc/triggers/manual/paths/invoke"
+            );
+            assert_eq!(templates.len(), 2);
+            assert_eq!(
+                templates[&IpEventType::IpConflict],
+                "/etc/findip/teams/conflict.json"
+            );
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Teams {
+                    webhook_url: "".to_owned(),
+                    templates: HashMap::new(),
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_telegram_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/telegram.yml".to_string())?;
+
+        if let Notifier::Telegram {
+            bot_token,
+            chat_ids,
+            parse_mode,
+            api_base_url,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(
+                *bot_token,
+                SecretRef::File {
+                    file: "/run/secrets/telegram".to_owned()
+                }
+            );
+            assert_eq!(
+                *chat_ids,
+                vec!["-1001234567890".to_owned(), "@findip_alerts".to_owned()]
+            );
+            assert_eq!(*parse_mode, TelegramParseMode::MarkdownV2);
+            assert_eq!(*api_base_url, None);
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Telegram {
+                    bot_token: SecretRef::Value("".to_owned()),
+                    chat_ids: vec![],
+                    parse_mode: get_default_telegram_parse_mode(),
+                    api_base_url: None,
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_matrix_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/matrix.yml".to_string())?;
+
+        if let Notifier::Matrix {
+            homeserver_url,
+            access_token,
+            room_id,
+            max_retries,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(homeserver_url, "https://matrix.example.org");
+            assert_eq!(
+                *access_token,
+                SecretRef::Env {
+                    env: "MATRIX_ACCESS_TOKEN".to_owned()
+                }
+            );
+            assert_eq!(room_id, "!ops:example.org");
+            assert_eq!(*max_retries, 5);
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Matrix {
+                    homeserver_url: "".to_owned(),
+                    access_token: SecretRef::Value("".to_owned()),
+                    room_id: "".to_owned(),
+                    max_retries: get_default_max_retries(),
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_ntfy_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/ntfy.yml".to_string())?;
+
+        if let Notifier::Ntfy {
+            topic_url,
+            tags,
+            title,
+            token,
+            username,
+            password,
+            priorities,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(topic_url, "https://ntfy.sh/findip-alerts");
+            assert_eq!(*tags, vec!["globe_with_meridians".to_owned()]);
+            assert_eq!(
+                *title,
+                Some("{{TOKEN_HOSTNAME}} is now {{TOKEN_IP_ADDRESS}}".to_owned())
+            );
+            assert_eq!(
+                *token,
+                Some(SecretRef::Env {
+                    env: "NTFY_TOKEN".to_owned()
+                })
+            );
+            assert_eq!(*username, None);
+            assert_eq!(*password, None);
+            assert_eq!(priorities[&IpEventType::IpChanged], 4);
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Ntfy {
+                    topic_url: "".to_owned(),
+                    tags: vec![],
+                    title: None,
+                    token: None,
+                    username: None,
+                    password: None,
+                    priorities: HashMap::new(),
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_gotify_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/gotify.yml".to_string())?;
+
+        if let Notifier::Gotify {
+            server_url,
+            app_token,
+            priorities,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(server_url, "https://gotify.example.com");
+            assert_eq!(
+                *app_token,
+                SecretRef::File {
+                    file: "/run/secrets/gotify".to_owned()
+                }
+            );
+            assert!(priorities.is_empty());
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Gotify {
+                    server_url: "".to_owned(),
+                    app_token: SecretRef::Value("".to_owned()),
+                    priorities: HashMap::new(),
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_push_priorities_out_of_range() {
+        let ntfy: Result<Notifier, _> = serde_yaml::from_str(
+            "notifierType: ntfy\nproperties:\n  topicUrl: https://ntfy.sh/findip\n  priorities:\n    ip.changed: 0\n",
+        );
+        let gotify: Result<Notifier, _> = serde_yaml::from_str(
+            "notifierType: gotify\nproperties:\n  serverUrl: https://gotify.example.com\n  appToken: token\n  priorities:\n    ip.changed: 11\n",
+        );
+        let gotify_lowest: Result<Notifier, _> = serde_yaml::from_str(
+            "notifierType: gotify\nproperties:\n  serverUrl: https://gotify.example.com\n  appToken: token\n  priorities:\n    ip.unchanged: 0\n",
+        );
+
+        assert!(ntfy.is_err());
+        assert!(gotify.is_err());
+        assert!(gotify_lowest.is_ok());
+    }
+
+    #[test]
+    fn test_pushover_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/pushover.yml".to_string())?;
+
+        if let Notifier::Pushover {
+            app_token,
+            user_key,
+            devices,
+            emergency_after_failures,
+            retry_seconds,
+            expire_seconds,
+            status_url,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(
+                *app_token,
+                SecretRef::Env {
+                    env: "PUSHOVER_APP_TOKEN".to_owned()
+                }
+            );
+            assert_eq!(
+                *user_key,
+                SecretRef::Value("uQiRzpo4DXghDmr9QzzfQu27cmVRsG".to_owned())
+            );
+            assert_eq!(*devices, vec!["phone".to_owned()]);
+            assert_eq!(*emergency_after_failures, Some(3));
+            assert_eq!(*retry_seconds, get_default_pushover_retry_seconds());
+            assert_eq!(*expire_seconds, 7200);
+            assert_eq!(*status_url, Some("https://status.example.com".to_owned()));
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Pushover {
+                    app_token: SecretRef::Value("".to_owned()),
+                    user_key: SecretRef::Value("".to_owned()),
+                    devices: vec![],
+                    emergency_after_failures: None,
+                    retry_seconds: get_default_pushover_retry_seconds(),
+                    expire_seconds: get_default_pushover_expire_seconds(),
+                    status_url: None,
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_email_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/email.yml".to_string())?;
+
+        if let Notifier::Email {
+            smtp_host,
+            smtp_port,
+            tls,
+            username,
+            password,
+            auth_mechanism,
+            from,
+            to,
+            subject,
+            text_template_path,
+            html_template_path,
+            timeout_seconds,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(smtp_host, "smtp.example.com");
+            assert_eq!(*smtp_port, Some(465));
+            assert_eq!(*tls, SmtpTls::Implicit);
+            assert_eq!(*username, Some(SecretRef::Value("findip".to_owned())));
+            assert_eq!(
+                *password,
+                Some(SecretRef::Env {
+                    env: "SMTP_PASSWORD".to_owned()
+                })
+            );
+            assert_eq!(*auth_mechanism, Some(SmtpAuthMechanism::Login));
+            assert_eq!(from, "findip <findip@example.com>");
+            assert_eq!(
+                *to,
+                vec!["ops@example.com".to_owned(), "helpdesk@example.com".to_owned()]
+            );
+            assert_eq!(
+                *subject,
+                Some("[findip] {{TOKEN_HOSTNAME}} is now {{TOKEN_IP_ADDRESS}}".to_owned())
+            );
+            assert_eq!(*text_template_path, None);
+            assert_eq!(
+                *html_template_path,
+                Some("/etc/findip/email.html".to_owned())
+            );
+            assert_eq!(*timeout_seconds, get_default_command_timeout_seconds());
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Email {
+                    smtp_host: "".to_owned(),
+                    smtp_port: None,
+                    tls: get_default_smtp_tls(),
+                    username: None,
+                    password: None,
+                    auth_mechanism: None,
+                    from: "".to_owned(),
+                    to: vec![],
+                    subject: None,
+                    text_template_path: None,
+                    html_template_path: None,
+                    timeout_seconds: get_default_command_timeout_seconds(),
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_mqtt_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/mqtt.yml".to_string())?;
+
+        if let Notifier::Mqtt {
+            host,
+            port,
+            topic,
+            qos,
+            client_id,
+            username,
+            password,
+            tls,
+            ca_certificate,
+            home_assistant_discovery,
+            discovery_prefix,
+            availability_topic,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(host, "mqtt.example.com");
+            assert_eq!(*port, 8883);
+            assert_eq!(topic, "findip/ip");
+            assert_eq!(*qos, 2);
+            assert_eq!(*client_id, None);
+            assert_eq!(*username, Some("findip".to_owned()));
+            assert_eq!(
+                *password,
+                Some(SecretRef::Env {
+                    env: "MQTT_PASSWORD".to_owned()
+                })
+            );
+            assert!(*tls);
+            assert_eq!(*ca_certificate, Some("/etc/findip/mqtt-ca.pem".to_owned()));
+            assert!(*home_assistant_discovery);
+            assert_eq!(*discovery_prefix, get_default_discovery_prefix());
+            assert_eq!(*availability_topic, Some("findip/availability".to_owned()));
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Mqtt {
+                    host: "".to_owned(),
+                    port: get_default_mqtt_port(),
+                    topic: "".to_owned(),
+                    qos: get_default_mqtt_qos(),
+                    client_id: None,
+                    username: None,
+                    password: None,
+                    tls: false,
+                    ca_certificate: None,
+                    home_assistant_discovery: false,
+                    discovery_prefix: get_default_discovery_prefix(),
+                    availability_topic: None,
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_mqtt_qos_out_of_range() {
+        let notifier: Result<Notifier, _> = serde_yaml::from_str(
+            "notifierType: mqtt\nproperties:\n  host: localhost\n  topic: findip/ip\n  qos: 3\n",
+        );
+
+        assert!(notifier.is_err());
+    }
+
+    #[test]
+    fn test_redis_notifier_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let config_file = load_config_from_file("testfiles/redis.yml".to_string())?;
+
+        if let Notifier::Redis {
+            host,
+            port,
+            key,
+            ttl_seconds,
+            value_format,
+            channel,
+            username,
+            password,
+            tls,
+            database,
+            timeout_seconds,
+        } = &config_file.notifiers[0]
+        {
+            assert_eq!(host, "redis.internal");
+            assert_eq!(*port, get_default_redis_port());
+            assert_eq!(key, "egress:ip");
+            assert_eq!(*ttl_seconds, Some(900));
+            assert_eq!(*value_format, RedisValueFormat::Json);
+            assert_eq!(*channel, Some("egress:events".to_owned()));
+            assert_eq!(*username, Some("findip".to_owned()));
+            assert_eq!(
+                *password,
+                Some(SecretRef::Env {
+                    env: "REDIS_PASSWORD".to_owned()
+                })
+            );
+            assert!(*tls);
+            assert_eq!(*database, 3);
+            assert_eq!(*timeout_seconds, get_default_dns_timeout_seconds());
+            Ok(())
+        } else {
+            Err(Box::new(UnexpectedNotifierError {
+                expected: Notifier::Redis {
+                    host: "".to_owned(),
+                    port: get_default_redis_port(),
+                    key: "".to_owned(),
+                    ttl_seconds: None,
+                    value_format: get_default_redis_value_format(),
+                    channel: None,
+                    username: None,
+                    password: None,
+                    tls: false,
+                    database: 0,
+                    timeout_seconds: get_default_dns_timeout_seconds(),
+                },
+            }))
+        }
+    }
+
+    #[test]
+    fn test_secret_ref_deserialization() -> Result<(), Box<dyn Error + 'static>> {
+        let value: SecretRef = serde_yaml::from_str("shhh")?;
+        let env: SecretRef = serde_yaml::from_str("env: MY_TOKEN")?;
+        let file: SecretRef = serde_yaml::from_str("file: /run/secrets/token")?;
+
+        assert_eq!(value, SecretRef::Value("shhh".to_owned()));
+        assert_eq!(
+            env,
+            SecretRef::Env {
+                env: "MY_TOKEN".to_owned()
+            }
+        );
+        assert_eq!(
+            file,
+            SecretRef::File {
+                file: "/run/secrets/token".to_owned()
+            }
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_missing_services_deserialization() -> Result<(), Box<dyn Error + 'static>> {
         let config_file = load_config_from_file("testfiles/textfile.yml".to_string())?;
@@ -0,0 +1,251 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::{ErrorReason, IpError};
+
+pub const DNS_PORT: u16 = 53;
+
+const CLASS_IN: u16 = 1;
+const HEADER_LENGTH: usize = 12;
+const MAX_MESSAGE_SIZE: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum RecordType {
+    A,
+    #[serde(rename = "AAAA")]
+    Aaaa,
+}
+
+impl RecordType {
+    pub fn for_ip(ip: IpAddr) -> RecordType {
+        match ip {
+            IpAddr::V4(_) => RecordType::A,
+            IpAddr::V6(_) => RecordType::Aaaa,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecordType::A => "A",
+            RecordType::Aaaa => "AAAA",
+        }
+    }
+
+    pub fn code(&self) -> u16 {
+        match self {
+            RecordType::A => 1,
+            RecordType::Aaaa => 28,
+        }
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DnsRecord {
+    // Whatever the provider needs to find this exact record again, like its id. Records that
+    // don't exist yet have none.
+    pub id: Option<String>,
+    pub name: String,
+    pub record_type: RecordType,
+    pub value: String,
+    pub ttl: Option<u32>,
+}
+
+// The operations a DNS host has to offer for `notifier::DnsNotifier` to keep records pointed at
+// the current IP. Names are passed on the way they are configured. The Route 53, Cloud DNS and
+// PowerDNS notifiers don't go through it since those APIs replace whole record sets, and neither
+// does Cloudflare's, whose records carry a proxying setting `DnsRecord` has no room for.
+#[async_trait]
+pub trait DnsProvider: Send + Sync {
+    async fn get_records(
+        &self,
+        name: &str,
+        record_type: RecordType,
+    ) -> Result<Vec<DnsRecord>, IpError>;
+    // Updates the record when it has an id and creates it otherwise.
+    async fn upsert_record(&self, record: &DnsRecord) -> Result<(), IpError>;
+    async fn delete_record(&self, record: &DnsRecord) -> Result<(), IpError>;
+}
+
+pub fn fqdn(name: &str) -> String {
+    format!("{}.", name.trim_end_matches('.'))
+}
+
+// Wire format, uncompressed, as used by RFC 2136 updates and TSIG.
+pub fn encode_name(name: &str) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(name.len() + 2);
+    for label in name
+        .trim_end_matches('.')
+        .split('.')
+        .filter(|l| !l.is_empty())
+    {
+        encoded.push(label.len() as u8);
+        encoded.extend_from_slice(label.as_bytes());
+    }
+    encoded.push(0);
+    encoded
+}
+
+pub(crate) fn skip_name(message: &[u8], mut position: usize) -> Option<usize> {
+    loop {
+        let length = *message.get(position)?;
+        match length {
+            0 => return Some(position + 1),
+            // A compression pointer always ends the name.
+            l if l & 0xC0 == 0xC0 => return Some(position + 2),
+            l => position += 1 + l as usize,
+        }
+    }
+}
+
+// Accepts `host` or `host:port`, with port 53 as the default.
+pub fn server_address(server: &str) -> Result<SocketAddr, IpError> {
+    let addresses = match server.to_socket_addrs() {
+        Ok(addresses) => addresses,
+        Err(_) => (server, DNS_PORT).to_socket_addrs().map_err(|e| {
+            IpError::new(ErrorReason::DnsLookupFailed(format!(
+                "could not resolve {}: {}",
+                server, e
+            )))
+        })?,
+    };
+
+    addresses.into_iter().next().ok_or_else(|| {
+        IpError::new(ErrorReason::DnsLookupFailed(format!(
+            "{} did not resolve to an address",
+            server
+        )))
+    })
+}
+
+// Asks `server` for the A or AAAA records of `name` over UDP. A name that doesn't exist has no
+// addresses rather than being an error.
+pub fn resolve(
+    server: SocketAddr,
+    name: &str,
+    record_type: RecordType,
+    timeout: Duration,
+) -> Result<Vec<IpAddr>, IpError> {
+    let lookup_error = |context: String| {
+        IpError::new(ErrorReason::DnsLookupFailed(format!(
+            "{} through {}: {}",
+            name, server, context
+        )))
+    };
+    let random = uuid::Uuid::new_v4();
+    let id = [random.as_bytes()[0], random.as_bytes()[1]];
+
+    let mut query = Vec::with_capacity(512);
+    query.extend_from_slice(&id);
+    // Recursion desired and one question.
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    query.extend(encode_name(name));
+    query.extend_from_slice(&record_type.code().to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+    let bind_address = if server.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let io_error = |e: std::io::Error| lookup_error(e.to_string());
+    let socket = UdpSocket::bind(bind_address).map_err(io_error)?;
+    socket.set_read_timeout(Some(timeout)).map_err(io_error)?;
+    socket.connect(server).map_err(io_error)?;
+    socket.send(&query).map_err(io_error)?;
+
+    let mut buffer = vec![0u8; MAX_MESSAGE_SIZE];
+    let response = loop {
+        let length = socket.recv(&mut buffer).map_err(io_error)?;
+        if length >= HEADER_LENGTH && buffer[..2] == id && buffer[2] & 0x80 != 0 {
+            break &buffer[..length];
+        }
+    };
+
+    match response[3] & 0x0F {
+        0 => {}
+        3 => return Ok(vec![]),
+        response_code => return Err(lookup_error(format!("response code {}", response_code))),
+    }
+
+    parse_answers(response, record_type)
+        .ok_or_else(|| lookup_error("the response could not be parsed".to_string()))
+}
+
+fn parse_answers(response: &[u8], record_type: RecordType) -> Option<Vec<IpAddr>> {
+    let count = |index: usize| u16::from_be_bytes([response[index], response[index + 1]]);
+    let mut position = HEADER_LENGTH;
+    for _ in 0..count(4) {
+        position = skip_name(response, position)? + 4;
+    }
+
+    // CNAMEs come before the records they point at, only the addresses are of interest.
+    let mut addresses = Vec::new();
+    for _ in 0..count(6) {
+        position = skip_name(response, position)?;
+        let header = response.get(position..position + 10)?;
+        let answer_type = u16::from_be_bytes([header[0], header[1]]);
+        let length = u16::from_be_bytes([header[8], header[9]]) as usize;
+        let rdata = response.get(position + 10..position + 10 + length)?;
+        position += 10 + length;
+
+        match (record_type, answer_type == record_type.code(), rdata.len()) {
+            (RecordType::A, true, 4) => {
+                addresses.push(IpAddr::V4(Ipv4Addr::new(
+                    rdata[0], rdata[1], rdata[2], rdata[3],
+                )));
+            }
+            (RecordType::Aaaa, true, 16) => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(rdata);
+                addresses.push(IpAddr::V6(Ipv6Addr::from(octets)));
+            }
+            _ => {}
+        }
+    }
+
+    Some(addresses)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    #[test]
+    fn test_record_type_for_ip() {
+        assert_eq!(
+            RecordType::for_ip(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
+            RecordType::A
+        );
+        assert_eq!(
+            RecordType::for_ip(IpAddr::V6(Ipv6Addr::LOCALHOST)),
+            RecordType::Aaaa
+        );
+    }
+
+    #[test]
+    fn test_encode_name() {
+        assert_eq!(
+            encode_name("home.example.com."),
+            b"\x04home\x07example\x03com\x00".to_vec()
+        );
+        assert_eq!(
+            encode_name("home.example.com"),
+            encode_name("home.example.com.")
+        );
+        assert_eq!(encode_name("."), vec![0]);
+    }
+}
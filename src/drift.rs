@@ -0,0 +1,298 @@
+use std::{
+    net::{IpAddr, ToSocketAddrs},
+    time::Duration,
+};
+
+use log::{debug, error};
+
+use crate::{
+    config_file::DnsDriftConfig,
+    dns::{self, RecordType},
+    errors::{ErrorReason, IpError},
+    ip_event::IpEvent,
+    ip_result::{IpResultStorage, IpResults},
+    notifier::IpNotifier,
+};
+
+// Checks that the hostnames we publish still resolve to the IP address we last found, since
+// records can be changed by hand or miss an update without anyone noticing.
+pub struct DnsDriftMonitor {
+    hostnames: Vec<String>,
+    resolvers: Vec<String>,
+    timeout: Duration,
+}
+
+impl DnsDriftMonitor {
+    pub fn new(hostnames: Vec<String>) -> DnsDriftMonitor {
+        DnsDriftMonitor {
+            hostnames,
+            resolvers: vec![],
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    pub fn from_config(config: &DnsDriftConfig) -> DnsDriftMonitor {
+        DnsDriftMonitor::new(config.hostnames.clone())
+            .with_resolvers(config.resolvers.clone())
+            .with_timeout(Duration::from_secs(config.timeout_seconds))
+    }
+
+    // Without resolvers the system resolver is used.
+    pub fn with_resolvers(mut self, resolvers: Vec<String>) -> DnsDriftMonitor {
+        self.resolvers = resolvers;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> DnsDriftMonitor {
+        self.timeout = timeout;
+        self
+    }
+
+    // Sends a `DnsDrift` event for every hostname and resolver whose answer isn't the latest IP
+    // in `results`, and returns the events that were sent.
+    pub fn check<N>(&self, results: &IpResults, notifier: &N) -> Vec<IpEvent>
+    where
+        N: IpNotifier + ?Sized,
+    {
+        let ip = match results.get_latest_ip() {
+            Ok(ip) => ip,
+            Err(_) => {
+                debug!("No IP address has been found yet, skipping the DNS drift check.");
+                return vec![];
+            }
+        };
+
+        let events: Vec<IpEvent> = self
+            .find_drift(ip)
+            .into_iter()
+            .map(|details| IpEvent::dns_drift(ip, details))
+            .collect();
+        for event in events.iter() {
+            notifier.notify_event(event);
+        }
+        events
+    }
+
+    pub fn find_drift(&self, ip: IpAddr) -> Vec<String> {
+        let record_type = RecordType::for_ip(ip);
+        let mut drift = Vec::new();
+
+        for hostname in self.hostnames.iter() {
+            let answers: Vec<(String, Result<Vec<IpAddr>, IpError>)> = if self.resolvers.is_empty()
+            {
+                vec![(
+                    "the system resolver".to_string(),
+                    lookup_system(hostname, record_type),
+                )]
+            } else {
+                self.resolvers
+                    .iter()
+                    .map(|resolver| {
+                        let addresses = dns::server_address(resolver).and_then(|server| {
+                            dns::resolve(server, hostname, record_type, self.timeout)
+                        });
+                        (resolver.clone(), addresses)
+                    })
+                    .collect()
+            };
+
+            for (resolver, addresses) in answers {
+                match addresses {
+                    Ok(addresses) if addresses == [ip] => {
+                        debug!("{} resolves to {} through {}.", hostname, ip, resolver)
+                    }
+                    Ok(addresses) => drift.push(format!(
+                        "{} resolves to [{}] through {} instead of {}",
+                        hostname,
+                        addresses
+                            .iter()
+                            .map(|address| address.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", "),
+                        resolver,
+                        ip
+                    )),
+                    // A resolver that can't be reached says nothing about the record itself.
+                    Err(err) => error!("{}", err),
+                }
+            }
+        }
+
+        drift
+    }
+}
+
+fn lookup_system(hostname: &str, record_type: RecordType) -> Result<Vec<IpAddr>, IpError> {
+    let addresses = (hostname, 0)
+        .to_socket_addrs()
+        .map_err(|e| IpError::new(ErrorReason::DnsLookupFailed(format!("{}: {}", hostname, e))))?;
+
+    let mut matching: Vec<IpAddr> = addresses
+        .map(|address| address.ip())
+        .filter(|address| RecordType::for_ip(*address) == record_type)
+        .collect();
+    matching.dedup();
+    Ok(matching)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        net::{Ipv4Addr, UdpSocket},
+        sync::Mutex,
+        thread,
+    };
+
+    use chrono::Utc;
+    use trust_dns_proto::{
+        op::{Message, MessageType, ResponseCode},
+        rr::{Name, RData, Record},
+    };
+
+    use super::*;
+
+    // Answers A queries from `records` until the test ends.
+    fn start_resolver(records: HashMap<&'static str, Vec<Ipv4Addr>>) -> String {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            let mut buffer = vec![0u8; 512];
+            loop {
+                let (length, peer) = socket.recv_from(&mut buffer).unwrap();
+                let query = Message::from_vec(&buffer[..length]).unwrap();
+                let question = query.queries()[0].clone();
+
+                let mut response = Message::new();
+                response
+                    .set_id(query.id())
+                    .set_message_type(MessageType::Response)
+                    .add_query(question.clone());
+                match records.get(question.name().to_ascii().trim_end_matches('.')) {
+                    Some(addresses) => {
+                        for address in addresses {
+                            response.add_answer(Record::from_rdata(
+                                Name::from_ascii(question.name().to_ascii()).unwrap(),
+                                60,
+                                RData::A(*address),
+                            ));
+                        }
+                    }
+                    None => {
+                        response.set_response_code(ResponseCode::NXDomain);
+                    }
+                }
+                socket.send_to(&response.to_vec().unwrap(), peer).unwrap();
+            }
+        });
+
+        address
+    }
+
+    #[derive(Default)]
+    struct RecordingNotifier {
+        events: Mutex<Vec<IpEvent>>,
+    }
+
+    impl IpNotifier for RecordingNotifier {
+        fn notify_success(&self, _ip: IpAddr) {}
+
+        fn notify_event(&self, event: &IpEvent) {
+            self.events.lock().unwrap().push(event.clone());
+        }
+    }
+
+    fn results(ip: Ipv4Addr) -> IpResults {
+        let mut results = IpResults::new(None);
+        results.add_result(IpAddr::V4(ip), Utc::now());
+        results
+    }
+
+    #[test]
+    fn test_matching_records_are_not_drift() {
+        let resolver = start_resolver(
+            vec![("home.example.com", vec![Ipv4Addr::new(203, 0, 113, 7)])]
+                .into_iter()
+                .collect(),
+        );
+        let monitor = DnsDriftMonitor::new(vec!["home.example.com".to_string()])
+            .with_resolvers(vec![resolver]);
+        let notifier = RecordingNotifier::default();
+
+        assert!(monitor
+            .check(&results(Ipv4Addr::new(203, 0, 113, 7)), &notifier)
+            .is_empty());
+        assert!(notifier.events.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_disagreeing_resolvers_send_drift_events() {
+        let up_to_date = start_resolver(
+            vec![("home.example.com", vec![Ipv4Addr::new(203, 0, 113, 7)])]
+                .into_iter()
+                .collect(),
+        );
+        let stale = start_resolver(
+            vec![("home.example.com", vec![Ipv4Addr::new(198, 51, 100, 1)])]
+                .into_iter()
+                .collect(),
+        );
+        let monitor = DnsDriftMonitor::new(vec![
+            "home.example.com".to_string(),
+            "missing.example.com".to_string(),
+        ])
+        .with_resolvers(vec![up_to_date, stale.clone()]);
+        let notifier = RecordingNotifier::default();
+
+        let events = monitor.check(&results(Ipv4Addr::new(203, 0, 113, 7)), &notifier);
+
+        assert_eq!(events.len(), 3);
+        assert_eq!(notifier.events.lock().unwrap().len(), 3);
+        let message = events[0].message.clone().unwrap();
+        assert!(message.contains(&format!(
+            "home.example.com resolves to [198.51.100.1] through {} instead of 203.0.113.7",
+            stale
+        )));
+        assert!(events[1]
+            .message
+            .clone()
+            .unwrap()
+            .contains("missing.example.com resolves to []"));
+        assert_eq!(
+            events[0].ip,
+            Some(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)))
+        );
+    }
+
+    #[test]
+    fn test_unreachable_resolvers_are_not_drift() {
+        // Bound but never answered.
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let monitor = DnsDriftMonitor::new(vec!["home.example.com".to_string()])
+            .with_resolvers(vec![socket.local_addr().unwrap().to_string()])
+            .with_timeout(Duration::from_millis(100));
+
+        assert!(monitor
+            .find_drift(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_nothing_is_checked_before_the_first_result() {
+        let monitor = DnsDriftMonitor::new(vec!["home.example.com".to_string()]);
+
+        assert!(monitor
+            .check(&IpResults::new(None), &RecordingNotifier::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_system_resolver() {
+        let monitor = DnsDriftMonitor::new(vec!["localhost".to_string()]);
+
+        assert!(monitor
+            .find_drift(IpAddr::V4(Ipv4Addr::LOCALHOST))
+            .is_empty());
+    }
+}
@@ -9,7 +9,15 @@ pub enum ErrorReason {
     FileOpenFailed(String),
     S3WriteFailed(String),
     RestRequestFailed(String),
+    TlsConfigFailed(String),
+    CertificatePinMismatch(String),
     IpParseFailed(String),
+    CommandFailed(String),
+    CommandTimedOut(String),
+    DnsUpdateFailed(String),
+    DnsLookupFailed(String),
+    DnsDrift(String),
+    MessageFailed(String),
     Generic(String),
 }
 
@@ -36,7 +44,15 @@ impl fmt::Display for IpError {
             ErrorReason::FileOpenFailed(file) => write!(f, "Failed to open file at path {}", file),
             ErrorReason::S3WriteFailed(reason) => write!(f, "Failed to write IP address to S3. Reason: {}", reason),
             ErrorReason::RestRequestFailed(reason) => write!(f, "Failed to make a REST request. Reason: {}", reason),
+            ErrorReason::TlsConfigFailed(reason) => write!(f, "Failed to configure TLS. Reason: {}", reason),
+            ErrorReason::CertificatePinMismatch(reason) => write!(f, "The server certificate did not match a pinned key. Reason: {}", reason),
             ErrorReason::IpParseFailed(reason) => write!(f, "Failed to parse IP address. Reason: {}", reason),
+            ErrorReason::CommandFailed(reason) => write!(f, "Failed to run a command. Reason: {}", reason),
+            ErrorReason::CommandTimedOut(reason) => write!(f, "A command timed out. Reason: {}", reason),
+            ErrorReason::DnsUpdateFailed(reason) => write!(f, "Failed to update DNS records. Reason: {}", reason),
+            ErrorReason::DnsLookupFailed(reason) => write!(f, "Failed to look up DNS records. Reason: {}", reason),
+            ErrorReason::DnsDrift(reason) => write!(f, "A published DNS record does not match the current IP address. Details: {}", reason),
+            ErrorReason::MessageFailed(reason) => write!(f, "Failed to send a message. Reason: {}", reason),
             ErrorReason::Generic(context) => write!(f, "An error was encountered. Context: {}", context),
         }
     }
@@ -48,6 +64,10 @@ impl IpError {
     pub fn new(reason: ErrorReason) -> Self {
         IpError { reason }
     }
+
+    pub fn reason(&self) -> &ErrorReason {
+        &self.reason
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,248 @@
+use std::{collections::HashMap, fmt, net::IpAddr};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    errors::{ErrorReason, IpError},
+    utils,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum IpEventType {
+    #[serde(rename = "ip.changed")]
+    IpChanged,
+    #[serde(rename = "ip.unchanged")]
+    IpUnchanged,
+    #[serde(rename = "ip.conflict")]
+    IpConflict,
+    #[serde(rename = "query.failed")]
+    QueryFailed,
+    #[serde(rename = "dns.drift")]
+    DnsDrift,
+}
+
+impl IpEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IpEventType::IpChanged => "ip.changed",
+            IpEventType::IpUnchanged => "ip.unchanged",
+            IpEventType::IpConflict => "ip.conflict",
+            IpEventType::QueryFailed => "query.failed",
+            IpEventType::DnsDrift => "dns.drift",
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            IpEventType::IpConflict | IpEventType::QueryFailed | IpEventType::DnsDrift
+        )
+    }
+}
+
+impl fmt::Display for IpEventType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IpEvent {
+    // Tells apart events that happen in the same millisecond.
+    #[serde(skip)]
+    pub id: Uuid,
+    pub event_type: IpEventType,
+    pub ip: Option<IpAddr>,
+    pub previous_ip: Option<IpAddr>,
+    pub hostname: String,
+    pub timestamp: DateTime<Utc>,
+    pub message: Option<String>,
+    #[serde(skip)]
+    pub error: Option<IpError>,
+}
+
+impl IpEvent {
+    pub fn new(ip: IpAddr, previous_ip: Option<IpAddr>) -> IpEvent {
+        let event_type = if previous_ip == Some(ip) {
+            IpEventType::IpUnchanged
+        } else {
+            IpEventType::IpChanged
+        };
+
+        IpEvent {
+            id: Uuid::new_v4(),
+            event_type,
+            ip: Some(ip),
+            previous_ip,
+            hostname: utils::get_hostname(),
+            timestamp: Utc::now(),
+            message: None,
+            error: None,
+        }
+    }
+
+    pub fn from_error(err: IpError) -> IpEvent {
+        let event_type = match err.reason() {
+            ErrorReason::IpConflict(_) => IpEventType::IpConflict,
+            ErrorReason::DnsDrift(_) => IpEventType::DnsDrift,
+            _ => IpEventType::QueryFailed,
+        };
+
+        IpEvent {
+            id: Uuid::new_v4(),
+            event_type,
+            ip: None,
+            previous_ip: None,
+            hostname: utils::get_hostname(),
+            timestamp: Utc::now(),
+            message: Some(err.to_string()),
+            error: Some(err),
+        }
+    }
+
+    // Carries the current IP as well as the error, so notifiers that only look at errors still
+    // hear about it.
+    pub fn dns_drift(ip: IpAddr, details: String) -> IpEvent {
+        IpEvent {
+            ip: Some(ip),
+            ..IpEvent::from_error(IpError::new(ErrorReason::DnsDrift(details)))
+        }
+    }
+
+    pub fn ip_or_empty(&self) -> String {
+        self.ip.map(|ip| ip.to_string()).unwrap_or_default()
+    }
+
+    pub fn previous_ip_or_empty(&self) -> String {
+        self.previous_ip
+            .map(|ip| ip.to_string())
+            .unwrap_or_default()
+    }
+
+    pub fn token_values(&self) -> HashMap<String, String> {
+        let mut token_value_map = HashMap::new();
+        token_value_map.insert(utils::TOKEN_IP_ADDRESS.to_string(), self.ip_or_empty());
+        token_value_map.insert(
+            utils::TOKEN_PREVIOUS_IP_ADDRESS.to_string(),
+            self.previous_ip_or_empty(),
+        );
+        token_value_map.insert(utils::TOKEN_HOSTNAME.to_string(), self.hostname.clone());
+        token_value_map.insert(
+            utils::TOKEN_TIMESTAMP.to_string(),
+            self.timestamp.to_rfc3339_opts(SecondsFormat::Secs, true),
+        );
+        token_value_map
+    }
+}
+
+// The events the notifier tests send.
+#[cfg(test)]
+impl IpEvent {
+    pub fn sample_changed() -> IpEvent {
+        IpEvent::new(
+            IpAddr::V4(std::net::Ipv4Addr::new(203, 0, 113, 7)),
+            Some(IpAddr::V4(std::net::Ipv4Addr::new(198, 51, 100, 1))),
+        )
+    }
+
+    pub fn sample_failed() -> IpEvent {
+        IpEvent::from_error(IpError::new(ErrorReason::IpParseFailed(
+            "garbage".to_string(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_new_event_without_previous_ip() {
+        let event = IpEvent::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), None);
+        assert_eq!(event.event_type, IpEventType::IpChanged);
+        assert_eq!(event.previous_ip_or_empty(), "");
+    }
+
+    #[test]
+    fn test_new_event_with_same_previous_ip() {
+        let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let event = IpEvent::new(ip, Some(ip));
+        assert_eq!(event.event_type, IpEventType::IpUnchanged);
+    }
+
+    #[test]
+    fn test_new_event_with_different_previous_ip() {
+        let event = IpEvent::new(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)),
+            Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
+        );
+        assert_eq!(event.event_type, IpEventType::IpChanged);
+        assert_eq!(event.ip_or_empty(), "127.0.0.2");
+        assert_eq!(event.previous_ip_or_empty(), "127.0.0.1");
+    }
+
+    #[test]
+    fn test_event_from_conflict_error() {
+        let event = IpEvent::from_error(IpError::new(ErrorReason::IpConflict(vec![
+            "127.0.0.1".to_string(),
+            "127.0.0.2".to_string(),
+        ])));
+        assert_eq!(event.event_type, IpEventType::IpConflict);
+        assert!(event.event_type.is_error());
+        assert!(event.ip.is_none());
+    }
+
+    #[test]
+    fn test_event_from_other_error() {
+        let event = IpEvent::from_error(IpError::new(ErrorReason::IpParseFailed(
+            "garbage".to_string(),
+        )));
+        assert_eq!(event.event_type, IpEventType::QueryFailed);
+        assert!(event.message.is_some());
+    }
+
+    #[test]
+    fn test_dns_drift_event() {
+        let event = IpEvent::dns_drift(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)),
+            "home.example.com resolves to 127.0.0.1".to_string(),
+        );
+        assert_eq!(event.event_type, IpEventType::DnsDrift);
+        assert_eq!(event.ip_or_empty(), "127.0.0.2");
+        assert!(event.error.is_some());
+        assert!(event
+            .message
+            .unwrap()
+            .contains("home.example.com resolves to 127.0.0.1"));
+    }
+
+    #[test]
+    fn test_token_values() {
+        let event = IpEvent::new(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)),
+            Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
+        );
+        let rendered = utils::replace_tokens(
+            "allow {{TOKEN_IP_ADDRESS}}; # was {{TOKEN_PREVIOUS_IP_ADDRESS}}".to_string(),
+            event.token_values(),
+        );
+
+        assert_eq!(rendered, "allow 127.0.0.2; # was 127.0.0.1");
+    }
+
+    #[test]
+    fn test_event_serialization() {
+        let event = IpEvent::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), None);
+        let json = serde_json::to_value(&event).unwrap();
+
+        assert_eq!(json["eventType"], "ip.changed");
+        assert_eq!(json["ip"], "127.0.0.1");
+        assert!(json["previousIp"].is_null());
+        assert!(json.get("error").is_none());
+    }
+}
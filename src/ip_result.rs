@@ -4,6 +4,7 @@ use chrono::{DateTime, Utc};
 
 use crate::{
     errors::{ErrorReason, IpError},
+    ip_event::IpEvent,
     ip_query::{run_ip_query, IpQueryParams},
     notifier::IpNotifier,
 };
@@ -26,6 +27,8 @@ struct IpResult {
 pub struct IpResults {
     only_notify_on_change: bool,
     results: Vec<IpResult>,
+    // `results` keeps the first IP next to the newest one, so events get the one before from here.
+    last_ip: Option<IpAddr>,
 }
 
 impl IpResults {
@@ -36,6 +39,7 @@ impl IpResults {
                 None => false,
             },
             results: Vec::new(),
+            last_ip: None,
         }
     }
 
@@ -46,21 +50,27 @@ impl IpResults {
         let params = IpQueryParams { services };
 
         match run_ip_query(params) {
-            Ok(ip) => {
-                self.add_result(ip, Utc::now());
-                if self.only_notify_on_change {
-                    if self.ip_has_changed() {
-                        notifier.notify_success(ip);
-                    }
-                } else {
-                    notifier.notify_success(ip);
-                }
-            }
+            Ok(ip) => self.record_ip(ip, &notifier),
             Err(e) => {
-                notifier.notify_error(e);
+                notifier.notify_event(&IpEvent::from_error(e));
             }
         };
     }
+
+    fn record_ip<N>(&mut self, ip: IpAddr, notifier: &N)
+    where
+        N: IpNotifier + ?Sized,
+    {
+        let previous_ip = self.last_ip.replace(ip);
+        self.add_result(ip, Utc::now());
+        if self.only_notify_on_change {
+            if self.ip_has_changed() {
+                notifier.notify_event(&IpEvent::new(ip, previous_ip));
+            }
+        } else {
+            notifier.notify_event(&IpEvent::new(ip, previous_ip));
+        }
+    }
 }
 
 impl IpResultStorage for IpResults {
@@ -98,10 +108,25 @@ impl IpResultStorage for IpResults {
 #[cfg(test)]
 mod tests {
 
-    use std::{error::Error, fmt, net::Ipv4Addr};
+    use std::{cell::RefCell, error::Error, fmt, net::Ipv4Addr};
 
     use super::*;
 
+    #[derive(Default)]
+    struct RecordingNotifier {
+        events: RefCell<Vec<IpEvent>>,
+    }
+
+    impl IpNotifier for RecordingNotifier {
+        fn notify_success(&self, ip: IpAddr) {
+            self.notify_event(&IpEvent::new(ip, None));
+        }
+
+        fn notify_event(&self, event: &IpEvent) {
+            self.events.borrow_mut().push(event.clone());
+        }
+    }
+
     #[derive(Debug, Clone)]
     struct UnexpectedOutputError {}
 
@@ -191,4 +216,25 @@ mod tests {
 
         assert!(results.ip_has_changed() == false);
     }
+
+    #[test]
+    fn test_events_report_the_ip_found_just_before() {
+        let mut results = IpResults::new(Some(false));
+        let notifier = RecordingNotifier::default();
+        let a = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+        let b = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+        let c = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 3));
+
+        results.record_ip(a, &notifier);
+        results.record_ip(b, &notifier);
+        results.record_ip(c, &notifier);
+
+        let previous: Vec<Option<IpAddr>> = notifier
+            .events
+            .borrow()
+            .iter()
+            .map(|event| event.previous_ip)
+            .collect();
+        assert_eq!(previous, vec![None, Some(a), Some(b)]);
+    }
 }
@@ -1,24 +1,60 @@
 use log::{error, info};
 
-use crate::ip_query::IpQueryParams;
+use crate::{
+    drift::DnsDriftMonitor, ip_event::IpEvent, ip_query::IpQueryParams, notifier::build_notifiers,
+};
 
+pub mod cloud_event;
+pub mod command;
 pub mod config_file;
+pub mod dns;
+pub mod drift;
 pub mod errors;
+pub mod ip_event;
 pub mod ip_query;
 pub mod ip_result;
 pub mod notifier;
 pub mod sdk;
+pub mod secret;
+pub mod state;
+pub mod tls;
+pub mod tsig;
 pub mod utils;
+pub mod zone_file;
 
 pub fn schedule_ip_notification(config: config_file::ConfigFile) {
-    match ip_query::run_ip_query(IpQueryParams {
+    let notifiers = match build_notifiers(&config) {
+        Ok(notifiers) => notifiers,
+        Err(e) => return error!("{}", e),
+    };
+
+    let events = match ip_query::run_ip_query(IpQueryParams {
         services: config.services.clone(),
     }) {
-        Ok(ip_result) => {
-            info!("{:?}", ip_result);
+        Ok(ip) => {
+            info!("{:?}", ip);
+
+            let mut events = vec![IpEvent::new(ip, None)];
+            // Resolving is done once, however many notifiers hear about the drift.
+            if let Some(dns_drift) = &config.dns_drift {
+                events.extend(
+                    DnsDriftMonitor::from_config(dns_drift)
+                        .find_drift(ip)
+                        .into_iter()
+                        .map(|details| IpEvent::dns_drift(ip, details)),
+                );
+            }
+            events
         }
         Err(e) => {
             error!("{:?}", e);
+            vec![IpEvent::from_error(e)]
         }
     };
+
+    for notifier in notifiers.iter() {
+        for event in events.iter() {
+            notifier.notify_event(event);
+        }
+    }
 }
@@ -1,68 +1,88 @@
-use std::{collections::HashMap, fs, io::Write, net::IpAddr};
+use std::{collections::HashMap, net::IpAddr};
 
 use chrono::Utc;
-use http::HeaderMap;
+use http::{header::CONTENT_TYPE, HeaderMap, HeaderValue};
 use log::{debug, error, info};
-use reqwest::{Client, Method, Response};
+use reqwest::{Client, Method, RequestBuilder, Response};
 use rusoto_core::Region;
 use rusoto_s3::{PutObjectRequest, StreamingBody, S3};
 
 use crate::{
+    cloud_event::{self, CloudEvent},
+    config_file::{CloudEventsConfig, CloudEventsMode, TlsConfig},
     errors::{ErrorReason, IpError},
+    ip_event::IpEvent,
     sdk::{get_s3_client, CustomStsProvider},
-    utils,
+    tls, utils,
 };
 
+pub mod build;
+pub mod cloudflare;
+pub mod digitalocean;
+pub mod discord;
+pub mod dns;
+pub mod dyndns;
+pub mod email;
+pub mod exec;
+pub mod file;
+pub mod google_cloud_dns;
+pub mod matrix;
+pub mod message;
+pub mod mqtt;
+pub mod powerdns;
+pub mod push;
+pub mod pushover;
+pub mod redis;
+pub mod rfc2136;
+pub mod route53;
+pub mod slack;
+pub mod teams;
+pub mod telegram;
+pub mod template;
+pub mod zone_file;
+
+pub use build::{build_notifier, build_notifiers};
+pub use cloudflare::CloudflareNotifier;
+pub use digitalocean::DigitalOceanProvider;
+pub use discord::DiscordNotifier;
+pub use dns::DnsNotifier;
+pub use dyndns::DynDnsNotifier;
+pub use email::EmailNotifier;
+pub use exec::ExecNotifier;
+pub use file::FileNotifier;
+pub use google_cloud_dns::GoogleCloudDnsNotifier;
+pub use matrix::MatrixNotifier;
+pub use mqtt::MqttNotifier;
+pub use powerdns::PowerDnsNotifier;
+pub use push::PushNotifier;
+pub use pushover::PushoverNotifier;
+pub use redis::RedisNotifier;
+pub use rfc2136::Rfc2136Notifier;
+pub use route53::Route53Notifier;
+pub use slack::SlackNotifier;
+pub use teams::TeamsNotifier;
+pub use telegram::TelegramNotifier;
+pub use template::TemplateNotifier;
+pub use zone_file::ZoneFileNotifier;
+
 pub trait IpNotifier {
     fn notify_success(&self, ip: IpAddr);
     fn notify_error(&self, err: IpError) {
         error!("{}", err);
     }
-}
-
-pub struct FileNotifier {
-    overwrite: bool,
-    file_path: String,
-}
-
-impl FileNotifier {
-    pub fn new(file_path: String, overwrite: bool) -> FileNotifier {
-        FileNotifier {
-            file_path,
-            overwrite,
-        }
+    fn notify_event(&self, event: &IpEvent) {
+        dispatch_event(self, event);
     }
 }
 
-impl IpNotifier for FileNotifier {
-    fn notify_success(&self, ip: IpAddr) {
-        if self.overwrite {
-            match fs::write(ip.to_string(), self.file_path.clone()) {
-                Ok(()) => debug!("IP written to file successfully."),
-                Err(_) => IpNotifier::notify_error(
-                    self,
-                    IpError::new(ErrorReason::FileWriteFailed(self.file_path.clone())),
-                ),
-            }
-        } else {
-            match fs::OpenOptions::new()
-                .write(true)
-                .append(true)
-                .open(self.file_path.clone())
-            {
-                Ok(mut file) => match file.write(&ip.to_string().into_bytes()) {
-                    Ok(_) => debug!("IP written to file successfully."),
-                    Err(_) => IpNotifier::notify_error(
-                        self,
-                        IpError::new(ErrorReason::FileWriteFailed(self.file_path.clone())),
-                    ),
-                },
-                Err(_) => IpNotifier::notify_error(
-                    self,
-                    IpError::new(ErrorReason::FileOpenFailed(self.file_path.clone())),
-                ),
-            }
-        }
+pub fn dispatch_event<N>(notifier: &N, event: &IpEvent)
+where
+    N: IpNotifier + ?Sized,
+{
+    match (&event.error, event.ip) {
+        (Some(err), _) => notifier.notify_error(err.clone()),
+        (None, Some(ip)) => notifier.notify_success(ip),
+        (None, None) => debug!("Skipping {} event without an IP address.", event.event_type),
     }
 }
 
@@ -139,6 +159,7 @@ pub struct RestNotifier {
     headers: HeaderMap,
     client: Client,
     tokens: Vec<String>,
+    cloud_events: Option<CloudEventsConfig>,
 }
 
 impl RestNotifier {
@@ -155,26 +176,92 @@ impl RestNotifier {
             headers,
             client: Client::builder().build().unwrap(),
             tokens: vec!["{{TOKEN_IP_ADDRESS}}".to_string()],
+            cloud_events: None,
         }
     }
 
-    async fn make_request(&self, ip: IpAddr) -> Result<Response, reqwest::Error> {
+    pub fn with_tls(
+        url: String,
+        method: Method,
+        body: HashMap<String, String>,
+        headers: HeaderMap,
+        tls_config: TlsConfig,
+    ) -> Result<RestNotifier, IpError> {
+        Ok(RestNotifier {
+            client: tls::build_client(Some(&tls_config))?,
+            ..RestNotifier::new(url, method, body, headers)
+        })
+    }
+
+    pub fn with_cloud_events(mut self, cloud_events: CloudEventsConfig) -> RestNotifier {
+        self.cloud_events = Some(cloud_events);
+        self
+    }
+
+    fn token_value_map(&self, ip: String) -> HashMap<String, String> {
         let mut token_value_map = HashMap::new();
-        token_value_map.insert(self.tokens[0].clone(), ip.to_string());
+        token_value_map.insert(self.tokens[0].clone(), ip);
+        token_value_map
+    }
+
+    async fn make_request(&self, ip: IpAddr) -> Result<Response, IpError> {
+        let token_value_map = self.token_value_map(ip.to_string());
 
         let request = self.client.request(
             self.method.clone(),
             utils::replace_tokens(self.url.clone(), token_value_map.clone()),
         );
 
+        self.send(
+            request
+                .headers(self.headers.clone())
+                .body(utils::replace_tokens(
+                    serde_json::to_string(&self.body).unwrap(),
+                    token_value_map.clone(),
+                )),
+        )
+        .await
+    }
+
+    async fn make_cloud_event_request(
+        &self,
+        event: &IpEvent,
+        cloud_events: &CloudEventsConfig,
+    ) -> Result<Response, IpError> {
+        let source = cloud_events
+            .source
+            .clone()
+            .unwrap_or_else(|| cloud_event::default_source(&event.hostname));
+        let cloud_event = CloudEvent::new(event, source);
+        let mut headers = self.headers.clone();
+
+        let body = match cloud_events.mode {
+            CloudEventsMode::Structured => {
+                headers.insert(
+                    CONTENT_TYPE,
+                    HeaderValue::from_static(cloud_event::STRUCTURED_CONTENT_TYPE),
+                );
+                serde_json::to_string(&cloud_event).unwrap()
+            }
+            CloudEventsMode::Binary => {
+                headers.extend(cloud_event.binary_headers()?);
+                serde_json::to_string(event).unwrap()
+            }
+        };
+
+        let request = self.client.request(
+            self.method.clone(),
+            utils::replace_tokens(self.url.clone(), self.token_value_map(event.ip_or_empty())),
+        );
+
+        self.send(request.headers(headers).body(body)).await
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, IpError> {
         request
-            .headers(self.headers.clone())
-            .body(utils::replace_tokens(
-                serde_json::to_string(&self.body).unwrap(),
-                token_value_map.clone(),
-            ))
             .send()
             .await
+            .map_err(|err| IpError::new(ErrorReason::RestRequestFailed(err.to_string())))
     }
 }
 
@@ -189,10 +276,25 @@ impl IpNotifier for RestNotifier {
                 debug!("IP written to REST successfully. Output follows.");
                 debug!("{:#?}", output);
             }
-            Err(err) => IpNotifier::notify_error(
-                self,
-                IpError::new(ErrorReason::RestRequestFailed(err.to_string())),
-            ),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+
+    fn notify_event(&self, event: &IpEvent) {
+        let cloud_events = match &self.cloud_events {
+            Some(cloud_events) => cloud_events,
+            None => return dispatch_event(self, event),
+        };
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let response = runtime.block_on(self.make_cloud_event_request(event, cloud_events));
+
+        match response {
+            Ok(output) => {
+                debug!("CloudEvent sent to REST successfully. Output follows.");
+                debug!("{:#?}", output);
+            }
+            Err(err) => IpNotifier::notify_error(self, err),
         }
     }
 }
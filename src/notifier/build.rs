@@ -0,0 +1,685 @@
+use std::{path::Path, time::Duration};
+
+use rumqttc::QoS;
+
+use crate::{
+    command::CommandSpec,
+    config_file::{ConfigFile, Notifier},
+    errors::{ErrorReason, IpError},
+    notifier::{
+        email::SmtpCredentials,
+        google_cloud_dns::ServiceAccountKey,
+        push::{NtfyAuth, PushService},
+        slack::SlackTarget,
+        CloudflareNotifier, DigitalOceanProvider, DiscordNotifier, DnsNotifier, DynDnsNotifier,
+        EmailNotifier, ExecNotifier, FileNotifier, GoogleCloudDnsNotifier, IpNotifier,
+        MatrixNotifier, MqttNotifier, PowerDnsNotifier, PushNotifier, PushoverNotifier,
+        RedisNotifier, RestNotifier, Rfc2136Notifier, Route53Notifier, S3Notifier, SlackNotifier,
+        StdoutNotifier, TeamsNotifier, TelegramNotifier, TemplateNotifier, ZoneFileNotifier,
+    },
+    state::StateFile,
+    tsig::TsigKey,
+};
+
+pub fn build_notifiers(config: &ConfigFile) -> Result<Vec<Box<dyn IpNotifier>>, IpError> {
+    config
+        .notifiers
+        .iter()
+        .map(|notifier| build_notifier(notifier, config))
+        .collect()
+}
+
+// Turns one entry of `notifiers` into the notifier it configures. Everything that can be checked
+// before the first event, like key files and commands, is checked here.
+pub fn build_notifier(
+    notifier: &Notifier,
+    config: &ConfigFile,
+) -> Result<Box<dyn IpNotifier>, IpError> {
+    let built: Box<dyn IpNotifier> = match notifier.clone() {
+        Notifier::File {
+            overwrite,
+            file_path,
+            format,
+            permissions,
+        } => {
+            let file = FileNotifier::new(file_path, overwrite).with_format(format);
+            Box::new(match permissions {
+                Some(permissions) => file.with_permissions(permissions),
+                None => file,
+            })
+        }
+        Notifier::S3 {
+            access_key_id,
+            secret_access_key,
+            assume_role_arn,
+            region,
+            bucket_name,
+        } => Box::new(S3Notifier::new(
+            access_key_id,
+            secret_access_key,
+            assume_role_arn.to_string(),
+            region.name().to_string(),
+            bucket_name,
+        )),
+        Notifier::RestApi {
+            url,
+            method,
+            body,
+            headers,
+            tls,
+            cloud_events,
+        } => {
+            let rest = match tls {
+                Some(tls) => RestNotifier::with_tls(url, method, body, headers, tls)?,
+                None => RestNotifier::new(url, method, body, headers),
+            };
+            Box::new(match cloud_events {
+                Some(cloud_events) => rest.with_cloud_events(cloud_events),
+                None => rest,
+            })
+        }
+        Notifier::Template {
+            template_path,
+            destination_path,
+            permissions,
+            post_render_command,
+            command_timeout_seconds,
+        } => {
+            let mut template = TemplateNotifier::new(template_path, destination_path);
+            if let Some(permissions) = permissions {
+                template = template.with_permissions(permissions);
+            }
+            let timeout = Duration::from_secs(command_timeout_seconds);
+            if let Some(command) = CommandSpec::from_argv(&post_render_command, timeout) {
+                template = template.with_post_render_command(command);
+            }
+            Box::new(template)
+        }
+        Notifier::Exec {
+            command,
+            args,
+            env,
+            clear_env,
+            working_directory,
+            timeout_seconds,
+        } => {
+            let mut spec = CommandSpec::new(command, args, Duration::from_secs(timeout_seconds))
+                .with_env(env)
+                .with_clear_env(clear_env);
+            if let Some(working_directory) = working_directory {
+                spec = spec.with_working_directory(working_directory);
+            }
+            Box::new(ExecNotifier::new(spec))
+        }
+        Notifier::Cloudflare {
+            api_token,
+            zone_name,
+            record_names,
+            create_missing,
+            proxied,
+            ttl,
+            api_base_url,
+        } => {
+            let mut cloudflare = CloudflareNotifier::new(api_token, zone_name, record_names)
+                .with_create_missing(create_missing);
+            if let Some(proxied) = proxied {
+                cloudflare = cloudflare.with_proxied(proxied);
+            }
+            if let Some(ttl) = ttl {
+                cloudflare = cloudflare.with_ttl(ttl);
+            }
+            if let Some(api_base_url) = api_base_url {
+                cloudflare = cloudflare.with_api_base_url(api_base_url);
+            }
+            Box::new(cloudflare)
+        }
+        Notifier::Route53 {
+            access_key_id,
+            secret_access_key,
+            assume_role_arn,
+            region,
+            hosted_zone_id,
+            record_names,
+            ttl,
+            wait_for_sync,
+            sync_timeout_seconds,
+            endpoint,
+        } => {
+            let mut route53 = Route53Notifier::new(
+                access_key_id,
+                secret_access_key,
+                assume_role_arn.to_string(),
+                region,
+                hosted_zone_id,
+                record_names,
+                ttl,
+            );
+            if wait_for_sync {
+                route53 = route53.with_wait_for_sync(Duration::from_secs(sync_timeout_seconds));
+            }
+            if let Some(endpoint) = endpoint {
+                route53 = route53.with_endpoint(endpoint);
+            }
+            Box::new(route53)
+        }
+        Notifier::Rfc2136 {
+            server,
+            zone,
+            record_names,
+            ttl,
+            key_file,
+            timeout_seconds,
+        } => Box::new(
+            Rfc2136Notifier::new(
+                server,
+                zone,
+                record_names,
+                ttl,
+                TsigKey::from_key_file(&key_file)?,
+            )
+            .with_timeout(Duration::from_secs(timeout_seconds)),
+        ),
+        Notifier::Dyndns2 {
+            url,
+            username,
+            password,
+            hostnames,
+        } => Box::new(
+            DynDnsNotifier::new(url, username, password, hostnames).with_state_file(
+                StateFile::for_notifier(Path::new(&config.state_directory), notifier),
+            ),
+        ),
+        Notifier::PowerDns {
+            api_url,
+            api_key,
+            server_id,
+            zone,
+            record_names,
+            ttl,
+            comment,
+        } => {
+            let powerdns = PowerDnsNotifier::new(api_url, api_key, zone, record_names, ttl)
+                .with_server_id(server_id);
+            Box::new(match comment {
+                Some(comment) => powerdns.with_comment(comment),
+                None => powerdns,
+            })
+        }
+        Notifier::DigitalOcean {
+            token,
+            domain,
+            record_names,
+            create_missing,
+            remove_others,
+            ttl,
+            api_base_url,
+        } => {
+            let mut provider = DigitalOceanProvider::new(token, domain);
+            if let Some(api_base_url) = api_base_url {
+                provider = provider.with_api_base_url(api_base_url);
+            }
+            let mut digitalocean = DnsNotifier::new(provider, record_names)
+                .with_create_missing(create_missing)
+                .with_remove_others(remove_others);
+            if let Some(ttl) = ttl {
+                digitalocean = digitalocean.with_ttl(ttl);
+            }
+            Box::new(digitalocean)
+        }
+        Notifier::GoogleCloudDns {
+            key_file,
+            project,
+            managed_zone,
+            record_names,
+            ttl,
+            token_url,
+            api_base_url,
+        } => {
+            let key = ServiceAccountKey::from_key_file(&key_file)?;
+            let mut google = GoogleCloudDnsNotifier::new(key, managed_zone, record_names, ttl);
+            if let Some(project) = project {
+                google = google.with_project(project);
+            }
+            if let Some(token_url) = token_url {
+                google = google.with_token_url(token_url);
+            }
+            if let Some(api_base_url) = api_base_url {
+                google = google.with_api_base_url(api_base_url);
+            }
+            Box::new(google)
+        }
+        Notifier::ZoneFile {
+            zone_file_path,
+            zone,
+            record_names,
+            serial_format,
+            rndc_reload,
+            command_timeout_seconds,
+        } => {
+            let mut zone_file = ZoneFileNotifier::new(zone_file_path, zone, record_names)
+                .with_serial_format(serial_format);
+            if rndc_reload {
+                let reload = zone_file.rndc_reload(Duration::from_secs(command_timeout_seconds));
+                zone_file = zone_file.with_reload_command(reload);
+            }
+            Box::new(zone_file)
+        }
+        Notifier::Slack {
+            webhook_url,
+            bot_token,
+            channel,
+            api_base_url,
+        } => {
+            let target = match (webhook_url, bot_token, channel) {
+                (Some(webhook_url), None, None) => SlackTarget::Webhook(webhook_url),
+                (None, Some(token), Some(channel)) => SlackTarget::Bot { token, channel },
+                _ => {
+                    return Err(invalid(
+                        "slack needs either webhookUrl or botToken and channel",
+                    ))
+                }
+            };
+            let slack = SlackNotifier::new(target);
+            Box::new(match api_base_url {
+                Some(api_base_url) => slack.with_api_base_url(api_base_url),
+                None => slack,
+            })
+        }
+        Notifier::Discord {
+            webhook_url,
+            mention_users,
+            mention_roles,
+            max_retries,
+        } => Box::new(
+            DiscordNotifier::new(webhook_url)
+                .with_mentions(mention_users, mention_roles)
+                .with_max_retries(max_retries),
+        ),
+        Notifier::Teams {
+            webhook_url,
+            templates,
+        } => Box::new(
+            TeamsNotifier::new(webhook_url)
+                .with_templates(templates)
+                .with_sources(config.services.clone()),
+        ),
+        Notifier::Telegram {
+            bot_token,
+            chat_ids,
+            parse_mode,
+            api_base_url,
+        } => {
+            let telegram = TelegramNotifier::new(bot_token, chat_ids).with_parse_mode(parse_mode);
+            Box::new(match api_base_url {
+                Some(api_base_url) => telegram.with_api_base_url(api_base_url),
+                None => telegram,
+            })
+        }
+        Notifier::Matrix {
+            homeserver_url,
+            access_token,
+            room_id,
+            max_retries,
+        } => Box::new(
+            MatrixNotifier::new(homeserver_url, access_token, room_id)
+                .with_max_retries(max_retries),
+        ),
+        Notifier::Ntfy {
+            topic_url,
+            tags,
+            title,
+            token,
+            username,
+            password,
+            priorities,
+        } => {
+            let auth = match (token, username, password) {
+                (None, None, None) => None,
+                (Some(token), None, None) => Some(NtfyAuth::Token(token)),
+                (None, Some(username), Some(password)) => {
+                    Some(NtfyAuth::Basic { username, password })
+                }
+                _ => {
+                    return Err(invalid(
+                        "ntfy takes either a token or a username and password",
+                    ))
+                }
+            };
+            let ntfy = PushNotifier::new(PushService::Ntfy {
+                topic_url,
+                tags,
+                auth,
+            })
+            .with_priorities(priorities);
+            Box::new(match title {
+                Some(title) => ntfy.with_title(title),
+                None => ntfy,
+            })
+        }
+        Notifier::Gotify {
+            server_url,
+            app_token,
+            priorities,
+        } => Box::new(
+            PushNotifier::new(PushService::Gotify {
+                server_url,
+                app_token,
+            })
+            .with_priorities(priorities),
+        ),
+        Notifier::Pushover {
+            app_token,
+            user_key,
+            devices,
+            emergency_after_failures,
+            retry_seconds,
+            expire_seconds,
+            status_url,
+        } => {
+            let mut pushover = PushoverNotifier::new(app_token, user_key)
+                .with_devices(devices)
+                .with_state_file(StateFile::for_notifier(
+                    Path::new(&config.state_directory),
+                    notifier,
+                ));
+            if let Some(after_failures) = emergency_after_failures {
+                pushover = pushover.with_emergency(after_failures, retry_seconds, expire_seconds);
+            }
+            if let Some(status_url) = status_url {
+                pushover = pushover.with_status_url(status_url);
+            }
+            Box::new(pushover)
+        }
+        Notifier::Email {
+            smtp_host,
+            smtp_port,
+            tls,
+            username,
+            password,
+            auth_mechanism,
+            from,
+            to,
+            subject,
+            text_template_path,
+            html_template_path,
+            timeout_seconds,
+        } => {
+            let mut email = EmailNotifier::new(smtp_host, from, to)
+                .with_tls(tls)
+                .with_timeout(Duration::from_secs(timeout_seconds));
+            if let Some(smtp_port) = smtp_port {
+                email = email.with_smtp_port(smtp_port);
+            }
+            match (username, password) {
+                (Some(username), Some(password)) => {
+                    email = email.with_credentials(SmtpCredentials {
+                        username,
+                        password,
+                        mechanism: auth_mechanism,
+                    })
+                }
+                (None, None) => {}
+                _ => return Err(invalid("email needs both a username and a password")),
+            }
+            if let Some(subject) = subject {
+                email = email.with_subject(subject);
+            }
+            if let Some(text_template_path) = text_template_path {
+                email = email.with_text_template(text_template_path);
+            }
+            if let Some(html_template_path) = html_template_path {
+                email = email.with_html_template(html_template_path);
+            }
+            Box::new(email)
+        }
+        Notifier::Mqtt {
+            host,
+            port,
+            topic,
+            qos,
+            client_id,
+            username,
+            password,
+            tls,
+            ca_certificate,
+            home_assistant_discovery,
+            discovery_prefix,
+            availability_topic,
+        } => {
+            let mut mqtt = MqttNotifier::new(host, port, topic).with_qos(match qos {
+                0 => QoS::AtMostOnce,
+                1 => QoS::AtLeastOnce,
+                _ => QoS::ExactlyOnce,
+            });
+            if let Some(client_id) = client_id {
+                mqtt = mqtt.with_client_id(client_id);
+            }
+            match (username, password) {
+                (Some(username), Some(password)) => {
+                    mqtt = mqtt.with_credentials(username, password)
+                }
+                (None, None) => {}
+                _ => return Err(invalid("mqtt needs both a username and a password")),
+            }
+            if tls {
+                mqtt = mqtt.with_tls(ca_certificate);
+            }
+            if home_assistant_discovery {
+                mqtt = mqtt.with_home_assistant_discovery(discovery_prefix);
+            }
+            if let Some(availability_topic) = availability_topic {
+                mqtt = mqtt.with_availability_topic(availability_topic);
+            }
+            Box::new(mqtt)
+        }
+        Notifier::Redis {
+            host,
+            port,
+            key,
+            ttl_seconds,
+            value_format,
+            channel,
+            username,
+            password,
+            tls,
+            database,
+            timeout_seconds,
+        } => {
+            let mut redis = RedisNotifier::new(host, port, key)
+                .with_value_format(value_format)
+                .with_database(database)
+                .with_timeout(Duration::from_secs(timeout_seconds));
+            if let Some(ttl_seconds) = ttl_seconds {
+                redis = redis.with_ttl(Duration::from_secs(ttl_seconds));
+            }
+            if let Some(channel) = channel {
+                redis = redis.with_channel(channel);
+            }
+            if let Some(password) = password {
+                redis = redis.with_credentials(username, password);
+            }
+            if tls {
+                redis = redis.with_tls();
+            }
+            Box::new(redis)
+        }
+        Notifier::Stdout => Box::new(StdoutNotifier::new()),
+    };
+
+    Ok(built)
+}
+
+fn invalid(context: &str) -> IpError {
+    IpError::new(ErrorReason::InvalidInput(context.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        net::{IpAddr, Ipv4Addr},
+    };
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::config_file::load_config_from_file;
+
+    fn build_fixture(name: &str) -> Result<Vec<Box<dyn IpNotifier>>, IpError> {
+        let config = load_config_from_file(format!("testfiles/{}.yml", name)).unwrap();
+        build_notifiers(&config)
+    }
+
+    #[test]
+    fn test_file_notifier_writes_the_ip() {
+        let directory = tempdir().unwrap();
+        let path = directory.path().join("ip.txt");
+        let mut config = load_config_from_file("testfiles/stdout.yml".to_string()).unwrap();
+        config.notifiers = vec![Notifier::File {
+            overwrite: true,
+            file_path: path.display().to_string(),
+            format: crate::config_file::FileFormat::Plain,
+            permissions: None,
+        }];
+
+        let notifiers = build_notifiers(&config).unwrap();
+        notifiers[0].notify_success(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)));
+
+        assert!(fs::read_to_string(&path).unwrap().contains("203.0.113.7"));
+    }
+
+    #[test]
+    fn test_builds_s3() {
+        assert!(build_fixture("s3").is_ok());
+    }
+
+    #[test]
+    fn test_builds_rest_api() {
+        assert!(build_fixture("rest").is_ok());
+        assert!(build_fixture("rest_tls").is_ok());
+        assert!(build_fixture("rest_cloud_events").is_ok());
+    }
+
+    #[test]
+    fn test_builds_stdout() {
+        assert!(build_fixture("stdout").is_ok());
+    }
+
+    #[test]
+    fn test_builds_template() {
+        assert!(build_fixture("template").is_ok());
+    }
+
+    #[test]
+    fn test_builds_exec() {
+        assert!(build_fixture("exec").is_ok());
+    }
+
+    #[test]
+    fn test_builds_cloudflare() {
+        assert!(build_fixture("cloudflare").is_ok());
+    }
+
+    #[test]
+    fn test_builds_route53() {
+        assert!(build_fixture("route53").is_ok());
+    }
+
+    #[test]
+    fn test_rfc2136_needs_its_key_file() {
+        assert!(build_fixture("rfc2136").is_err());
+    }
+
+    #[test]
+    fn test_builds_dyndns2() {
+        assert!(build_fixture("dyndns2").is_ok());
+    }
+
+    #[test]
+    fn test_builds_powerdns() {
+        assert!(build_fixture("powerdns").is_ok());
+    }
+
+    #[test]
+    fn test_builds_digitalocean() {
+        assert!(build_fixture("digitalocean").is_ok());
+    }
+
+    #[test]
+    fn test_google_cloud_dns_needs_its_key_file() {
+        assert!(build_fixture("google_cloud_dns").is_err());
+    }
+
+    #[test]
+    fn test_builds_zone_file() {
+        assert!(build_fixture("zone_file").is_ok());
+    }
+
+    #[test]
+    fn test_builds_slack() {
+        assert!(build_fixture("slack").is_ok());
+    }
+
+    #[test]
+    fn test_slack_needs_exactly_one_target() {
+        let mut config = load_config_from_file("testfiles/slack.yml".to_string()).unwrap();
+        config.notifiers = vec![Notifier::Slack {
+            webhook_url: None,
+            bot_token: None,
+            channel: None,
+            api_base_url: None,
+        }];
+
+        assert!(build_notifiers(&config).is_err());
+    }
+
+    #[test]
+    fn test_builds_discord() {
+        assert!(build_fixture("discord").is_ok());
+    }
+
+    #[test]
+    fn test_builds_teams() {
+        assert!(build_fixture("teams").is_ok());
+    }
+
+    #[test]
+    fn test_builds_telegram() {
+        assert!(build_fixture("telegram").is_ok());
+    }
+
+    #[test]
+    fn test_builds_matrix() {
+        assert!(build_fixture("matrix").is_ok());
+    }
+
+    #[test]
+    fn test_builds_ntfy() {
+        assert!(build_fixture("ntfy").is_ok());
+    }
+
+    #[test]
+    fn test_builds_gotify() {
+        assert!(build_fixture("gotify").is_ok());
+    }
+
+    #[test]
+    fn test_builds_pushover() {
+        assert!(build_fixture("pushover").is_ok());
+    }
+
+    #[test]
+    fn test_builds_email() {
+        assert!(build_fixture("email").is_ok());
+    }
+
+    #[test]
+    fn test_builds_mqtt() {
+        assert!(build_fixture("mqtt").is_ok());
+    }
+
+    #[test]
+    fn test_builds_redis() {
+        assert!(build_fixture("redis").is_ok());
+    }
+}
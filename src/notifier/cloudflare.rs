@@ -0,0 +1,415 @@
+use std::net::IpAddr;
+
+use log::{debug, info};
+use reqwest::{Client, RequestBuilder};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    dns::RecordType,
+    errors::{ErrorReason, IpError},
+    notifier::IpNotifier,
+};
+
+pub const CLOUDFLARE_API_BASE_URL: &str = "https://api.cloudflare.com/client/v4";
+
+// Cloudflare treats a TTL of 1 as "automatic".
+const AUTOMATIC_TTL: u32 = 1;
+
+#[derive(Debug, Deserialize)]
+struct CloudflareResponse<T> {
+    success: bool,
+    #[serde(default)]
+    errors: Vec<CloudflareError>,
+    result: Option<T>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CloudflareError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Zone {
+    id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DnsRecord {
+    id: String,
+    content: String,
+    proxied: Option<bool>,
+    ttl: u32,
+}
+
+pub struct CloudflareNotifier {
+    api_token: String,
+    zone_name: String,
+    record_names: Vec<String>,
+    create_missing: bool,
+    proxied: Option<bool>,
+    ttl: Option<u32>,
+    api_base_url: String,
+    client: Client,
+}
+
+impl CloudflareNotifier {
+    pub fn new(
+        api_token: String,
+        zone_name: String,
+        record_names: Vec<String>,
+    ) -> CloudflareNotifier {
+        CloudflareNotifier {
+            api_token,
+            zone_name,
+            record_names,
+            create_missing: false,
+            proxied: None,
+            ttl: None,
+            api_base_url: CLOUDFLARE_API_BASE_URL.to_string(),
+            client: Client::builder().build().unwrap(),
+        }
+    }
+
+    pub fn with_create_missing(mut self, create_missing: bool) -> CloudflareNotifier {
+        self.create_missing = create_missing;
+        self
+    }
+
+    pub fn with_proxied(mut self, proxied: bool) -> CloudflareNotifier {
+        self.proxied = Some(proxied);
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: u32) -> CloudflareNotifier {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_api_base_url(mut self, api_base_url: String) -> CloudflareNotifier {
+        self.api_base_url = api_base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub async fn update_records(&self, ip: IpAddr) -> Result<(), IpError> {
+        let zone_id = self.find_zone_id().await?;
+
+        let mut failures = Vec::new();
+        for record_name in self.record_names.iter() {
+            if let Err(err) = self.update_record(&zone_id, record_name, ip).await {
+                failures.push(match err.reason() {
+                    ErrorReason::DnsUpdateFailed(context) => context.clone(),
+                    _ => err.to_string(),
+                });
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(dns_error(failures.join("; ")))
+        }
+    }
+
+    async fn find_zone_id(&self) -> Result<String, IpError> {
+        let request = self
+            .client
+            .get(format!("{}/zones", self.api_base_url))
+            .query(&[("name", self.zone_name.as_str())]);
+        let zones: Vec<Zone> = self.call(request).await?;
+
+        zones
+            .into_iter()
+            .next()
+            .map(|zone| zone.id)
+            .ok_or_else(|| dns_error(format!("zone {} was not found", self.zone_name)))
+    }
+
+    async fn update_record(
+        &self,
+        zone_id: &str,
+        record_name: &str,
+        ip: IpAddr,
+    ) -> Result<(), IpError> {
+        let record_type = RecordType::for_ip(ip);
+        let records_url = format!("{}/zones/{}/dns_records", self.api_base_url, zone_id);
+
+        let request = self
+            .client
+            .get(&records_url)
+            .query(&[("type", record_type.as_str()), ("name", record_name)]);
+        let records: Vec<DnsRecord> = self.call(request).await?;
+
+        match records.into_iter().next() {
+            Some(record) if self.record_matches(&record, ip) => {
+                debug!(
+                    "{} {} already points at {}, skipping the update.",
+                    record_type, record_name, ip
+                );
+                Ok(())
+            }
+            Some(record) => {
+                let request = self
+                    .client
+                    .patch(format!("{}/{}", records_url, record.id))
+                    .json(&self.record_body(None, ip));
+                let _: Value = self.call(request).await?;
+                info!("Updated {} {} to {}.", record_type, record_name, ip);
+                Ok(())
+            }
+            None if self.create_missing => {
+                let request = self
+                    .client
+                    .post(&records_url)
+                    .json(&self.record_body(Some((record_type, record_name)), ip));
+                let _: Value = self.call(request).await?;
+                info!(
+                    "Created {} {} pointing at {}.",
+                    record_type, record_name, ip
+                );
+                Ok(())
+            }
+            None => Err(dns_error(format!(
+                "{} record {} does not exist",
+                record_type, record_name
+            ))),
+        }
+    }
+
+    fn record_matches(&self, record: &DnsRecord, ip: IpAddr) -> bool {
+        record.content == ip.to_string()
+            && (self.proxied.is_none() || self.proxied == record.proxied)
+            && (self.ttl.is_none() || self.ttl == Some(record.ttl))
+    }
+
+    // Only the fields that are configured get sent on updates so Cloudflare keeps the rest as is.
+    fn record_body(&self, new_record: Option<(RecordType, &str)>, ip: IpAddr) -> Value {
+        let mut body = Map::new();
+        body.insert("content".to_string(), json!(ip.to_string()));
+
+        if let Some((record_type, record_name)) = new_record {
+            body.insert("type".to_string(), json!(record_type.as_str()));
+            body.insert("name".to_string(), json!(record_name));
+            body.insert("ttl".to_string(), json!(self.ttl.unwrap_or(AUTOMATIC_TTL)));
+            body.insert("proxied".to_string(), json!(self.proxied.unwrap_or(false)));
+        } else {
+            if let Some(ttl) = self.ttl {
+                body.insert("ttl".to_string(), json!(ttl));
+            }
+            if let Some(proxied) = self.proxied {
+                body.insert("proxied".to_string(), json!(proxied));
+            }
+        }
+
+        Value::Object(body)
+    }
+
+    async fn call<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, IpError> {
+        let response = request
+            .bearer_auth(&self.api_token)
+            .send()
+            .await
+            .map_err(|e| dns_error(e.to_string()))?;
+        let status = response.status();
+        let body: CloudflareResponse<T> = response
+            .json()
+            .await
+            .map_err(|e| dns_error(format!("unexpected response ({}): {}", status, e)))?;
+
+        if !body.success {
+            let errors: Vec<String> = body
+                .errors
+                .iter()
+                .map(|error| format!("{} ({})", error.message, error.code))
+                .collect();
+            return Err(dns_error(format!(
+                "Cloudflare returned {}: {}",
+                status,
+                errors.join(", ")
+            )));
+        }
+
+        body.result
+            .ok_or_else(|| dns_error(format!("Cloudflare returned {} without a result", status)))
+    }
+}
+
+fn dns_error(context: String) -> IpError {
+    IpError::new(ErrorReason::DnsUpdateFailed(context))
+}
+
+impl IpNotifier for CloudflareNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        match runtime.block_on(self.update_records(ip)) {
+            Ok(()) => debug!("Cloudflare records are up to date."),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use httpmock::{
+        Method::{GET, PATCH, POST},
+        MockServer,
+    };
+
+    use super::*;
+
+    fn ip() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))
+    }
+
+    fn notifier(server: &MockServer) -> CloudflareNotifier {
+        CloudflareNotifier::new(
+            "token".to_string(),
+            "example.com".to_string(),
+            vec!["home.example.com".to_string()],
+        )
+        .with_api_base_url(server.base_url())
+    }
+
+    fn update(notifier: &CloudflareNotifier, ip: IpAddr) -> Result<(), IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.update_records(ip))
+    }
+
+    fn mock_zone(server: &MockServer) {
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/zones")
+                .query_param("name", "example.com")
+                .header("Authorization", "Bearer token");
+            then.status(200)
+                .json_body(json!({ "success": true, "errors": [], "result": [{ "id": "zone1" }] }));
+        });
+    }
+
+    fn mock_records(server: &MockServer, record_type: &str, records: Value) {
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/zones/zone1/dns_records")
+                .query_param("type", record_type)
+                .query_param("name", "home.example.com");
+            then.status(200)
+                .json_body(json!({ "success": true, "errors": [], "result": records }));
+        });
+    }
+
+    #[test]
+    fn test_skips_update_when_record_matches() {
+        let server = MockServer::start();
+        mock_zone(&server);
+        mock_records(
+            &server,
+            "A",
+            json!([{ "id": "rec1", "content": "203.0.113.7", "proxied": true, "ttl": 1 }]),
+        );
+        let patch = server.mock(|when, then| {
+            when.method(PATCH);
+            then.status(200);
+        });
+
+        assert!(update(&notifier(&server), ip()).is_ok());
+        patch.assert_hits(0);
+    }
+
+    #[test]
+    fn test_updates_record_and_preserves_proxied() {
+        let server = MockServer::start();
+        mock_zone(&server);
+        mock_records(
+            &server,
+            "A",
+            json!([{ "id": "rec1", "content": "198.51.100.1", "proxied": true, "ttl": 1 }]),
+        );
+        let patch = server.mock(|when, then| {
+            when.method(PATCH)
+                .path("/zones/zone1/dns_records/rec1")
+                .json_body(json!({ "content": "203.0.113.7" }));
+            then.status(200)
+                .json_body(json!({ "success": true, "errors": [], "result": {} }));
+        });
+
+        assert!(update(&notifier(&server), ip()).is_ok());
+        patch.assert();
+    }
+
+    #[test]
+    fn test_updates_record_when_ttl_differs() {
+        let server = MockServer::start();
+        mock_zone(&server);
+        mock_records(
+            &server,
+            "A",
+            json!([{ "id": "rec1", "content": "203.0.113.7", "proxied": false, "ttl": 1 }]),
+        );
+        let patch = server.mock(|when, then| {
+            when.method(PATCH)
+                .path("/zones/zone1/dns_records/rec1")
+                .json_body(json!({ "content": "203.0.113.7", "ttl": 300, "proxied": false }));
+            then.status(200)
+                .json_body(json!({ "success": true, "errors": [], "result": {} }));
+        });
+
+        let notifier = notifier(&server).with_ttl(300).with_proxied(false);
+        assert!(update(&notifier, ip()).is_ok());
+        patch.assert();
+    }
+
+    #[test]
+    fn test_creates_missing_aaaa_record() {
+        let server = MockServer::start();
+        mock_zone(&server);
+        mock_records(&server, "AAAA", json!([]));
+        let post = server.mock(|when, then| {
+            when.method(POST)
+                .path("/zones/zone1/dns_records")
+                .json_body(json!({
+                    "type": "AAAA",
+                    "name": "home.example.com",
+                    "content": "2001:db8::7",
+                    "ttl": 1,
+                    "proxied": false
+                }));
+            then.status(200)
+                .json_body(json!({ "success": true, "errors": [], "result": {} }));
+        });
+
+        let notifier = notifier(&server).with_create_missing(true);
+        let ipv6 = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 7));
+        assert!(update(&notifier, ipv6).is_ok());
+        post.assert();
+    }
+
+    #[test]
+    fn test_missing_record_without_create_is_an_error() {
+        let server = MockServer::start();
+        mock_zone(&server);
+        mock_records(&server, "A", json!([]));
+
+        assert!(update(&notifier(&server), ip()).is_err());
+    }
+
+    #[test]
+    fn test_api_errors_are_reported() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/zones");
+            then.status(403).json_body(json!({
+                "success": false,
+                "errors": [{ "code": 9109, "message": "Invalid access token" }],
+                "result": null
+            }));
+        });
+
+        let err = update(&notifier(&server), ip()).unwrap_err();
+        assert!(err.to_string().contains("Invalid access token"));
+    }
+}
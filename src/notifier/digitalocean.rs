@@ -0,0 +1,346 @@
+use async_trait::async_trait;
+use reqwest::{Client, RequestBuilder, Response};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::{
+    config_file::SecretRef,
+    dns::{DnsProvider, DnsRecord, RecordType},
+    errors::{ErrorReason, IpError},
+    secret::resolve_secret,
+};
+
+pub const DIGITALOCEAN_API_BASE_URL: &str = "https://api.digitalocean.com/v2";
+
+const PAGE_SIZE: &str = "200";
+
+#[derive(Debug, Deserialize)]
+struct RecordsPage {
+    domain_records: Vec<DomainRecord>,
+    #[serde(default)]
+    links: Links,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Links {
+    #[serde(default)]
+    pages: Pages,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct Pages {
+    next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DomainRecord {
+    id: u64,
+    data: String,
+    ttl: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    message: String,
+}
+
+pub struct DigitalOceanProvider {
+    token: SecretRef,
+    domain: String,
+    api_base_url: String,
+    client: Client,
+}
+
+impl DigitalOceanProvider {
+    pub fn new(token: SecretRef, domain: String) -> DigitalOceanProvider {
+        DigitalOceanProvider {
+            token,
+            domain: domain.trim_end_matches('.').to_string(),
+            api_base_url: DIGITALOCEAN_API_BASE_URL.to_string(),
+            client: Client::builder().build().unwrap(),
+        }
+    }
+
+    pub fn with_api_base_url(mut self, api_base_url: String) -> DigitalOceanProvider {
+        self.api_base_url = api_base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    fn records_url(&self) -> String {
+        format!("{}/domains/{}/records", self.api_base_url, self.domain)
+    }
+
+    // DigitalOcean names records relative to the domain, with `@` for the domain itself.
+    fn relative_name(&self, record_name: &str) -> String {
+        let record_name = record_name.trim_end_matches('.');
+
+        if record_name == self.domain {
+            "@".to_string()
+        } else {
+            record_name
+                .strip_suffix(&format!(".{}", self.domain))
+                .unwrap_or(record_name)
+                .to_string()
+        }
+    }
+
+    // The `name` filter of the listing on the other hand only takes fully qualified names.
+    fn absolute_name(&self, record_name: &str) -> String {
+        match self.relative_name(record_name).as_str() {
+            "@" => self.domain.clone(),
+            relative => format!("{}.{}", relative, self.domain),
+        }
+    }
+}
+
+#[async_trait]
+impl DnsProvider for DigitalOceanProvider {
+    async fn get_records(
+        &self,
+        name: &str,
+        record_type: RecordType,
+    ) -> Result<Vec<DnsRecord>, IpError> {
+        let token = resolve_secret(&self.token)?;
+        let mut records = Vec::new();
+        let mut request = self.client.get(self.records_url()).query(&[
+            ("type", record_type.as_str()),
+            ("name", self.absolute_name(name).as_str()),
+            ("per_page", PAGE_SIZE),
+        ]);
+
+        loop {
+            let page: RecordsPage = send(request, &token)
+                .await?
+                .json()
+                .await
+                .map_err(|e| dns_error(e.to_string()))?;
+            records.extend(page.domain_records.into_iter().map(|record| DnsRecord {
+                id: Some(record.id.to_string()),
+                name: name.to_string(),
+                record_type,
+                value: record.data,
+                ttl: Some(record.ttl),
+            }));
+
+            match page.links.pages.next {
+                // The next link already carries the filters, page and page size.
+                Some(next) => request = self.client.get(next),
+                None => return Ok(records),
+            }
+        }
+    }
+
+    async fn upsert_record(&self, record: &DnsRecord) -> Result<(), IpError> {
+        let token = resolve_secret(&self.token)?;
+        let mut body = Map::new();
+        body.insert("data".to_string(), json!(record.value));
+        if let Some(ttl) = record.ttl {
+            body.insert("ttl".to_string(), json!(ttl));
+        }
+
+        let request = match &record.id {
+            Some(id) => self.client.patch(format!("{}/{}", self.records_url(), id)),
+            None => {
+                body.insert("type".to_string(), json!(record.record_type.as_str()));
+                body.insert("name".to_string(), json!(self.relative_name(&record.name)));
+                self.client.post(self.records_url())
+            }
+        };
+        send(request.json(&Value::Object(body)), &token).await?;
+        Ok(())
+    }
+
+    async fn delete_record(&self, record: &DnsRecord) -> Result<(), IpError> {
+        let token = resolve_secret(&self.token)?;
+        let id = record
+            .id
+            .as_ref()
+            .ok_or_else(|| dns_error(format!("{} has no record id", record.name)))?;
+
+        send(
+            self.client.delete(format!("{}/{}", self.records_url(), id)),
+            &token,
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+async fn send(request: RequestBuilder, token: &str) -> Result<Response, IpError> {
+    let response = request
+        .bearer_auth(token)
+        .send()
+        .await
+        .map_err(|e| dns_error(e.to_string()))?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    let reason = serde_json::from_str::<ApiError>(&body)
+        .map(|error| error.message)
+        .unwrap_or(body);
+    Err(dns_error(format!(
+        "DigitalOcean returned {}: {}",
+        status, reason
+    )))
+}
+
+fn dns_error(context: String) -> IpError {
+    IpError::new(ErrorReason::DnsUpdateFailed(context))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use httpmock::{
+        Method::{DELETE, GET, PATCH, POST},
+        MockServer,
+    };
+
+    use super::*;
+    use crate::notifier::DnsNotifier;
+
+    fn ip() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))
+    }
+
+    fn notifier(server: &MockServer, record_names: Vec<&str>) -> DnsNotifier<DigitalOceanProvider> {
+        let provider = DigitalOceanProvider::new(
+            SecretRef::Value("token".to_string()),
+            "example.com".to_string(),
+        )
+        .with_api_base_url(server.base_url());
+
+        DnsNotifier::new(
+            provider,
+            record_names.into_iter().map(String::from).collect(),
+        )
+        .with_verify(false)
+    }
+
+    fn update(notifier: &DnsNotifier<DigitalOceanProvider>) -> Result<(), IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.update_records(ip()))
+    }
+
+    fn record(id: u64, data: &str) -> Value {
+        json!({ "id": id, "type": "A", "name": "home", "data": data, "ttl": 1800 })
+    }
+
+    #[test]
+    fn test_relative_and_absolute_names() {
+        let provider = DigitalOceanProvider::new(
+            SecretRef::Value("token".to_string()),
+            "example.com.".to_string(),
+        );
+
+        assert_eq!(provider.relative_name("example.com"), "@");
+        assert_eq!(provider.relative_name("home.example.com."), "home");
+        assert_eq!(provider.relative_name("home"), "home");
+        assert_eq!(provider.absolute_name("@"), "example.com");
+        assert_eq!(provider.absolute_name("home"), "home.example.com");
+        assert_eq!(
+            provider.absolute_name("home.example.com"),
+            "home.example.com"
+        );
+    }
+
+    #[test]
+    fn test_follows_pages_and_updates_records() {
+        let server = MockServer::start();
+        let next_page = server
+            .url("/domains/example.com/records?type=A&name=home.example.com&page=2&per_page=200");
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/domains/example.com/records")
+                .query_param("type", "A")
+                .query_param("name", "home.example.com")
+                .query_param("per_page", "200")
+                .header("Authorization", "Bearer token")
+                .matches(|req| {
+                    !req.query_params
+                        .iter()
+                        .flatten()
+                        .any(|(name, _)| name == "page")
+                });
+            then.status(200).json_body(json!({
+                "domain_records": [record(1, "198.51.100.1")],
+                "links": { "pages": { "next": next_page } },
+                "meta": { "total": 2 }
+            }));
+        });
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/domains/example.com/records")
+                .query_param("page", "2");
+            then.status(200).json_body(json!({
+                "domain_records": [record(2, "198.51.100.2")],
+                "links": {},
+                "meta": { "total": 2 }
+            }));
+        });
+        let patch = server.mock(|when, then| {
+            when.method(PATCH)
+                .path("/domains/example.com/records/1")
+                .json_body(json!({ "data": "203.0.113.7", "ttl": 1800 }));
+            then.status(200).json_body(json!({}));
+        });
+        let delete = server.mock(|when, then| {
+            when.method(DELETE).path("/domains/example.com/records/2");
+            then.status(204);
+        });
+
+        assert!(update(&notifier(&server, vec!["home"]).with_remove_others(true)).is_ok());
+        patch.assert();
+        delete.assert();
+    }
+
+    #[test]
+    fn test_creates_missing_records() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/domains/example.com/records")
+                .query_param("name", "example.com");
+            then.status(200)
+                .json_body(json!({ "domain_records": [], "links": {} }));
+        });
+        let post = server.mock(|when, then| {
+            when.method(POST)
+                .path("/domains/example.com/records")
+                .json_body(json!({
+                    "type": "A",
+                    "name": "@",
+                    "data": "203.0.113.7",
+                    "ttl": 300
+                }));
+            then.status(201).json_body(json!({}));
+        });
+
+        let creating = notifier(&server, vec!["example.com"])
+            .with_create_missing(true)
+            .with_ttl(300);
+        assert!(update(&creating).is_ok());
+        post.assert();
+    }
+
+    #[test]
+    fn test_api_errors_are_reported() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET);
+            then.status(401).json_body(json!({
+                "id": "unauthorized",
+                "message": "Unable to authenticate you"
+            }));
+        });
+
+        let err = update(&notifier(&server, vec!["home"])).unwrap_err();
+        assert!(err.to_string().contains("Unable to authenticate you"));
+    }
+}
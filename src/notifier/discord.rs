@@ -0,0 +1,303 @@
+use std::{net::IpAddr, time::Duration};
+
+use log::{debug, warn};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::time::sleep;
+
+use crate::{
+    errors::{ErrorReason, IpError},
+    ip_event::{IpEvent, IpEventType},
+    notifier::{message, IpNotifier},
+};
+
+// Waits longer than this are reported instead of sat out, since the next run isn't far off.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+// Used when Discord's `retry_after` isn't a usable number of seconds.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Deserialize)]
+struct RateLimited {
+    retry_after: f64,
+}
+
+pub struct DiscordNotifier {
+    webhook_url: String,
+    mention_users: Vec<String>,
+    mention_roles: Vec<String>,
+    max_retries: u32,
+    client: Client,
+}
+
+impl DiscordNotifier {
+    pub fn new(webhook_url: String) -> DiscordNotifier {
+        DiscordNotifier {
+            webhook_url,
+            mention_users: vec![],
+            mention_roles: vec![],
+            max_retries: 3,
+            client: Client::builder().build().unwrap(),
+        }
+    }
+
+    // Users and roles, by ID, that get pinged for error events only.
+    pub fn with_mentions(
+        mut self,
+        mention_users: Vec<String>,
+        mention_roles: Vec<String>,
+    ) -> DiscordNotifier {
+        self.mention_users = mention_users;
+        self.mention_roles = mention_roles;
+        self
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> DiscordNotifier {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn color(event_type: IpEventType) -> u32 {
+        match event_type {
+            IpEventType::IpChanged => 0x2ecc71,
+            IpEventType::IpUnchanged => 0x95a5a6,
+            IpEventType::IpConflict => 0xe67e22,
+            IpEventType::QueryFailed => 0xe74c3c,
+            IpEventType::DnsDrift => 0xf1c40f,
+        }
+    }
+
+    pub fn payload(&self, event: &IpEvent) -> Value {
+        let fields: Vec<Value> = message::facts(event)
+            .into_iter()
+            .filter(|(label, _)| *label != "Time")
+            .map(|(label, value)| json!({ "name": label, "value": value, "inline": true }))
+            .collect();
+        let mut embed = json!({
+            "title": message::title(event),
+            "color": DiscordNotifier::color(event.event_type),
+            "fields": fields,
+            "timestamp": message::timestamp(event),
+        });
+        if let Some(details) = &event.message {
+            embed["description"] = json!(format!("```\n{}\n```", details));
+        }
+
+        let mut payload = json!({ "embeds": [embed] });
+        // Nothing outside the configured mentions may ping anyone, whatever the text contains.
+        if event.event_type.is_error() {
+            let mentions: Vec<String> = self
+                .mention_users
+                .iter()
+                .map(|id| format!("<@{}>", id))
+                .chain(self.mention_roles.iter().map(|id| format!("<@&{}>", id)))
+                .collect();
+            if !mentions.is_empty() {
+                payload["content"] = json!(mentions.join(" "));
+            }
+            payload["allowed_mentions"] = json!({
+                "parse": [],
+                "users": self.mention_users,
+                "roles": self.mention_roles,
+            });
+        } else {
+            payload["allowed_mentions"] = json!({ "parse": [] });
+        }
+        payload
+    }
+
+    async fn post(&self, event: &IpEvent) -> Result<(), IpError> {
+        let payload = self.payload(event);
+        let mut attempt = 0;
+
+        loop {
+            let response = self
+                .client
+                .post(&self.webhook_url)
+                .json(&payload)
+                .send()
+                .await
+                .map_err(|e| message_error(e.to_string()))?;
+
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            if status.is_success() {
+                return Ok(());
+            }
+            if status != StatusCode::TOO_MANY_REQUESTS {
+                return Err(message_error(format!(
+                    "Discord returned {}: {}",
+                    status, body
+                )));
+            }
+
+            let seconds = serde_json::from_str::<RateLimited>(&body)
+                .map(|limited| limited.retry_after)
+                .map_err(|_| message_error(format!("Discord rate limited us: {}", body)))?;
+            let retry_after = retry_delay(seconds);
+            if attempt >= self.max_retries || retry_after > MAX_RETRY_AFTER {
+                return Err(message_error(format!(
+                    "Discord rate limited us for {:.1}s",
+                    seconds
+                )));
+            }
+            attempt += 1;
+            warn!(
+                "Discord rate limited us, retrying in {:.1}s.",
+                retry_after.as_secs_f64()
+            );
+            sleep(retry_after).await;
+        }
+    }
+}
+
+// Anything past `MAX_RETRY_AFTER` is reported rather than waited for, so longer waits are capped
+// just above it instead of overflowing `Duration`.
+fn retry_delay(seconds: f64) -> Duration {
+    if !seconds.is_finite() || seconds < 0.0 {
+        return DEFAULT_RETRY_AFTER;
+    }
+    Duration::from_secs_f64(seconds.min(MAX_RETRY_AFTER.as_secs_f64() + 1.0))
+}
+
+fn message_error(context: String) -> IpError {
+    IpError::new(ErrorReason::MessageFailed(context))
+}
+
+impl IpNotifier for DiscordNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        self.notify_event(&IpEvent::new(ip, None));
+    }
+
+    fn notify_event(&self, event: &IpEvent) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        match runtime.block_on(self.post(event)) {
+            Ok(()) => debug!("Posted the {} event to Discord.", event.event_type),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use httpmock::{Method::POST, MockServer};
+
+    use super::*;
+
+    fn post(notifier: &DiscordNotifier, event: &IpEvent) -> Result<(), IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.post(event))
+    }
+
+    #[test]
+    fn test_change_embed() {
+        let notifier = DiscordNotifier::new("http://localhost".to_string())
+            .with_mentions(vec!["80351110224678912".to_string()], vec![]);
+        let payload = notifier.payload(&IpEvent::sample_changed());
+        let embed = &payload["embeds"][0];
+
+        assert_eq!(embed["color"], 0x2ecc71);
+        assert_eq!(embed["fields"][1]["name"], "New IP");
+        assert_eq!(embed["fields"][1]["value"], "203.0.113.7");
+        assert_eq!(embed["fields"][2]["value"], "198.51.100.1");
+        assert!(embed["timestamp"].is_string());
+        assert!(payload.get("content").is_none());
+        assert_eq!(payload["allowed_mentions"], json!({ "parse": [] }));
+    }
+
+    #[test]
+    fn test_error_embed_mentions() {
+        let notifier = DiscordNotifier::new("http://localhost".to_string()).with_mentions(
+            vec!["80351110224678912".to_string()],
+            vec!["41771983423143936".to_string()],
+        );
+        let payload = notifier.payload(&IpEvent::sample_failed());
+
+        assert_eq!(payload["embeds"][0]["color"], 0xe74c3c);
+        assert!(payload["embeds"][0]["description"]
+            .as_str()
+            .unwrap()
+            .contains("garbage"));
+        assert_eq!(
+            payload["content"],
+            "<@80351110224678912> <@&41771983423143936>"
+        );
+        assert_eq!(
+            payload["allowed_mentions"]["users"],
+            json!(["80351110224678912"])
+        );
+    }
+
+    #[test]
+    fn test_posts_to_webhook() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/api/webhooks/1/token")
+                .body_contains(r#""embeds":["#);
+            then.status(204);
+        });
+        let notifier = DiscordNotifier::new(server.url("/api/webhooks/1/token"));
+
+        assert!(post(&notifier, &IpEvent::sample_changed()).is_ok());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_retries_when_rate_limited() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/api/webhooks/1/token");
+            then.status(429)
+                .json_body(json!({ "message": "You are being rate limited.", "retry_after": 0.01, "global": false }));
+        });
+        let notifier =
+            DiscordNotifier::new(server.url("/api/webhooks/1/token")).with_max_retries(2);
+
+        let err = post(&notifier, &IpEvent::sample_changed()).unwrap_err();
+        assert!(err.to_string().contains("rate limited"));
+        mock.assert_hits(3);
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(0.25), Duration::from_millis(250));
+        assert_eq!(retry_delay(f64::NAN), DEFAULT_RETRY_AFTER);
+        assert_eq!(retry_delay(f64::INFINITY), DEFAULT_RETRY_AFTER);
+        assert_eq!(retry_delay(-3.0), DEFAULT_RETRY_AFTER);
+        assert!(retry_delay(1e300) > MAX_RETRY_AFTER);
+        assert!(retry_delay(f64::MAX) < MAX_RETRY_AFTER * 2);
+    }
+
+    #[test]
+    fn test_long_rate_limits_are_not_waited_for() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/api/webhooks/1/token");
+            then.status(429)
+                .json_body(json!({ "message": "You are being rate limited.", "retry_after": 3600.0, "global": true }));
+        });
+        let notifier = DiscordNotifier::new(server.url("/api/webhooks/1/token"));
+
+        assert!(post(&notifier, &IpEvent::sample_changed()).is_err());
+        mock.assert_hits(1);
+    }
+
+    #[test]
+    fn test_absurd_rate_limits_are_not_waited_for() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/api/webhooks/1/token");
+            then.status(429)
+                .body(r#"{ "message": "You are being rate limited.", "retry_after": 1e300, "global": true }"#);
+        });
+        let notifier = DiscordNotifier::new(server.url("/api/webhooks/1/token"));
+
+        assert!(post(&notifier, &IpEvent::sample_changed()).is_err());
+        mock.assert_hits(1);
+    }
+}
@@ -0,0 +1,405 @@
+use std::net::IpAddr;
+
+use log::{debug, info};
+
+use crate::{
+    dns::{DnsProvider, DnsRecord, RecordType},
+    errors::{ErrorReason, IpError},
+    notifier::IpNotifier,
+};
+
+// Keeps one A or AAAA record of each of `record_names` pointed at the current IP through any
+// `DnsProvider`, leaving it alone when it already is. Other records for the same name are only
+// touched when asked to remove them.
+pub struct DnsNotifier<P> {
+    provider: P,
+    record_names: Vec<String>,
+    ttl: Option<u32>,
+    create_missing: bool,
+    remove_others: bool,
+    verify: bool,
+}
+
+impl<P: DnsProvider> DnsNotifier<P> {
+    pub fn new(provider: P, record_names: Vec<String>) -> DnsNotifier<P> {
+        DnsNotifier {
+            provider,
+            record_names,
+            ttl: None,
+            create_missing: false,
+            remove_others: false,
+            verify: true,
+        }
+    }
+
+    // Without a TTL the one already on the record is kept.
+    pub fn with_ttl(mut self, ttl: u32) -> DnsNotifier<P> {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_create_missing(mut self, create_missing: bool) -> DnsNotifier<P> {
+        self.create_missing = create_missing;
+        self
+    }
+
+    // Deletes the records of a name other than the one pointed at the IP, for names that should
+    // only ever resolve to this host.
+    pub fn with_remove_others(mut self, remove_others: bool) -> DnsNotifier<P> {
+        self.remove_others = remove_others;
+        self
+    }
+
+    // Reads every updated record back to check the provider really changed it.
+    pub fn with_verify(mut self, verify: bool) -> DnsNotifier<P> {
+        self.verify = verify;
+        self
+    }
+
+    pub fn provider(&self) -> &P {
+        &self.provider
+    }
+
+    pub async fn update_records(&self, ip: IpAddr) -> Result<(), IpError> {
+        let mut failures = Vec::new();
+        for record_name in self.record_names.iter() {
+            if let Err(err) = self.update_record(record_name, ip).await {
+                failures.push(match err.reason() {
+                    ErrorReason::DnsUpdateFailed(context) => context.clone(),
+                    _ => err.to_string(),
+                });
+            }
+        }
+
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(dns_error(failures.join("; ")))
+        }
+    }
+
+    async fn update_record(&self, name: &str, ip: IpAddr) -> Result<(), IpError> {
+        let record_type = RecordType::for_ip(ip);
+        let value = ip.to_string();
+        let records = self.provider.get_records(name, record_type).await?;
+
+        let is_current = |record: &DnsRecord| {
+            record.value == value && (self.ttl.is_none() || self.ttl == record.ttl)
+        };
+
+        // The record already holding the IP is the one managed, or else the first one there is.
+        let kept = records
+            .iter()
+            .find(|record| is_current(record))
+            .or_else(|| records.first());
+        let others: Vec<&DnsRecord> = records
+            .iter()
+            .filter(|record| self.remove_others && Some(*record) != kept)
+            .collect();
+        if matches!(kept, Some(record) if is_current(record)) && others.is_empty() {
+            debug!(
+                "{} {} already points at {}, skipping.",
+                record_type, name, ip
+            );
+            return Ok(());
+        }
+        if kept.is_none() && !self.create_missing {
+            return Err(dns_error(format!(
+                "there is no {} record for {}",
+                record_type, name
+            )));
+        }
+
+        let needs_upsert = !matches!(kept, Some(record) if is_current(record));
+        let updated = DnsRecord {
+            id: kept.and_then(|record| record.id.clone()),
+            name: name.to_string(),
+            record_type,
+            value,
+            ttl: self.ttl.or_else(|| kept.and_then(|record| record.ttl)),
+        };
+        if needs_upsert {
+            self.provider.upsert_record(&updated).await?;
+        }
+        for record in others {
+            self.provider.delete_record(record).await?;
+        }
+
+        if self.verify {
+            self.verify_record(&updated).await?;
+        }
+        info!("Updated {} {} to {}.", record_type, name, ip);
+        Ok(())
+    }
+
+    async fn verify_record(&self, expected: &DnsRecord) -> Result<(), IpError> {
+        let records = self
+            .provider
+            .get_records(&expected.name, expected.record_type)
+            .await?;
+
+        let points_at_ip = records.iter().any(|record| record.value == expected.value);
+        if points_at_ip && (!self.remove_others || records.len() == 1) {
+            return Ok(());
+        }
+
+        Err(dns_error(format!(
+            "{} {} still doesn't point at {}{} after the update",
+            expected.record_type,
+            expected.name,
+            if self.remove_others { "only " } else { "" },
+            expected.value
+        )))
+    }
+}
+
+fn dns_error(context: String) -> IpError {
+    IpError::new(ErrorReason::DnsUpdateFailed(context))
+}
+
+impl<P: DnsProvider> IpNotifier for DnsNotifier<P> {
+    fn notify_success(&self, ip: IpAddr) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        if let Err(err) = runtime.block_on(self.update_records(ip)) {
+            IpNotifier::notify_error(self, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, Ipv6Addr},
+        sync::Mutex,
+    };
+
+    use async_trait::async_trait;
+
+    use super::*;
+
+    // Keeps records in memory and remembers every change made to them.
+    #[derive(Default)]
+    struct MemoryProvider {
+        records: Mutex<Vec<DnsRecord>>,
+        changes: Mutex<Vec<String>>,
+        ignore_updates: bool,
+    }
+
+    impl MemoryProvider {
+        fn with_records(records: Vec<(&str, RecordType, &str)>) -> MemoryProvider {
+            let provider = MemoryProvider::default();
+            *provider.records.lock().unwrap() = records
+                .into_iter()
+                .enumerate()
+                .map(|(id, (name, record_type, value))| DnsRecord {
+                    id: Some(id.to_string()),
+                    name: name.to_string(),
+                    record_type,
+                    value: value.to_string(),
+                    ttl: Some(300),
+                })
+                .collect();
+            provider
+        }
+
+        fn changes(&self) -> Vec<String> {
+            self.changes.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl DnsProvider for MemoryProvider {
+        async fn get_records(
+            &self,
+            name: &str,
+            record_type: RecordType,
+        ) -> Result<Vec<DnsRecord>, IpError> {
+            Ok(self
+                .records
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|r| r.name == name && r.record_type == record_type)
+                .cloned()
+                .collect())
+        }
+
+        async fn upsert_record(&self, record: &DnsRecord) -> Result<(), IpError> {
+            let mut records = self.records.lock().unwrap();
+            self.changes.lock().unwrap().push(format!(
+                "upsert {:?} {} {}",
+                record.id, record.name, record.value
+            ));
+            if self.ignore_updates {
+                return Ok(());
+            }
+
+            match records
+                .iter_mut()
+                .find(|r| r.id.is_some() && r.id == record.id)
+            {
+                Some(existing) => *existing = record.clone(),
+                None => {
+                    let id = records.len().to_string();
+                    records.push(DnsRecord {
+                        id: Some(id),
+                        ..record.clone()
+                    });
+                }
+            }
+            Ok(())
+        }
+
+        async fn delete_record(&self, record: &DnsRecord) -> Result<(), IpError> {
+            self.changes
+                .lock()
+                .unwrap()
+                .push(format!("delete {:?}", record.id));
+            self.records.lock().unwrap().retain(|r| r.id != record.id);
+            Ok(())
+        }
+    }
+
+    fn update(notifier: &DnsNotifier<MemoryProvider>, ip: IpAddr) -> Result<(), IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.update_records(ip))
+    }
+
+    fn ipv4() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))
+    }
+
+    #[test]
+    fn test_updates_only_stale_records() {
+        let provider = MemoryProvider::with_records(vec![
+            ("home.example.com", RecordType::A, "198.51.100.1"),
+            ("vpn.example.com", RecordType::A, "203.0.113.7"),
+            ("home.example.com", RecordType::Aaaa, "2001:db8::1"),
+        ]);
+        let notifier = DnsNotifier::new(
+            provider,
+            vec![
+                "home.example.com".to_string(),
+                "vpn.example.com".to_string(),
+            ],
+        );
+
+        assert!(update(&notifier, ipv4()).is_ok());
+        assert_eq!(
+            notifier.provider().changes(),
+            vec!["upsert Some(\"0\") home.example.com 203.0.113.7"]
+        );
+        // The TTL already on the record is kept and the AAAA record is left alone.
+        let records = notifier.provider().records.lock().unwrap().clone();
+        assert_eq!(records[0].ttl, Some(300));
+        assert_eq!(records[2].value, "2001:db8::1");
+    }
+
+    #[test]
+    fn test_uses_aaaa_records_for_ipv6() {
+        let provider = MemoryProvider::with_records(vec![
+            ("home.example.com", RecordType::A, "198.51.100.1"),
+            ("home.example.com", RecordType::Aaaa, "2001:db8::1"),
+        ]);
+        let notifier = DnsNotifier::new(provider, vec!["home.example.com".to_string()]);
+
+        assert!(update(
+            &notifier,
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 7))
+        )
+        .is_ok());
+        assert_eq!(
+            notifier.provider().changes(),
+            vec!["upsert Some(\"1\") home.example.com 2001:db8::7"]
+        );
+    }
+
+    #[test]
+    fn test_changed_ttl_is_an_update() {
+        let provider =
+            MemoryProvider::with_records(vec![("home.example.com", RecordType::A, "203.0.113.7")]);
+        let notifier =
+            DnsNotifier::new(provider, vec!["home.example.com".to_string()]).with_ttl(60);
+
+        assert!(update(&notifier, ipv4()).is_ok());
+        assert_eq!(notifier.provider().records.lock().unwrap()[0].ttl, Some(60));
+    }
+
+    #[test]
+    fn test_missing_records_are_created_only_when_asked() {
+        let notifier = DnsNotifier::new(
+            MemoryProvider::default(),
+            vec!["home.example.com".to_string()],
+        );
+        assert!(update(&notifier, ipv4()).is_err());
+        assert!(notifier.provider().changes().is_empty());
+
+        let notifier = notifier.with_create_missing(true).with_ttl(60);
+        assert!(update(&notifier, ipv4()).is_ok());
+        assert_eq!(
+            notifier.provider().changes(),
+            vec!["upsert None home.example.com 203.0.113.7"]
+        );
+    }
+
+    #[test]
+    fn test_other_records_are_left_alone() {
+        let provider = MemoryProvider::with_records(vec![
+            ("home.example.com", RecordType::A, "198.51.100.1"),
+            ("home.example.com", RecordType::A, "198.51.100.2"),
+            ("home.example.com", RecordType::A, "203.0.113.7"),
+        ]);
+        let notifier = DnsNotifier::new(provider, vec!["home.example.com".to_string()]);
+
+        assert!(update(&notifier, ipv4()).is_ok());
+        assert!(notifier.provider().changes().is_empty());
+
+        let provider = MemoryProvider::with_records(vec![
+            ("home.example.com", RecordType::A, "198.51.100.1"),
+            ("home.example.com", RecordType::A, "198.51.100.2"),
+        ]);
+        let notifier = DnsNotifier::new(provider, vec!["home.example.com".to_string()]);
+
+        assert!(update(&notifier, ipv4()).is_ok());
+        assert_eq!(
+            notifier.provider().changes(),
+            vec!["upsert Some(\"0\") home.example.com 203.0.113.7"]
+        );
+        assert_eq!(
+            notifier.provider().records.lock().unwrap()[1].value,
+            "198.51.100.2"
+        );
+    }
+
+    #[test]
+    fn test_other_records_are_removed_when_asked() {
+        let provider = MemoryProvider::with_records(vec![
+            ("home.example.com", RecordType::A, "198.51.100.1"),
+            ("home.example.com", RecordType::A, "203.0.113.7"),
+        ]);
+        let notifier = DnsNotifier::new(provider, vec!["home.example.com".to_string()])
+            .with_remove_others(true);
+
+        assert!(update(&notifier, ipv4()).is_ok());
+        assert_eq!(notifier.provider().changes(), vec!["delete Some(\"0\")"]);
+    }
+
+    #[test]
+    fn test_unapplied_updates_fail_verification() {
+        let provider = MemoryProvider {
+            ignore_updates: true,
+            ..MemoryProvider::with_records(vec![(
+                "home.example.com",
+                RecordType::A,
+                "198.51.100.1",
+            )])
+        };
+        let notifier = DnsNotifier::new(provider, vec!["home.example.com".to_string()]);
+
+        let err = update(&notifier, ipv4()).unwrap_err();
+        assert!(err.to_string().contains("still doesn't point at"));
+        assert!(update(&notifier.with_verify(false), ipv4()).is_ok());
+    }
+}
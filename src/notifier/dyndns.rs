@@ -0,0 +1,423 @@
+use std::{net::IpAddr, sync::Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, error, info};
+use reqwest::{header::USER_AGENT, Client};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config_file::SecretRef,
+    errors::{ErrorReason, IpError},
+    notifier::IpNotifier,
+    secret::resolve_secret,
+    state::StateFile,
+};
+
+// The protocol asks clients to back off for at least half an hour after a server side error.
+const RETRY_AFTER_MINUTES: i64 = 30;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DynDnsAnswer {
+    Good,
+    NoChange,
+    // The request can't succeed without a change on our side and repeating it can get the
+    // account blocked.
+    Fatal(String),
+    // A problem on the provider's side, worth trying again later.
+    Retry(String),
+}
+
+impl DynDnsAnswer {
+    pub fn parse(line: &str) -> DynDnsAnswer {
+        let code = line.split_whitespace().next().unwrap_or_default();
+        match code {
+            "good" => DynDnsAnswer::Good,
+            "nochg" => DynDnsAnswer::NoChange,
+            "badauth" | "!donator" | "notfqdn" | "nohost" | "numhost" | "abuse" | "badagent"
+            | "!yours" => DynDnsAnswer::Fatal(code.to_string()),
+            _ => DynDnsAnswer::Retry(line.trim().to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DynDnsState {
+    disabled_because: Option<String>,
+    retry_after: Option<DateTime<Utc>>,
+}
+
+pub struct DynDnsNotifier {
+    url: String,
+    username: String,
+    password: SecretRef,
+    hostnames: Vec<String>,
+    client: Client,
+    state: Mutex<DynDnsState>,
+    state_file: Option<StateFile>,
+}
+
+impl DynDnsNotifier {
+    pub fn new(
+        url: String,
+        username: String,
+        password: SecretRef,
+        hostnames: Vec<String>,
+    ) -> DynDnsNotifier {
+        DynDnsNotifier {
+            url,
+            username,
+            password,
+            hostnames,
+            client: Client::builder().build().unwrap(),
+            state: Mutex::new(DynDnsState::default()),
+            state_file: None,
+        }
+    }
+
+    // Keeps the disabled and backing off state in `state_file` so it outlives the process.
+    pub fn with_state_file(mut self, state_file: StateFile) -> DynDnsNotifier {
+        self.state = Mutex::new(state_file.load());
+        self.state_file = Some(state_file);
+        self
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.state.lock().unwrap().disabled_because.is_some()
+    }
+
+    // Returns whether the update was sent, it is skipped while the notifier is disabled or
+    // backing off.
+    pub async fn update(&self, ip: IpAddr) -> Result<bool, IpError> {
+        {
+            let state = self.state.lock().unwrap();
+            if let Some(reason) = &state.disabled_because {
+                debug!(
+                    "dyndns2 updates are disabled after the server answered {}, skipping.",
+                    reason
+                );
+                return Ok(false);
+            }
+            if matches!(state.retry_after, Some(t) if Utc::now() < t) {
+                debug!("Backing off from the dyndns2 server, skipping.");
+                return Ok(false);
+            }
+        }
+
+        let password = resolve_secret(&self.password)?;
+        let response = self
+            .client
+            .get(&self.url)
+            .query(&[
+                ("hostname", self.hostnames.join(",")),
+                ("myip", ip.to_string()),
+            ])
+            .basic_auth(&self.username, Some(&password))
+            .header(USER_AGENT, format!("findip/{}", env!("CARGO_PKG_VERSION")))
+            .send()
+            .await
+            .map_err(|e| dns_error(e.to_string()))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|e| dns_error(e.to_string()))?;
+
+        // There is one answer per hostname, in the order they were sent, except for answers about
+        // the whole request like `badauth` or `911`, which come once.
+        let answers: Vec<DynDnsAnswer> = body.lines().map(DynDnsAnswer::parse).collect();
+        let answers: Vec<(&String, DynDnsAnswer)> = match answers.as_slice() {
+            [answer @ (DynDnsAnswer::Fatal(_) | DynDnsAnswer::Retry(_))] => self
+                .hostnames
+                .iter()
+                .map(|hostname| (hostname, answer.clone()))
+                .collect(),
+            _ if answers.len() == self.hostnames.len() => {
+                self.hostnames.iter().zip(answers.iter().cloned()).collect()
+            }
+            _ => {
+                return Err(dns_error(format!(
+                    "{} returned {} with {} answers for {} hostnames",
+                    self.url,
+                    status,
+                    answers.len(),
+                    self.hostnames.len()
+                )))
+            }
+        };
+
+        let mut state = self.state.lock().unwrap();
+        let mut failures = Vec::new();
+        for (hostname, answer) in answers {
+            match answer {
+                DynDnsAnswer::Good => info!("Updated {} to {}.", hostname, ip),
+                DynDnsAnswer::NoChange => debug!("{} already points at {}.", hostname, ip),
+                DynDnsAnswer::Fatal(code) => {
+                    error!(
+                        "The dyndns2 server answered {} for {}, no more updates will be sent until its config changes.",
+                        code, hostname
+                    );
+                    state.disabled_because = Some(code.clone());
+                    failures.push(format!("{}: {}", hostname, code));
+                }
+                DynDnsAnswer::Retry(code) => {
+                    state.retry_after = Some(Utc::now() + Duration::minutes(RETRY_AFTER_MINUTES));
+                    failures.push(format!("{}: {}", hostname, code));
+                }
+            }
+        }
+
+        if let Some(err) = self.state_file.as_ref().and_then(|f| f.save(&*state).err()) {
+            error!("{}, the dyndns2 state will be lost when findip exits.", err);
+        }
+
+        if failures.is_empty() {
+            Ok(true)
+        } else {
+            Err(dns_error(failures.join(", ")))
+        }
+    }
+}
+
+fn dns_error(context: String) -> IpError {
+    IpError::new(ErrorReason::DnsUpdateFailed(context))
+}
+
+impl IpNotifier for DynDnsNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        if let Err(err) = runtime.block_on(self.update(ip)) {
+            IpNotifier::notify_error(self, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, net::Ipv4Addr, path::Path};
+
+    use httpmock::{Method::GET, MockServer};
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::config_file::{Notifier, SecretRef};
+
+    fn ip() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))
+    }
+
+    fn notifier(server: &MockServer) -> DynDnsNotifier {
+        DynDnsNotifier::new(
+            server.url("/nic/update"),
+            "user".to_string(),
+            SecretRef::Value("pass".to_string()),
+            vec![
+                "home.example.com".to_string(),
+                "vpn.example.com".to_string(),
+            ],
+        )
+    }
+
+    fn update(notifier: &DynDnsNotifier) -> Result<bool, IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.update(ip()))
+    }
+
+    #[test]
+    fn test_parse_answers() {
+        assert_eq!(DynDnsAnswer::parse("good 203.0.113.7"), DynDnsAnswer::Good);
+        assert_eq!(
+            DynDnsAnswer::parse("nochg 203.0.113.7"),
+            DynDnsAnswer::NoChange
+        );
+        assert_eq!(
+            DynDnsAnswer::parse("badauth"),
+            DynDnsAnswer::Fatal("badauth".to_string())
+        );
+        assert_eq!(
+            DynDnsAnswer::parse("abuse"),
+            DynDnsAnswer::Fatal("abuse".to_string())
+        );
+        assert_eq!(
+            DynDnsAnswer::parse("nohost"),
+            DynDnsAnswer::Fatal("nohost".to_string())
+        );
+        assert_eq!(
+            DynDnsAnswer::parse("911"),
+            DynDnsAnswer::Retry("911".to_string())
+        );
+    }
+
+    #[test]
+    fn test_updates_all_hostnames() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/nic/update")
+                .query_param("hostname", "home.example.com,vpn.example.com")
+                .query_param("myip", "203.0.113.7")
+                .header("Authorization", "Basic dXNlcjpwYXNz");
+            then.status(200)
+                .body("good 203.0.113.7\nnochg 203.0.113.7\n");
+        });
+
+        assert!(update(&notifier(&server)).unwrap());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_fatal_answer_disables_the_notifier() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/nic/update");
+            then.status(401).body("badauth\n");
+        });
+        let notifier = notifier(&server);
+
+        assert!(update(&notifier).is_err());
+        assert!(notifier.is_disabled());
+        assert!(!update(&notifier).unwrap());
+        mock.assert_hits(1);
+    }
+
+    #[test]
+    fn test_missing_answers_are_an_error() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/nic/update");
+            then.status(200).body("good 203.0.113.7\n");
+        });
+        let notifier = notifier(&server);
+
+        let err = update(&notifier).unwrap_err();
+        assert!(err.to_string().contains("1 answers for 2 hostnames"));
+        assert!(!notifier.is_disabled());
+    }
+
+    #[test]
+    fn test_password_is_a_secret_reference() {
+        env::set_var("FINDIP_TEST_DYNDNS_PASSWORD", "pass");
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET)
+                .path("/nic/update")
+                .header("Authorization", "Basic dXNlcjpwYXNz");
+            then.status(200).body("good 203.0.113.7\n");
+        });
+        let notifier = DynDnsNotifier::new(
+            server.url("/nic/update"),
+            "user".to_string(),
+            SecretRef::Env {
+                env: "FINDIP_TEST_DYNDNS_PASSWORD".to_string(),
+            },
+            vec!["home.example.com".to_string()],
+        );
+
+        assert!(update(&notifier).unwrap());
+        mock.assert();
+    }
+
+    fn from_config(config: &Notifier, state_directory: &Path) -> DynDnsNotifier {
+        match config {
+            Notifier::Dyndns2 {
+                url,
+                username,
+                password,
+                hostnames,
+            } => DynDnsNotifier::new(
+                url.clone(),
+                username.clone(),
+                password.clone(),
+                hostnames.clone(),
+            )
+            .with_state_file(StateFile::for_notifier(state_directory, config)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn config(server: &MockServer, hostname: &str) -> Notifier {
+        Notifier::Dyndns2 {
+            url: server.url("/nic/update"),
+            username: "user".to_string(),
+            password: SecretRef::Value("pass".to_string()),
+            hostnames: vec![hostname.to_string()],
+        }
+    }
+
+    #[test]
+    fn test_disabled_state_outlives_the_notifier() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/nic/update");
+            then.status(401).body("badauth\n");
+        });
+        let state_directory = tempdir().unwrap();
+        let config = config(&server, "home.example.com");
+
+        assert!(update(&from_config(&config, state_directory.path())).is_err());
+
+        let next_run = from_config(&config, state_directory.path());
+        assert!(next_run.is_disabled());
+        assert!(!update(&next_run).unwrap());
+        mock.assert_hits(1);
+    }
+
+    #[test]
+    fn test_changed_config_is_enabled_again() {
+        let server = MockServer::start();
+        let state_directory = tempdir().unwrap();
+        let mut mock = server.mock(|when, then| {
+            when.method(GET).path("/nic/update");
+            then.status(401).body("nohost\n");
+        });
+        assert!(update(&from_config(
+            &config(&server, "hoem.example.com"),
+            state_directory.path()
+        ))
+        .is_err());
+        mock.delete();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/nic/update");
+            then.status(200).body("good 203.0.113.7\n");
+        });
+
+        let fixed = from_config(&config(&server, "home.example.com"), state_directory.path());
+
+        assert!(!fixed.is_disabled());
+        assert!(update(&fixed).unwrap());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_backing_off_outlives_the_notifier() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/nic/update");
+            then.status(200).body("dnserr\n");
+        });
+        let state_directory = tempdir().unwrap();
+        let config = config(&server, "home.example.com");
+
+        assert!(update(&from_config(&config, state_directory.path())).is_err());
+
+        assert!(!update(&from_config(&config, state_directory.path())).unwrap());
+        mock.assert_hits(1);
+    }
+
+    #[test]
+    fn test_server_error_backs_off() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(GET).path("/nic/update");
+            then.status(200).body("911\n911\n");
+        });
+        let notifier = notifier(&server);
+
+        assert!(update(&notifier).is_err());
+        assert!(!notifier.is_disabled());
+        assert!(!update(&notifier).unwrap());
+        mock.assert_hits(1);
+    }
+}
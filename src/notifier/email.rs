@@ -0,0 +1,390 @@
+use std::{fs, net::IpAddr, time::Duration};
+
+use lettre::{
+    message::{header::ContentType, Mailbox, MultiPart},
+    transport::smtp::authentication::{Credentials, Mechanism},
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use log::debug;
+
+use crate::{
+    config_file::{SecretRef, SmtpAuthMechanism, SmtpTls},
+    errors::{ErrorReason, IpError},
+    ip_event::IpEvent,
+    notifier::{message, IpNotifier},
+    secret::resolve_secret,
+    utils,
+};
+
+pub struct SmtpCredentials {
+    pub username: SecretRef,
+    pub password: SecretRef,
+    // Whatever the server offers, PLAIN first, when `None`.
+    pub mechanism: Option<SmtpAuthMechanism>,
+}
+
+pub struct EmailNotifier {
+    smtp_host: String,
+    smtp_port: Option<u16>,
+    tls: SmtpTls,
+    credentials: Option<SmtpCredentials>,
+    from: String,
+    to: Vec<String>,
+    subject: Option<String>,
+    text_template_path: Option<String>,
+    html_template_path: Option<String>,
+    timeout: Duration,
+}
+
+impl EmailNotifier {
+    pub fn new(smtp_host: String, from: String, to: Vec<String>) -> EmailNotifier {
+        EmailNotifier {
+            smtp_host,
+            smtp_port: None,
+            tls: SmtpTls::StartTls,
+            credentials: None,
+            from,
+            to,
+            subject: None,
+            text_template_path: None,
+            html_template_path: None,
+            timeout: Duration::from_secs(30),
+        }
+    }
+
+    // Without a port the usual one for `tls` is used: 465, 587 or 25.
+    pub fn with_smtp_port(mut self, smtp_port: u16) -> EmailNotifier {
+        self.smtp_port = Some(smtp_port);
+        self
+    }
+
+    pub fn with_tls(mut self, tls: SmtpTls) -> EmailNotifier {
+        self.tls = tls;
+        self
+    }
+
+    pub fn with_credentials(mut self, credentials: SmtpCredentials) -> EmailNotifier {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    pub fn with_subject(mut self, subject: String) -> EmailNotifier {
+        self.subject = Some(subject);
+        self
+    }
+
+    pub fn with_text_template(mut self, text_template_path: String) -> EmailNotifier {
+        self.text_template_path = Some(text_template_path);
+        self
+    }
+
+    pub fn with_html_template(mut self, html_template_path: String) -> EmailNotifier {
+        self.html_template_path = Some(html_template_path);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> EmailNotifier {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn message(&self, event: &IpEvent) -> Result<Message, IpError> {
+        let subject = match &self.subject {
+            Some(subject) => utils::replace_tokens(subject.clone(), event.token_values()),
+            None => message::title(event),
+        };
+        let text = match &self.text_template_path {
+            Some(path) => utils::replace_tokens(read_template(path)?, event.token_values()),
+            None => message::plain_text(event),
+        };
+
+        let mut builder = Message::builder()
+            .from(parse_mailbox(&self.from)?)
+            .subject(subject);
+        for to in self.to.iter() {
+            builder = builder.to(parse_mailbox(to)?);
+        }
+
+        let built = match &self.html_template_path {
+            Some(path) => {
+                // Values go into markup, so they're escaped like any other text would be.
+                let token_values = event
+                    .token_values()
+                    .into_iter()
+                    .map(|(token, value)| (token, message::escape_html(&value)))
+                    .collect();
+                let html = utils::replace_tokens(read_template(path)?, token_values);
+                builder.multipart(MultiPart::alternative_plain_html(text, html))
+            }
+            None => builder.header(ContentType::TEXT_PLAIN).body(text),
+        };
+        built.map_err(|e| message_error(e.to_string()))
+    }
+
+    fn transport(&self) -> Result<AsyncSmtpTransport<Tokio1Executor>, IpError> {
+        let builder = match self.tls {
+            SmtpTls::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(&self.smtp_host)
+                .map_err(|e| message_error(e.to_string()))?,
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&self.smtp_host)
+                    .map_err(|e| message_error(e.to_string()))?
+            }
+            SmtpTls::None => {
+                AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(self.smtp_host.clone())
+                    .port(25)
+            }
+        };
+        let mut builder = builder.timeout(Some(self.timeout));
+        if let Some(port) = self.smtp_port {
+            builder = builder.port(port);
+        }
+
+        if let Some(credentials) = &self.credentials {
+            builder = builder.credentials(Credentials::new(
+                resolve_secret(&credentials.username)?,
+                resolve_secret(&credentials.password)?,
+            ));
+            builder = match credentials.mechanism {
+                Some(SmtpAuthMechanism::Plain) => builder.authentication(vec![Mechanism::Plain]),
+                Some(SmtpAuthMechanism::Login) => builder.authentication(vec![Mechanism::Login]),
+                None => builder,
+            };
+        }
+        Ok(builder.build())
+    }
+
+    async fn send(&self, event: &IpEvent) -> Result<(), IpError> {
+        let message = self.message(event)?;
+
+        self.transport()?
+            .send(message)
+            .await
+            .map(|_| ())
+            .map_err(|e| message_error(e.to_string()))
+    }
+}
+
+fn read_template(path: &str) -> Result<String, IpError> {
+    fs::read_to_string(path)
+        .map_err(|_| IpError::new(ErrorReason::FileOpenFailed(path.to_string())))
+}
+
+fn parse_mailbox(address: &str) -> Result<Mailbox, IpError> {
+    address.parse().map_err(|e| {
+        IpError::new(ErrorReason::InvalidInput(format!(
+            "{} is not a valid email address: {}",
+            address, e
+        )))
+    })
+}
+
+fn message_error(context: String) -> IpError {
+    IpError::new(ErrorReason::MessageFailed(context))
+}
+
+impl IpNotifier for EmailNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        self.notify_event(&IpEvent::new(ip, None));
+    }
+
+    fn notify_event(&self, event: &IpEvent) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        match runtime.block_on(self.send(event)) {
+            Ok(()) => debug!(
+                "Emailed the {} event to {}.",
+                event.event_type,
+                self.to.join(", ")
+            ),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::{Ipv4Addr, TcpListener},
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    // A local SMTP sink that takes one message and keeps everything the client sent.
+    fn start_sink() -> (u16, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let transcript = Arc::new(Mutex::new(Vec::new()));
+        let lines = transcript.clone();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            writer.write_all(b"220 localhost ESMTP sink\r\n").unwrap();
+
+            let mut in_data = false;
+            let mut login_steps = 0;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 {
+                    break;
+                }
+                let line = line.trim_end_matches("\r\n").to_string();
+                lines.lock().unwrap().push(line.clone());
+
+                let reply: &[u8] = if in_data {
+                    if line != "." {
+                        continue;
+                    }
+                    in_data = false;
+                    b"250 queued\r\n"
+                } else if login_steps > 0 {
+                    login_steps -= 1;
+                    if login_steps == 1 {
+                        b"334 UGFzc3dvcmQ6\r\n"
+                    } else {
+                        b"235 authenticated\r\n"
+                    }
+                } else if line.starts_with("EHLO") {
+                    b"250-localhost\r\n250-AUTH PLAIN LOGIN\r\n250 8BITMIME\r\n"
+                } else if line.starts_with("AUTH PLAIN") {
+                    b"235 authenticated\r\n"
+                } else if line == "AUTH LOGIN" {
+                    login_steps = 2;
+                    b"334 VXNlcm5hbWU6\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 bye\r\n").unwrap();
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                writer.write_all(reply).unwrap();
+            }
+        });
+
+        (port, transcript)
+    }
+
+    fn changed() -> IpEvent {
+        let mut event = IpEvent::sample_changed();
+        event.hostname = "nas".to_string();
+        event
+    }
+
+    fn notifier(port: u16) -> EmailNotifier {
+        EmailNotifier::new(
+            "127.0.0.1".to_string(),
+            "findip <findip@example.com>".to_string(),
+            vec![
+                "ops@example.com".to_string(),
+                "Help Desk <helpdesk@example.com>".to_string(),
+            ],
+        )
+        .with_tls(SmtpTls::None)
+        .with_smtp_port(port)
+        .with_timeout(Duration::from_secs(5))
+    }
+
+    fn send(notifier: &EmailNotifier, event: &IpEvent) -> Result<(), IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.send(event))
+    }
+
+    #[test]
+    fn test_sends_to_every_recipient() {
+        let (port, transcript) = start_sink();
+        let notifier = notifier(port).with_credentials(SmtpCredentials {
+            username: SecretRef::Value("findip".to_string()),
+            password: SecretRef::Value("hunter2".to_string()),
+            mechanism: None,
+        });
+
+        send(&notifier, &changed()).unwrap();
+
+        let transcript = transcript.lock().unwrap().join("\n");
+        // "\0findip\0hunter2"
+        assert!(transcript.contains("AUTH PLAIN AGZpbmRpcABodW50ZXIy"));
+        assert!(transcript.contains("MAIL FROM:<findip@example.com>"));
+        assert!(transcript.contains("RCPT TO:<ops@example.com>"));
+        assert!(transcript.contains("RCPT TO:<helpdesk@example.com>"));
+        assert!(transcript.contains("Subject: Public IP of nas changed"));
+        assert!(transcript.contains("New IP: 203.0.113.7"));
+    }
+
+    #[test]
+    fn test_auth_login() {
+        let (port, transcript) = start_sink();
+        let notifier = notifier(port).with_credentials(SmtpCredentials {
+            username: SecretRef::Value("findip".to_string()),
+            password: SecretRef::Value("hunter2".to_string()),
+            mechanism: Some(SmtpAuthMechanism::Login),
+        });
+
+        send(&notifier, &changed()).unwrap();
+
+        let transcript = transcript.lock().unwrap();
+        let login = transcript
+            .iter()
+            .position(|line| line == "AUTH LOGIN")
+            .unwrap();
+        assert_eq!(transcript[login + 1], "ZmluZGlw");
+        assert_eq!(transcript[login + 2], "aHVudGVyMg==");
+    }
+
+    #[test]
+    fn test_templates() {
+        let dir = tempdir().unwrap();
+        let text_path = dir.path().join("body.txt");
+        let html_path = dir.path().join("body.html");
+        fs::write(&text_path, "Now {{TOKEN_IP_ADDRESS}} on {{TOKEN_HOSTNAME}}").unwrap();
+        fs::write(
+            &html_path,
+            "<p>Now <b>{{TOKEN_IP_ADDRESS}}</b> on {{TOKEN_HOSTNAME}}</p>",
+        )
+        .unwrap();
+        let notifier = notifier(25)
+            .with_subject("[findip] {{TOKEN_HOSTNAME}} is now {{TOKEN_IP_ADDRESS}}".to_string())
+            .with_text_template(text_path.to_str().unwrap().to_string())
+            .with_html_template(html_path.to_str().unwrap().to_string());
+        let mut event = changed();
+        event.hostname = "<nas>".to_string();
+
+        let formatted = String::from_utf8(notifier.message(&event).unwrap().formatted()).unwrap();
+        assert!(formatted.contains("Subject: [findip] <nas> is now 203.0.113.7"));
+        assert!(formatted.contains("multipart/alternative"));
+        assert!(formatted.contains("Now 203.0.113.7 on <nas>"));
+        assert!(formatted.contains("<p>Now <b>203.0.113.7</b> on &lt;nas&gt;</p>"));
+    }
+
+    #[test]
+    fn test_invalid_address_is_an_error() {
+        let notifier = EmailNotifier::new(
+            "127.0.0.1".to_string(),
+            "findip@example.com".to_string(),
+            vec!["not an address".to_string()],
+        );
+
+        assert!(notifier.message(&changed()).is_err());
+    }
+
+    #[test]
+    fn test_unreachable_server_is_an_error() {
+        // Bound but never accepted from, then closed.
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        assert!(send(&notifier(port), &changed()).is_err());
+    }
+}
@@ -0,0 +1,116 @@
+use std::{collections::HashMap, net::IpAddr};
+
+use log::debug;
+
+use crate::{command::CommandSpec, errors::IpError, ip_event::IpEvent, notifier::IpNotifier};
+
+pub struct ExecNotifier {
+    command: CommandSpec,
+}
+
+impl ExecNotifier {
+    pub fn new(command: CommandSpec) -> ExecNotifier {
+        ExecNotifier { command }
+    }
+
+    pub fn run(&self, event: &IpEvent) -> Result<String, IpError> {
+        let input = serde_json::to_vec(event).unwrap();
+        self.command
+            .run_with_input(Some(&input), &ExecNotifier::event_env(event))
+    }
+
+    fn event_env(event: &IpEvent) -> HashMap<String, String> {
+        let mut env = HashMap::new();
+        env.insert("FINDIP_IP".to_string(), event.ip_or_empty());
+        env.insert(
+            "FINDIP_PREVIOUS_IP".to_string(),
+            event.previous_ip_or_empty(),
+        );
+        env.insert("FINDIP_EVENT".to_string(), event.event_type.to_string());
+        env.insert("FINDIP_HOSTNAME".to_string(), event.hostname.clone());
+        env
+    }
+}
+
+impl IpNotifier for ExecNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        self.notify_event(&IpEvent::new(ip, None));
+    }
+
+    // Every event is handed to the command, errors included, so scripts can react to failed
+    // queries as well.
+    fn notify_event(&self, event: &IpEvent) {
+        match self.run(event) {
+            Ok(output) => debug!(
+                "{} ran successfully. Output: {}",
+                self.command.program, output
+            ),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, net::Ipv4Addr, time::Duration};
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::errors::ErrorReason;
+
+    fn sh(script: String) -> CommandSpec {
+        CommandSpec::new(
+            "sh".to_string(),
+            vec!["-c".to_string(), script],
+            Duration::from_secs(5),
+        )
+    }
+
+    #[test]
+    fn test_exports_event_env_and_stdin() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("output");
+        let notifier = ExecNotifier::new(sh(format!(
+            "echo \"$FINDIP_EVENT $FINDIP_IP $FINDIP_PREVIOUS_IP\" > {0}; cat >> {0}",
+            output.to_str().unwrap()
+        )));
+
+        notifier.notify_event(&IpEvent::new(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)),
+            Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
+        ));
+        let contents = fs::read_to_string(output).unwrap();
+        let (first_line, json) = contents.split_once('\n').unwrap();
+        let json: serde_json::Value = serde_json::from_str(json).unwrap();
+
+        assert_eq!(first_line, "ip.changed 127.0.0.2 127.0.0.1");
+        assert_eq!(json["ip"], "127.0.0.2");
+    }
+
+    #[test]
+    fn test_non_zero_exit_is_an_error() {
+        let notifier = ExecNotifier::new(sh("exit 1".to_string()));
+        let result = notifier.run(&IpEvent::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)), None));
+
+        assert!(matches!(
+            result.unwrap_err().reason(),
+            ErrorReason::CommandFailed(_)
+        ));
+    }
+
+    #[test]
+    fn test_error_events_are_passed_to_the_command() {
+        let dir = tempdir().unwrap();
+        let output = dir.path().join("output");
+        let notifier = ExecNotifier::new(sh(format!(
+            "echo \"$FINDIP_EVENT|$FINDIP_IP\" > {}",
+            output.to_str().unwrap()
+        )));
+
+        notifier.notify_event(&IpEvent::from_error(IpError::new(ErrorReason::IpConflict(
+            vec!["127.0.0.1".to_string(), "127.0.0.2".to_string()],
+        ))));
+        assert_eq!(fs::read_to_string(output).unwrap(), "ip.conflict|\n");
+    }
+}
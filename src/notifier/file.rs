@@ -0,0 +1,314 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    process,
+};
+
+use chrono::SecondsFormat;
+use log::debug;
+
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+use crate::{
+    config_file::FileFormat,
+    errors::{ErrorReason, IpError},
+    ip_event::IpEvent,
+    notifier::{dispatch_event, IpNotifier},
+};
+
+const CSV_HEADER: &str = "timestamp,ip,previous_ip\n";
+
+pub struct FileNotifier {
+    overwrite: bool,
+    file_path: String,
+    format: FileFormat,
+    permissions: Option<u32>,
+}
+
+impl FileNotifier {
+    pub fn new(file_path: String, overwrite: bool) -> FileNotifier {
+        FileNotifier {
+            file_path,
+            overwrite,
+            format: FileFormat::Plain,
+            permissions: None,
+        }
+    }
+
+    pub fn with_format(mut self, format: FileFormat) -> FileNotifier {
+        self.format = format;
+        self
+    }
+
+    pub fn with_permissions(mut self, permissions: u32) -> FileNotifier {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    fn write_event(&self, event: &IpEvent) -> Result<(), IpError> {
+        let line = self.format_line(event);
+
+        if self.overwrite {
+            let contents = match self.format {
+                FileFormat::Csv => format!("{}{}", CSV_HEADER, line),
+                _ => line,
+            };
+            write_atomically(
+                Path::new(&self.file_path),
+                contents.as_bytes(),
+                self.permissions,
+            )
+            .map_err(|_| IpError::new(ErrorReason::FileWriteFailed(self.file_path.clone())))
+        } else {
+            self.append(&line)
+        }
+    }
+
+    fn format_line(&self, event: &IpEvent) -> String {
+        match self.format {
+            FileFormat::Plain => format!("{}\n", event.ip_or_empty()),
+            FileFormat::JsonLines => format!("{}\n", serde_json::to_string(event).unwrap()),
+            FileFormat::Csv => format!(
+                "{},{},{}\n",
+                event.timestamp.to_rfc3339_opts(SecondsFormat::Secs, true),
+                event.ip_or_empty(),
+                event.previous_ip_or_empty()
+            ),
+        }
+    }
+
+    fn append(&self, line: &str) -> Result<(), IpError> {
+        let is_empty = fs::metadata(&self.file_path)
+            .map(|metadata| metadata.len() == 0)
+            .unwrap_or(true);
+
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        if let Some(permissions) = self.permissions {
+            options.mode(permissions);
+        }
+
+        let mut file = options
+            .open(&self.file_path)
+            .map_err(|_| IpError::new(ErrorReason::FileOpenFailed(self.file_path.clone())))?;
+
+        let contents = match self.format {
+            FileFormat::Csv if is_empty => format!("{}{}", CSV_HEADER, line),
+            _ => line.to_string(),
+        };
+
+        file.write_all(contents.as_bytes())
+            .map_err(|_| IpError::new(ErrorReason::FileWriteFailed(self.file_path.clone())))
+    }
+}
+
+// Writes to a sibling temp file and renames it over the target so readers never see a partially
+// written file. Without an explicit mode, the permissions of the file being replaced are kept.
+pub(crate) fn write_atomically(
+    path: &Path,
+    contents: &[u8],
+    permissions: Option<u32>,
+) -> io::Result<()> {
+    let temp_path = temp_path_for(path);
+
+    let result = File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|_| apply_permissions(path, &temp_path, permissions))
+        .and_then(|_| fs::rename(&temp_path, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn apply_permissions(path: &Path, temp_path: &Path, permissions: Option<u32>) -> io::Result<()> {
+    let permissions = match (permissions, fs::metadata(path)) {
+        (Some(mode), _) => fs::Permissions::from_mode(mode),
+        (None, Ok(metadata)) => metadata.permissions(),
+        (None, Err(_)) => return Ok(()),
+    };
+
+    fs::set_permissions(temp_path, permissions)
+}
+
+#[cfg(not(unix))]
+fn apply_permissions(path: &Path, temp_path: &Path, _permissions: Option<u32>) -> io::Result<()> {
+    match fs::metadata(path) {
+        Ok(metadata) => fs::set_permissions(temp_path, metadata.permissions()),
+        Err(_) => Ok(()),
+    }
+}
+
+fn temp_path_for(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    path.with_file_name(format!(".{}.{}.tmp", file_name, process::id()))
+}
+
+impl IpNotifier for FileNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        self.notify_event(&IpEvent::new(ip, None));
+    }
+
+    fn notify_event(&self, event: &IpEvent) {
+        if event.error.is_some() || event.ip.is_none() {
+            return dispatch_event(self, event);
+        }
+
+        match self.write_event(event) {
+            Ok(()) => debug!("IP written to file successfully."),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn ip(last_octet: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(127, 0, 0, last_octet))
+    }
+
+    fn path_in(dir: &tempfile::TempDir, name: &str) -> String {
+        dir.path().join(name).to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_append_creates_missing_file() {
+        let dir = tempdir().unwrap();
+        let file_path = path_in(&dir, "ip.log");
+
+        FileNotifier::new(file_path.clone(), false).notify_success(ip(1));
+        assert_eq!(fs::read_to_string(file_path).unwrap(), "127.0.0.1\n");
+    }
+
+    #[test]
+    fn test_append_adds_one_line_per_notification() {
+        let dir = tempdir().unwrap();
+        let file_path = path_in(&dir, "ip.log");
+        let notifier = FileNotifier::new(file_path.clone(), false);
+
+        notifier.notify_success(ip(1));
+        notifier.notify_success(ip(2));
+        assert_eq!(
+            fs::read_to_string(file_path).unwrap(),
+            "127.0.0.1\n127.0.0.2\n"
+        );
+    }
+
+    #[test]
+    fn test_overwrite_writes_ip_to_file_path() {
+        let dir = tempdir().unwrap();
+        let file_path = path_in(&dir, "ip.log");
+        let notifier = FileNotifier::new(file_path.clone(), true);
+
+        notifier.notify_success(ip(1));
+        notifier.notify_success(ip(2));
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "127.0.0.2\n");
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_json_lines_format() {
+        let dir = tempdir().unwrap();
+        let file_path = path_in(&dir, "ip.jsonl");
+        let notifier =
+            FileNotifier::new(file_path.clone(), false).with_format(FileFormat::JsonLines);
+
+        notifier.notify_event(&IpEvent::new(ip(2), Some(ip(1))));
+        let contents = fs::read_to_string(file_path).unwrap();
+        let json: serde_json::Value = serde_json::from_str(contents.trim_end()).unwrap();
+
+        assert_eq!(json["ip"], "127.0.0.2");
+        assert_eq!(json["previousIp"], "127.0.0.1");
+        assert_eq!(json["eventType"], "ip.changed");
+    }
+
+    #[test]
+    fn test_csv_format_writes_header_once() {
+        let dir = tempdir().unwrap();
+        let file_path = path_in(&dir, "ip.csv");
+        let notifier = FileNotifier::new(file_path.clone(), false).with_format(FileFormat::Csv);
+
+        notifier.notify_event(&IpEvent::new(ip(1), None));
+        notifier.notify_event(&IpEvent::new(ip(2), Some(ip(1))));
+        let contents = fs::read_to_string(file_path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "timestamp,ip,previous_ip");
+        assert!(lines[1].ends_with(",127.0.0.1,"));
+        assert!(lines[2].ends_with(",127.0.0.2,127.0.0.1"));
+    }
+
+    #[test]
+    fn test_csv_overwrite_keeps_header() {
+        let dir = tempdir().unwrap();
+        let file_path = path_in(&dir, "ip.csv");
+        let notifier = FileNotifier::new(file_path.clone(), true).with_format(FileFormat::Csv);
+
+        notifier.notify_event(&IpEvent::new(ip(1), None));
+        notifier.notify_event(&IpEvent::new(ip(2), Some(ip(1))));
+        let contents = fs::read_to_string(file_path).unwrap();
+
+        assert_eq!(contents.lines().count(), 2);
+        assert!(contents.starts_with(CSV_HEADER));
+    }
+
+    #[test]
+    fn test_error_events_are_not_written() {
+        let dir = tempdir().unwrap();
+        let file_path = path_in(&dir, "ip.log");
+        let notifier = FileNotifier::new(file_path.clone(), false);
+
+        notifier.notify_event(&IpEvent::from_error(IpError::new(
+            ErrorReason::NoIpAddressesFound,
+        )));
+        assert!(!Path::new(&file_path).exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_permissions_are_applied() {
+        let dir = tempdir().unwrap();
+        let appended = path_in(&dir, "appended.log");
+        let overwritten = path_in(&dir, "overwritten.log");
+
+        FileNotifier::new(appended.clone(), false)
+            .with_permissions(0o600)
+            .notify_success(ip(1));
+        FileNotifier::new(overwritten.clone(), true)
+            .with_permissions(0o640)
+            .notify_success(ip(1));
+
+        let mode = |path: &str| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&appended), 0o600);
+        assert_eq!(mode(&overwritten), 0o640);
+    }
+
+    #[test]
+    fn test_missing_directory_does_not_leave_temp_file() {
+        let dir = tempdir().unwrap();
+        let file_path = path_in(&dir, "missing/ip.log");
+
+        FileNotifier::new(file_path, true).notify_success(ip(1));
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
@@ -0,0 +1,456 @@
+use std::{
+    fs,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use log::{debug, info};
+use reqwest::{Client, RequestBuilder, Response};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dns::{fqdn, RecordType},
+    errors::{ErrorReason, IpError},
+    notifier::IpNotifier,
+    utils,
+};
+
+pub const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+pub const CLOUD_DNS_API_BASE_URL: &str = "https://dns.googleapis.com/dns/v1";
+
+const CLOUD_DNS_SCOPE: &str = "https://www.googleapis.com/auth/ndev.clouddns.readwrite";
+const JWT_BEARER_GRANT: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+const ASSERTION_LIFETIME_SECONDS: u64 = 3600;
+// Tokens are renewed a little before they expire so one never runs out mid-update.
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServiceAccountKey {
+    pub project_id: String,
+    pub private_key_id: String,
+    pub private_key: String,
+    pub client_email: String,
+}
+
+impl ServiceAccountKey {
+    pub fn from_key_file(path: &str) -> Result<ServiceAccountKey, IpError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|_| IpError::new(ErrorReason::FileOpenFailed(path.to_string())))?;
+
+        serde_json::from_str(&contents)
+            .map_err(|e| IpError::new(ErrorReason::InvalidInput(format!("{}: {}", path, e))))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Claims {
+    iss: String,
+    scope: String,
+    aud: String,
+    iat: u64,
+    exp: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+struct ResourceRecordSet {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: String,
+    ttl: u32,
+    rrdatas: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResourceRecordSets {
+    #[serde(default)]
+    rrsets: Vec<ResourceRecordSet>,
+}
+
+#[derive(Debug, Serialize)]
+struct Change {
+    additions: Vec<ResourceRecordSet>,
+    deletions: Vec<ResourceRecordSet>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiErrorResponse {
+    error: ApiError,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    message: String,
+}
+
+pub struct GoogleCloudDnsNotifier {
+    key: ServiceAccountKey,
+    project: String,
+    managed_zone: String,
+    record_names: Vec<String>,
+    ttl: u32,
+    token_url: String,
+    api_base_url: String,
+    client: Client,
+    access_token: Mutex<Option<(String, Instant)>>,
+}
+
+impl GoogleCloudDnsNotifier {
+    pub fn new(
+        key: ServiceAccountKey,
+        managed_zone: String,
+        record_names: Vec<String>,
+        ttl: u32,
+    ) -> GoogleCloudDnsNotifier {
+        GoogleCloudDnsNotifier {
+            project: key.project_id.clone(),
+            key,
+            managed_zone,
+            record_names,
+            ttl,
+            token_url: GOOGLE_TOKEN_URL.to_string(),
+            api_base_url: CLOUD_DNS_API_BASE_URL.to_string(),
+            client: Client::builder().build().unwrap(),
+            access_token: Mutex::new(None),
+        }
+    }
+
+    // The project of the service account is used unless the zone lives in another one.
+    pub fn with_project(mut self, project: String) -> GoogleCloudDnsNotifier {
+        self.project = project;
+        self
+    }
+
+    pub fn with_token_url(mut self, token_url: String) -> GoogleCloudDnsNotifier {
+        self.token_url = token_url;
+        self
+    }
+
+    pub fn with_api_base_url(mut self, api_base_url: String) -> GoogleCloudDnsNotifier {
+        self.api_base_url = api_base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    fn zone_url(&self) -> String {
+        format!(
+            "{}/projects/{}/managedZones/{}",
+            self.api_base_url, self.project, self.managed_zone
+        )
+    }
+
+    fn assertion(&self, now: u64) -> Result<String, IpError> {
+        let claims = Claims {
+            iss: self.key.client_email.clone(),
+            scope: CLOUD_DNS_SCOPE.to_string(),
+            aud: self.token_url.clone(),
+            iat: now,
+            exp: now + ASSERTION_LIFETIME_SECONDS,
+        };
+        let header = Header {
+            kid: Some(self.key.private_key_id.clone()),
+            ..Header::new(Algorithm::RS256)
+        };
+        let encoding_key = EncodingKey::from_rsa_pem(self.key.private_key.as_bytes())
+            .map_err(|e| dns_error(format!("the service account key is invalid: {}", e)))?;
+
+        jsonwebtoken::encode(&header, &claims, &encoding_key)
+            .map_err(|e| dns_error(format!("could not sign the token request: {}", e)))
+    }
+
+    async fn access_token(&self) -> Result<String, IpError> {
+        if let Some((token, expires_at)) = self.access_token.lock().unwrap().as_ref() {
+            if Instant::now() < *expires_at {
+                return Ok(token.clone());
+            }
+        }
+
+        let assertion = self.assertion(utils::get_time_in_millis() as u64 / 1000)?;
+        let request = self.client.post(&self.token_url).form(&[
+            ("grant_type", JWT_BEARER_GRANT),
+            ("assertion", assertion.as_str()),
+        ]);
+        let token: TokenResponse = send(request)
+            .await?
+            .json()
+            .await
+            .map_err(|e| dns_error(e.to_string()))?;
+
+        let lifetime = Duration::from_secs(token.expires_in).saturating_sub(TOKEN_EXPIRY_MARGIN);
+        *self.access_token.lock().unwrap() =
+            Some((token.access_token.clone(), Instant::now() + lifetime));
+        Ok(token.access_token)
+    }
+
+    async fn get_rrset(
+        &self,
+        token: &str,
+        name: &str,
+        record_type: RecordType,
+    ) -> Result<Option<ResourceRecordSet>, IpError> {
+        let request = self
+            .client
+            .get(format!("{}/rrsets", self.zone_url()))
+            .query(&[("name", name), ("type", record_type.as_str())])
+            .bearer_auth(token);
+        let rrsets: ResourceRecordSets = send(request)
+            .await?
+            .json()
+            .await
+            .map_err(|e| dns_error(e.to_string()))?;
+
+        Ok(rrsets.rrsets.into_iter().next())
+    }
+
+    pub async fn update_records(&self, ip: IpAddr) -> Result<(), IpError> {
+        let token = self.access_token().await?;
+        let record_type = RecordType::for_ip(ip);
+
+        // Cloud DNS only deletes a record set when it is given exactly as it is now, so the
+        // current ones are read first.
+        let mut change = Change {
+            additions: vec![],
+            deletions: vec![],
+        };
+        for record_name in self.record_names.iter() {
+            let addition = ResourceRecordSet {
+                name: fqdn(record_name),
+                record_type: record_type.to_string(),
+                ttl: self.ttl,
+                rrdatas: vec![ip.to_string()],
+            };
+
+            match self.get_rrset(&token, &addition.name, record_type).await? {
+                Some(existing) if existing == addition => {
+                    debug!(
+                        "{} {} already points at {}, skipping.",
+                        record_type, existing.name, ip
+                    );
+                    continue;
+                }
+                Some(existing) => change.deletions.push(existing),
+                None => {}
+            }
+            change.additions.push(addition);
+        }
+
+        if change.additions.is_empty() {
+            return Ok(());
+        }
+
+        let request = self
+            .client
+            .post(format!("{}/changes", self.zone_url()))
+            .bearer_auth(&token)
+            .json(&change);
+        send(request).await?;
+        info!(
+            "Submitted a change to {} setting {} to {}.",
+            self.managed_zone,
+            change
+                .additions
+                .iter()
+                .map(|rrset| rrset.name.as_str())
+                .collect::<Vec<&str>>()
+                .join(", "),
+            ip
+        );
+        Ok(())
+    }
+}
+
+async fn send(request: RequestBuilder) -> Result<Response, IpError> {
+    let response = request.send().await.map_err(|e| dns_error(e.to_string()))?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    let reason = serde_json::from_str::<ApiErrorResponse>(&body)
+        .map(|response| response.error.message)
+        .unwrap_or(body);
+    Err(dns_error(format!("Google returned {}: {}", status, reason)))
+}
+
+fn dns_error(context: String) -> IpError {
+    IpError::new(ErrorReason::DnsUpdateFailed(context))
+}
+
+impl IpNotifier for GoogleCloudDnsNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        if let Err(err) = runtime.block_on(self.update_records(ip)) {
+            IpNotifier::notify_error(self, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use httpmock::{
+        Method::{GET, POST},
+        MockServer,
+    };
+    use jsonwebtoken::{DecodingKey, Validation};
+    use serde_json::json;
+
+    use super::*;
+
+    const KEY_FILE: &str = "testfiles/gcp/service-account.json";
+    const PUBLIC_KEY_FILE: &str = "testfiles/gcp/service-account.pub.pem";
+
+    fn ip() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))
+    }
+
+    fn notifier(server: &MockServer) -> GoogleCloudDnsNotifier {
+        GoogleCloudDnsNotifier::new(
+            ServiceAccountKey::from_key_file(KEY_FILE).unwrap(),
+            "example-com".to_string(),
+            vec![
+                "home.example.com".to_string(),
+                "vpn.example.com".to_string(),
+            ],
+            300,
+        )
+        .with_token_url(server.url("/token"))
+        .with_api_base_url(server.url("/dns/v1"))
+    }
+
+    fn update(notifier: &GoogleCloudDnsNotifier) -> Result<(), IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.update_records(ip()))
+    }
+
+    fn mock_token(server: &MockServer) -> httpmock::Mock {
+        server.mock(|when, then| {
+            when.method(POST)
+                .path("/token")
+                .body_contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer")
+                .body_contains("assertion=");
+            then.status(200).json_body(json!({
+                "access_token": "ya29.token",
+                "expires_in": 3599,
+                "token_type": "Bearer"
+            }));
+        })
+    }
+
+    fn mock_rrset(server: &MockServer, name: &str, rrsets: serde_json::Value) {
+        server.mock(|when, then| {
+            when.method(GET)
+                .path("/dns/v1/projects/findip-test/managedZones/example-com/rrsets")
+                .query_param("name", name)
+                .query_param("type", "A")
+                .header("Authorization", "Bearer ya29.token");
+            then.status(200).json_body(json!({ "rrsets": rrsets }));
+        });
+    }
+
+    #[test]
+    fn test_assertion_is_signed_by_the_service_account() {
+        let server = MockServer::start();
+        let notifier = notifier(&server);
+        let now = utils::get_time_in_millis() as u64 / 1000;
+
+        let assertion = notifier.assertion(now).unwrap();
+        let header = jsonwebtoken::decode_header(&assertion).unwrap();
+        let public_key = fs::read(PUBLIC_KEY_FILE).unwrap();
+        let claims = jsonwebtoken::decode::<Claims>(
+            &assertion,
+            &DecodingKey::from_rsa_pem(&public_key).unwrap(),
+            &Validation::new(Algorithm::RS256),
+        )
+        .unwrap()
+        .claims;
+
+        assert_eq!(header.kid, Some("0123456789abcdef".to_string()));
+        assert_eq!(claims.iss, "findip@findip-test.iam.gserviceaccount.com");
+        assert_eq!(claims.scope, CLOUD_DNS_SCOPE);
+        assert_eq!(claims.aud, server.url("/token"));
+        assert_eq!(claims.exp, now + 3600);
+    }
+
+    #[test]
+    fn test_replaces_changed_records_only() {
+        let server = MockServer::start();
+        let token = mock_token(&server);
+        mock_rrset(
+            &server,
+            "home.example.com.",
+            json!([{ "name": "home.example.com.", "type": "A", "ttl": 300, "rrdatas": ["198.51.100.1"] }]),
+        );
+        mock_rrset(
+            &server,
+            "vpn.example.com.",
+            json!([{ "name": "vpn.example.com.", "type": "A", "ttl": 300, "rrdatas": ["203.0.113.7"] }]),
+        );
+        let change = server.mock(|when, then| {
+            when.method(POST)
+                .path("/dns/v1/projects/findip-test/managedZones/example-com/changes")
+                .header("Authorization", "Bearer ya29.token")
+                .json_body(json!({
+                    "additions": [
+                        { "name": "home.example.com.", "type": "A", "ttl": 300, "rrdatas": ["203.0.113.7"] }
+                    ],
+                    "deletions": [
+                        { "name": "home.example.com.", "type": "A", "ttl": 300, "rrdatas": ["198.51.100.1"] }
+                    ]
+                }));
+            then.status(200).json_body(json!({ "id": "1", "status": "pending" }));
+        });
+        let notifier = notifier(&server);
+
+        assert!(update(&notifier).is_ok());
+        assert!(update(&notifier).is_ok());
+        change.assert_hits(2);
+        // The access token is reused until it expires.
+        token.assert_hits(1);
+    }
+
+    #[test]
+    fn test_adds_missing_records_without_deletions() {
+        let server = MockServer::start();
+        mock_token(&server);
+        mock_rrset(&server, "home.example.com.", json!([]));
+        mock_rrset(&server, "vpn.example.com.", json!([]));
+        let change = server.mock(|when, then| {
+            when.method(POST)
+                .path("/dns/v1/projects/findip-test/managedZones/example-com/changes")
+                .body_contains(r#""deletions":[]"#)
+                .body_contains(r#""name":"vpn.example.com.""#);
+            then.status(200)
+                .json_body(json!({ "id": "1", "status": "pending" }));
+        });
+
+        assert!(update(&notifier(&server)).is_ok());
+        change.assert();
+    }
+
+    #[test]
+    fn test_token_errors_are_reported() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/token");
+            then.status(400).json_body(json!({
+                "error": "invalid_grant",
+                "error_description": "Invalid JWT Signature."
+            }));
+        });
+
+        let err = update(&notifier(&server)).unwrap_err();
+        assert!(err.to_string().contains("Invalid JWT Signature."));
+    }
+}
@@ -0,0 +1,314 @@
+use std::{net::IpAddr, time::Duration};
+
+use log::{debug, warn};
+use reqwest::{Client, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::time::sleep;
+use url::Url;
+
+use crate::{
+    config_file::SecretRef,
+    errors::{ErrorReason, IpError},
+    ip_event::IpEvent,
+    notifier::{message, IpNotifier},
+    secret::resolve_secret,
+};
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    errcode: Option<String>,
+    error: Option<String>,
+    retry_after_ms: Option<u64>,
+}
+
+pub struct MatrixNotifier {
+    homeserver_url: String,
+    access_token: SecretRef,
+    room_id: String,
+    max_retries: u32,
+    retry_delay: Duration,
+    client: Client,
+}
+
+impl MatrixNotifier {
+    pub fn new(homeserver_url: String, access_token: SecretRef, room_id: String) -> MatrixNotifier {
+        MatrixNotifier {
+            homeserver_url,
+            access_token,
+            room_id,
+            max_retries: 3,
+            retry_delay: Duration::from_secs(2),
+            client: Client::builder().build().unwrap(),
+        }
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> MatrixNotifier {
+        self.max_retries = max_retries;
+        self
+    }
+
+    // How long to wait before retrying when the homeserver doesn't say.
+    pub fn with_retry_delay(mut self, retry_delay: Duration) -> MatrixNotifier {
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    // The same event always gets the same ID, and the homeserver only keeps the first message
+    // sent with an ID, so retries can't post it twice.
+    pub fn transaction_id(event: &IpEvent) -> String {
+        format!("findip.{}.{}", event.event_type, event.id)
+    }
+
+    pub fn content(event: &IpEvent) -> Value {
+        let mut html = format!(
+            "<strong>{}</strong><ul>",
+            message::escape_html(&message::title(event))
+        );
+        for (label, value) in message::facts(event) {
+            html.push_str(&format!(
+                "<li><b>{}:</b> <code>{}</code></li>",
+                label,
+                message::escape_html(&value)
+            ));
+        }
+        html.push_str("</ul>");
+        if let Some(details) = &event.message {
+            html.push_str(&format!(
+                "<pre><code>{}</code></pre>",
+                message::escape_html(details)
+            ));
+        }
+
+        json!({
+            // Notices are how bots talk in Matrix and clients don't alert for them by default.
+            "msgtype": if event.event_type.is_error() { "m.text" } else { "m.notice" },
+            "body": message::plain_text(event),
+            "format": "org.matrix.custom.html",
+            "formatted_body": html,
+        })
+    }
+
+    fn send_url(&self, transaction_id: &str) -> Result<Url, IpError> {
+        let mut url = Url::parse(&self.homeserver_url).map_err(|e| {
+            IpError::new(ErrorReason::InvalidInput(format!(
+                "{} is not a valid homeserver URL: {}",
+                self.homeserver_url, e
+            )))
+        })?;
+        url.path_segments_mut()
+            .map_err(|_| {
+                IpError::new(ErrorReason::InvalidInput(format!(
+                    "{} is not a valid homeserver URL",
+                    self.homeserver_url
+                )))
+            })?
+            .pop_if_empty()
+            .extend(&[
+                "_matrix",
+                "client",
+                "v3",
+                "rooms",
+                &self.room_id,
+                "send",
+                "m.room.message",
+                transaction_id,
+            ]);
+        Ok(url)
+    }
+
+    async fn send(&self, event: &IpEvent) -> Result<(), IpError> {
+        let url = self.send_url(&MatrixNotifier::transaction_id(event))?;
+        let content = MatrixNotifier::content(event);
+        let mut attempt = 0;
+
+        loop {
+            let retry_after = match self
+                .client
+                .put(url.clone())
+                .bearer_auth(resolve_secret(&self.access_token)?)
+                .json(&content)
+                .send()
+                .await
+            {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    let body: Option<ErrorResponse> = response.json().await.ok();
+                    let reason = body
+                        .as_ref()
+                        .map(|body| {
+                            format!(
+                                "{} {}",
+                                body.errcode.clone().unwrap_or_default(),
+                                body.error.clone().unwrap_or_default()
+                            )
+                        })
+                        .unwrap_or_default();
+                    if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+                        return Err(message_error(format!(
+                            "the homeserver returned {}: {}",
+                            status,
+                            reason.trim()
+                        )));
+                    }
+                    if attempt >= self.max_retries {
+                        return Err(message_error(format!(
+                            "the homeserver returned {} after {} retries: {}",
+                            status,
+                            attempt,
+                            reason.trim()
+                        )));
+                    }
+                    body.and_then(|body| body.retry_after_ms)
+                        .map(Duration::from_millis)
+                        .unwrap_or(self.retry_delay)
+                }
+                Err(e) if attempt >= self.max_retries => {
+                    return Err(message_error(e.to_string()));
+                }
+                Err(_) => self.retry_delay,
+            };
+
+            attempt += 1;
+            warn!(
+                "Sending to {} failed, retrying in {:.1}s.",
+                self.room_id,
+                retry_after.as_secs_f64()
+            );
+            sleep(retry_after).await;
+        }
+    }
+}
+
+fn message_error(context: String) -> IpError {
+    IpError::new(ErrorReason::MessageFailed(context))
+}
+
+impl IpNotifier for MatrixNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        self.notify_event(&IpEvent::new(ip, None));
+    }
+
+    fn notify_event(&self, event: &IpEvent) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        match runtime.block_on(self.send(event)) {
+            Ok(()) => debug!("Sent the {} event to {}.", event.event_type, self.room_id),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use httpmock::{Method::PUT, MockServer};
+
+    use super::*;
+
+    fn notifier(server: &MockServer) -> MatrixNotifier {
+        MatrixNotifier::new(
+            server.base_url(),
+            SecretRef::Value("syt_token".to_string()),
+            "!room:example.org".to_string(),
+        )
+        .with_retry_delay(Duration::from_millis(10))
+    }
+
+    fn send(notifier: &MatrixNotifier, event: &IpEvent) -> Result<(), IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.send(event))
+    }
+
+    #[test]
+    fn test_content() {
+        let mut event = IpEvent::sample_changed();
+        event.hostname = "<nas>".to_string();
+        let content = MatrixNotifier::content(&event);
+
+        assert_eq!(content["msgtype"], "m.notice");
+        assert!(content["body"]
+            .as_str()
+            .unwrap()
+            .starts_with("Public IP of <nas> changed\nHost: <nas>\nNew IP: 203.0.113.7\n"));
+        assert!(content["formatted_body"]
+            .as_str()
+            .unwrap()
+            .starts_with("<strong>Public IP of &lt;nas&gt; changed</strong><ul><li><b>Host:</b> <code>&lt;nas&gt;</code></li>"));
+    }
+
+    #[test]
+    fn test_sends_to_room() {
+        let server = MockServer::start();
+        let event = IpEvent::sample_changed();
+        let path = format!(
+            "/_matrix/client/v3/rooms/!room:example.org/send/m.room.message/{}",
+            MatrixNotifier::transaction_id(&event)
+        );
+        let mock = server.mock(|when, then| {
+            when.method(PUT)
+                .path(path)
+                .header("Authorization", "Bearer syt_token")
+                .body_contains(r#""format":"org.matrix.custom.html""#);
+            then.status(200).json_body(json!({ "event_id": "$abc" }));
+        });
+
+        assert!(send(&notifier(&server), &event).is_ok());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_retries_reuse_the_transaction_id() {
+        let server = MockServer::start();
+        let event = IpEvent::sample_changed();
+        let path = format!(
+            "/_matrix/client/v3/rooms/!room:example.org/send/m.room.message/{}",
+            MatrixNotifier::transaction_id(&event)
+        );
+        let mock = server.mock(|when, then| {
+            when.method(PUT).path(path);
+            then.status(429).json_body(json!({
+                "errcode": "M_LIMIT_EXCEEDED",
+                "error": "Too many requests",
+                "retry_after_ms": 10
+            }));
+        });
+
+        let err = send(&notifier(&server).with_max_retries(2), &event).unwrap_err();
+        assert!(err.to_string().contains("M_LIMIT_EXCEEDED"));
+        mock.assert_hits(3);
+    }
+
+    #[test]
+    fn test_events_in_the_same_millisecond_get_their_own_transaction_id() {
+        let first = IpEvent::sample_changed();
+        let mut second = IpEvent::sample_changed();
+        second.timestamp = first.timestamp;
+
+        assert_ne!(
+            MatrixNotifier::transaction_id(&first),
+            MatrixNotifier::transaction_id(&second)
+        );
+    }
+
+    #[test]
+    fn test_client_errors_are_not_retried() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(PUT);
+            then.status(403).json_body(json!({
+                "errcode": "M_FORBIDDEN",
+                "error": "User is not in the room"
+            }));
+        });
+
+        let err = send(&notifier(&server), &IpEvent::sample_changed()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("M_FORBIDDEN User is not in the room"));
+        mock.assert_hits(1);
+    }
+}
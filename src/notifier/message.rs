@@ -0,0 +1,120 @@
+// Wording shared by the notifiers that send messages to people rather than machines.
+use chrono::SecondsFormat;
+
+use crate::{
+    errors::{ErrorReason, IpError},
+    ip_event::{IpEvent, IpEventType},
+};
+
+pub fn title(event: &IpEvent) -> String {
+    match event.event_type {
+        IpEventType::IpChanged => format!("Public IP of {} changed", event.hostname),
+        IpEventType::IpUnchanged => format!("Public IP of {} is unchanged", event.hostname),
+        IpEventType::IpConflict => format!(
+            "IP services disagree about the public IP of {}",
+            event.hostname
+        ),
+        IpEventType::QueryFailed => format!("Could not find the public IP of {}", event.hostname),
+        IpEventType::DnsDrift => format!("DNS records of {} have drifted", event.hostname),
+    }
+}
+
+pub fn ip_or_unknown(event: &IpEvent) -> String {
+    event
+        .ip
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+pub fn previous_ip_or_none(event: &IpEvent) -> String {
+    event
+        .previous_ip
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|| "none".to_string())
+}
+
+pub fn timestamp(event: &IpEvent) -> String {
+    event.timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+// The answers that disagreed, for `IpConflict` events.
+pub fn conflicting_ips(event: &IpEvent) -> Vec<String> {
+    match event.error.as_ref().map(IpError::reason) {
+        Some(ErrorReason::IpConflict(ips)) => ips.clone(),
+        _ => vec![],
+    }
+}
+
+// The facts every message shows, in order, as label and value.
+pub fn facts(event: &IpEvent) -> Vec<(&'static str, String)> {
+    let mut facts = vec![("Host", event.hostname.clone())];
+    if event.ip.is_some() || !event.event_type.is_error() {
+        facts.push(("New IP", ip_or_unknown(event)));
+        facts.push(("Previous IP", previous_ip_or_none(event)));
+    }
+    let sources = conflicting_ips(event);
+    if !sources.is_empty() {
+        facts.push(("Reported IPs", sources.join(", ")));
+    }
+    facts.push(("Time", timestamp(event)));
+    facts
+}
+
+// Enough for HTML text and for Slack's mrkdwn, which reserves the same three characters.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+pub fn plain_text(event: &IpEvent) -> String {
+    let mut lines = vec![title(event)];
+    lines.extend(
+        facts(event)
+            .into_iter()
+            .map(|(label, value)| format!("{}: {}", label, value)),
+    );
+    if let Some(message) = &event.message {
+        lines.push(message.clone());
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+
+    #[test]
+    fn test_plain_text_for_a_change() {
+        let mut event = IpEvent::new(
+            IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2)),
+            Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1))),
+        );
+        event.hostname = "nas".to_string();
+
+        let text = plain_text(&event);
+        assert!(text.starts_with("Public IP of nas changed\nHost: nas\nNew IP: 127.0.0.2\nPrevious IP: 127.0.0.1\nTime: "));
+    }
+
+    #[test]
+    fn test_facts_for_a_conflict() {
+        let event = IpEvent::from_error(IpError::new(ErrorReason::IpConflict(vec![
+            "127.0.0.1".to_string(),
+            "127.0.0.2".to_string(),
+        ])));
+        let labels: Vec<&str> = facts(&event).into_iter().map(|(label, _)| label).collect();
+
+        assert_eq!(labels, vec!["Host", "Reported IPs", "Time"]);
+        assert_eq!(conflicting_ips(&event).len(), 2);
+    }
+
+    #[test]
+    fn test_escape_html() {
+        assert_eq!(
+            escape_html("<b>R&D</b> \"lab\""),
+            "&lt;b&gt;R&amp;D&lt;/b&gt; \"lab\""
+        );
+    }
+}
@@ -0,0 +1,658 @@
+use std::{
+    fs,
+    net::IpAddr,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, info};
+use rumqttc::{
+    Client, Event, LastWill, MqttOptions, Outgoing, Packet, QoS, TlsConfiguration, Transport,
+};
+use serde_json::json;
+
+use crate::{
+    config_file::SecretRef,
+    errors::{ErrorReason, IpError},
+    notifier::IpNotifier,
+    secret::resolve_secret,
+    utils,
+};
+
+pub const DEFAULT_DISCOVERY_PREFIX: &str = "homeassistant";
+pub const PAYLOAD_ONLINE: &str = "online";
+pub const PAYLOAD_OFFLINE: &str = "offline";
+
+const KEEP_ALIVE: Duration = Duration::from_secs(30);
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// What the background connection got done, so publishing can wait for the broker's answers.
+#[derive(Debug, Default)]
+struct Progress {
+    connected: bool,
+    // Messages handed to the client and the ones the broker has acknowledged (or, with QoS 0,
+    // that were written out). Every message uses the same QoS, so they finish in order.
+    queued: usize,
+    completed: usize,
+    error: Option<String>,
+    stopped: bool,
+}
+
+type SharedProgress = Arc<(Mutex<Progress>, Condvar)>;
+
+struct Connection {
+    client: Client,
+    progress: SharedProgress,
+    broker: String,
+}
+
+// Keeps a single connection open in the background, so the broker can publish the last will
+// when `findip` goes away.
+pub struct MqttNotifier {
+    host: String,
+    port: u16,
+    topic: String,
+    qos: QoS,
+    client_id: String,
+    credentials: Option<(String, SecretRef)>,
+    tls: bool,
+    ca_certificate: Option<String>,
+    discovery_prefix: Option<String>,
+    availability_topic: Option<String>,
+    timeout: Duration,
+    connection: Mutex<Option<Connection>>,
+}
+
+impl MqttNotifier {
+    pub fn new(host: String, port: u16, topic: String) -> MqttNotifier {
+        MqttNotifier {
+            host,
+            port,
+            topic,
+            qos: QoS::AtLeastOnce,
+            client_id: format!("findip-{}", node_id()),
+            credentials: None,
+            tls: false,
+            ca_certificate: None,
+            discovery_prefix: None,
+            availability_topic: None,
+            timeout: DEFAULT_TIMEOUT,
+            connection: Mutex::new(None),
+        }
+    }
+
+    pub fn with_qos(mut self, qos: QoS) -> MqttNotifier {
+        self.qos = qos;
+        self
+    }
+
+    pub fn with_client_id(mut self, client_id: String) -> MqttNotifier {
+        self.client_id = client_id;
+        self
+    }
+
+    pub fn with_credentials(mut self, username: String, password: SecretRef) -> MqttNotifier {
+        self.credentials = Some((username, password));
+        self
+    }
+
+    // Trusts `ca_certificate` (a PEM file) instead of the system roots when given.
+    pub fn with_tls(mut self, ca_certificate: Option<String>) -> MqttNotifier {
+        self.tls = true;
+        self.ca_certificate = ca_certificate;
+        self
+    }
+
+    pub fn with_home_assistant_discovery(mut self, discovery_prefix: String) -> MqttNotifier {
+        self.discovery_prefix = Some(discovery_prefix);
+        self
+    }
+
+    // Holds `online` while connected and `offline`, through the last will, once disconnected.
+    pub fn with_availability_topic(mut self, availability_topic: String) -> MqttNotifier {
+        self.availability_topic = Some(availability_topic);
+        self
+    }
+
+    // How long to wait for the broker to accept the connection and acknowledge each message.
+    pub fn with_timeout(mut self, timeout: Duration) -> MqttNotifier {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn discovery_topic(&self) -> Option<String> {
+        self.discovery_prefix.as_ref().map(|prefix| {
+            format!(
+                "{}/sensor/{}/public_ip/config",
+                prefix.trim_end_matches('/'),
+                node_id()
+            )
+        })
+    }
+
+    pub fn discovery_config(&self) -> serde_json::Value {
+        let node_id = node_id();
+        let mut config = json!({
+            "name": "Public IP",
+            "unique_id": format!("{}_public_ip", node_id),
+            "state_topic": self.topic,
+            "icon": "mdi:ip-network",
+            "device": {
+                "identifiers": [node_id],
+                "name": format!("findip on {}", utils::get_hostname()),
+                "manufacturer": "findip",
+                "sw_version": env!("CARGO_PKG_VERSION"),
+            },
+        });
+        if let Some(availability_topic) = &self.availability_topic {
+            config["availability_topic"] = json!(availability_topic);
+            config["payload_available"] = json!(PAYLOAD_ONLINE);
+            config["payload_not_available"] = json!(PAYLOAD_OFFLINE);
+        }
+        config
+    }
+
+    // Retained messages that are published again every time the connection comes up.
+    fn announcements(&self) -> Vec<(String, String)> {
+        let mut announcements = vec![];
+        if let Some(topic) = self.discovery_topic() {
+            announcements.push((topic, self.discovery_config().to_string()));
+        }
+        if let Some(topic) = &self.availability_topic {
+            announcements.push((topic.clone(), PAYLOAD_ONLINE.to_string()));
+        }
+        announcements
+    }
+
+    fn options(&self) -> Result<MqttOptions, IpError> {
+        let mut options = MqttOptions::new(&self.client_id, &self.host, self.port);
+        options.set_keep_alive(KEEP_ALIVE);
+
+        if let Some((username, password)) = &self.credentials {
+            options.set_credentials(username, resolve_secret(password)?);
+        }
+        if self.tls {
+            let tls_config = match &self.ca_certificate {
+                Some(path) => TlsConfiguration::SimpleNative {
+                    ca: fs::read(path)
+                        .map_err(|_| IpError::new(ErrorReason::FileOpenFailed(path.clone())))?,
+                    client_auth: None,
+                },
+                None => TlsConfiguration::Native,
+            };
+            options.set_transport(Transport::tls_with_config(tls_config));
+        }
+        if let Some(topic) = &self.availability_topic {
+            options.set_last_will(LastWill::new(topic, PAYLOAD_OFFLINE, self.qos, true));
+        }
+        Ok(options)
+    }
+
+    fn connect(&self) -> Result<Connection, IpError> {
+        let (client, mut connection) = Client::new(self.options()?, 10);
+        let progress = SharedProgress::default();
+        let announcer = client.clone();
+        let announcements = self.announcements();
+        let qos = self.qos;
+        let broker = format!("{}:{}", self.host, self.port);
+        let shared = progress.clone();
+        let connected_to = broker.clone();
+
+        thread::spawn(move || {
+            let (lock, changed) = &*shared;
+            for notification in connection.iter() {
+                match notification {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        info!("Connected to the MQTT broker at {}.", connected_to);
+                        let mut progress = lock.lock().unwrap();
+                        for (topic, payload) in announcements.iter() {
+                            match announcer.try_publish(topic, qos, true, payload.clone()) {
+                                Ok(()) => progress.queued += 1,
+                                Err(e) => {
+                                    error!("Failed to queue an MQTT message for {}: {}", topic, e)
+                                }
+                            }
+                        }
+                        progress.connected = true;
+                        progress.error = None;
+                        changed.notify_all();
+                    }
+                    Ok(Event::Outgoing(Outgoing::Publish(_))) if qos == QoS::AtMostOnce => {
+                        lock.lock().unwrap().completed += 1;
+                        changed.notify_all();
+                    }
+                    Ok(Event::Incoming(Packet::PubAck(_))) if qos == QoS::AtLeastOnce => {
+                        lock.lock().unwrap().completed += 1;
+                        changed.notify_all();
+                    }
+                    Ok(Event::Incoming(Packet::PubComp(_))) if qos == QoS::ExactlyOnce => {
+                        lock.lock().unwrap().completed += 1;
+                        changed.notify_all();
+                    }
+                    // Otherwise the loop would reconnect once the broker hangs up.
+                    Ok(Event::Outgoing(Outgoing::Disconnect)) => break,
+                    Ok(_) => {}
+                    Err(e) => {
+                        let context =
+                            format!("the MQTT connection to {} failed: {}", connected_to, e);
+                        error!(
+                            "{}",
+                            IpError::new(ErrorReason::MessageFailed(context.clone()))
+                        );
+                        {
+                            let mut progress = lock.lock().unwrap();
+                            progress.connected = false;
+                            progress.error = Some(context);
+                            changed.notify_all();
+                            if progress.stopped {
+                                break;
+                            }
+                        }
+                        thread::sleep(RECONNECT_DELAY);
+                    }
+                }
+            }
+        });
+
+        let connection = Connection {
+            client,
+            progress,
+            broker,
+        };
+        if let Err(e) = connection.wait_until(self.timeout, |progress| progress.connected) {
+            connection.close();
+            return Err(mqtt_error(format!(
+                "the MQTT broker at {} didn't accept the connection: {}",
+                connection.broker, e
+            )));
+        }
+        Ok(connection)
+    }
+
+    pub fn publish(&self, ip: IpAddr) -> Result<(), IpError> {
+        let mut connection = self.connection.lock().unwrap();
+        if connection.is_none() {
+            *connection = Some(self.connect()?);
+        }
+
+        connection
+            .as_ref()
+            .unwrap()
+            .publish(&self.topic, self.qos, ip.to_string(), self.timeout)
+    }
+}
+
+impl Connection {
+    // Returns once the broker acknowledged the message, which is retained.
+    fn publish(
+        &self,
+        topic: &str,
+        qos: QoS,
+        payload: String,
+        timeout: Duration,
+    ) -> Result<(), IpError> {
+        let (lock, _) = &*self.progress;
+        let target = {
+            let mut progress = lock.lock().unwrap();
+            self.client
+                .try_publish(topic, qos, true, payload)
+                .map_err(|e| mqtt_error(e.to_string()))?;
+            progress.queued += 1;
+            progress.queued
+        };
+
+        self.wait_until(timeout, |progress| progress.completed >= target)
+            .map_err(|e| {
+                mqtt_error(format!(
+                    "the MQTT broker at {} didn't acknowledge the message for {}: {}",
+                    self.broker, topic, e
+                ))
+            })
+    }
+
+    // Waits for `done`, giving up when the connection fails or after `timeout`.
+    fn wait_until<F>(&self, timeout: Duration, done: F) -> Result<(), String>
+    where
+        F: Fn(&Progress) -> bool,
+    {
+        let (lock, changed) = &*self.progress;
+        let deadline = Instant::now() + timeout;
+        let mut progress = lock.lock().unwrap();
+
+        loop {
+            if done(&progress) {
+                return Ok(());
+            }
+            if let (false, Some(error)) = (progress.connected, &progress.error) {
+                return Err(error.clone());
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(format!("no answer after {}s", timeout.as_secs_f64()));
+            }
+            progress = changed.wait_timeout(progress, deadline - now).unwrap().0;
+        }
+    }
+
+    // Stops the background loop, which otherwise keeps reconnecting.
+    fn close(&self) {
+        self.progress.0.lock().unwrap().stopped = true;
+        let _ = self.client.try_disconnect();
+    }
+}
+
+fn mqtt_error(context: String) -> IpError {
+    IpError::new(ErrorReason::MessageFailed(context))
+}
+
+impl Drop for MqttNotifier {
+    // A clean disconnect doesn't trigger the last will, so the availability is set by hand.
+    fn drop(&mut self) {
+        if let Some(connection) = self.connection.lock().unwrap().take() {
+            if let Some(topic) = &self.availability_topic {
+                let offline = PAYLOAD_OFFLINE.to_string();
+                if let Err(err) = connection.publish(topic, self.qos, offline, self.timeout) {
+                    error!("{}", err);
+                }
+            }
+            connection.close();
+        }
+    }
+}
+
+// Home Assistant only allows these characters in IDs.
+fn node_id() -> String {
+    let hostname: String = utils::get_hostname()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("findip_{}", hostname)
+}
+
+impl IpNotifier for MqttNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        match self.publish(ip) {
+            Ok(()) => debug!("Published {} to {}.", ip, self.topic),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{Ipv4Addr, TcpListener, TcpStream},
+        sync::Arc,
+        time::Instant,
+    };
+
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Published {
+        topic: String,
+        payload: String,
+        qos: u8,
+        retain: bool,
+    }
+
+    #[derive(Debug, Default)]
+    struct Broker {
+        will: Option<(String, String)>,
+        username: Option<String>,
+        password: Option<String>,
+        published: Vec<Published>,
+    }
+
+    fn read_string(body: &[u8], at: &mut usize) -> String {
+        let length = u16::from_be_bytes([body[*at], body[*at + 1]]) as usize;
+        let value = String::from_utf8_lossy(&body[*at + 2..*at + 2 + length]).to_string();
+        *at += 2 + length;
+        value
+    }
+
+    fn read_packet(stream: &mut TcpStream) -> Option<(u8, Vec<u8>)> {
+        let mut header = [0u8; 1];
+        stream.read_exact(&mut header).ok()?;
+        let (mut length, mut shift) = (0usize, 0);
+        loop {
+            let mut byte = [0u8; 1];
+            stream.read_exact(&mut byte).ok()?;
+            length += ((byte[0] & 0x7f) as usize) << shift;
+            shift += 7;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0u8; length];
+        stream.read_exact(&mut body).ok()?;
+        Some((header[0], body))
+    }
+
+    fn start_broker() -> (u16, Arc<Mutex<Broker>>) {
+        start_broker_answering(true, true)
+    }
+
+    // Just enough of an MQTT 3.1.1 broker to accept one client and record what it sends. It can
+    // leave out the CONNACK or the PUBACKs to play a broker that doesn't answer.
+    fn start_broker_answering(connack: bool, puback: bool) -> (u16, Arc<Mutex<Broker>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let broker = Arc::new(Mutex::new(Broker::default()));
+        let state = broker.clone();
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            while let Some((header, body)) = read_packet(&mut stream) {
+                match header >> 4 {
+                    // CONNECT
+                    1 => {
+                        let mut at = 0;
+                        read_string(&body, &mut at);
+                        let flags = body[at + 1];
+                        at += 4;
+                        read_string(&body, &mut at);
+                        let mut state = state.lock().unwrap();
+                        if flags & 0x04 != 0 {
+                            let topic = read_string(&body, &mut at);
+                            state.will = Some((topic, read_string(&body, &mut at)));
+                        }
+                        if flags & 0x80 != 0 {
+                            state.username = Some(read_string(&body, &mut at));
+                        }
+                        if flags & 0x40 != 0 {
+                            state.password = Some(read_string(&body, &mut at));
+                        }
+                        if connack {
+                            stream.write_all(&[0x20, 0x02, 0x00, 0x00]).unwrap();
+                        }
+                    }
+                    // PUBLISH
+                    3 => {
+                        let qos = (header >> 1) & 0x03;
+                        let mut at = 0;
+                        let topic = read_string(&body, &mut at);
+                        if qos > 0 {
+                            if puback {
+                                stream
+                                    .write_all(&[0x40, 0x02, body[at], body[at + 1]])
+                                    .unwrap();
+                            }
+                            at += 2;
+                        }
+                        state.lock().unwrap().published.push(Published {
+                            topic,
+                            payload: String::from_utf8_lossy(&body[at..]).to_string(),
+                            qos,
+                            retain: header & 0x01 != 0,
+                        });
+                    }
+                    // PINGREQ
+                    12 => stream.write_all(&[0xd0, 0x00]).unwrap(),
+                    // DISCONNECT
+                    14 => break,
+                    _ => {}
+                }
+            }
+        });
+
+        (port, broker)
+    }
+
+    fn wait_for(broker: &Arc<Mutex<Broker>>, count: usize) -> Vec<Published> {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(5) {
+            let published = broker.lock().unwrap().published.clone();
+            if published.len() >= count {
+                return published;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("the broker only got {:?}", broker.lock().unwrap().published);
+    }
+
+    fn ip() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))
+    }
+
+    #[test]
+    fn test_publishes_retained_ip() {
+        let (port, broker) = start_broker();
+        let notifier = MqttNotifier::new("127.0.0.1".to_string(), port, "findip/ip".to_string())
+            .with_credentials(
+                "findip".to_string(),
+                SecretRef::Value("hunter2".to_string()),
+            );
+
+        notifier.notify_success(ip());
+
+        let published = wait_for(&broker, 1);
+        assert_eq!(
+            published[0],
+            Published {
+                topic: "findip/ip".to_string(),
+                payload: "203.0.113.7".to_string(),
+                qos: 1,
+                retain: true,
+            }
+        );
+        let broker = broker.lock().unwrap();
+        assert_eq!(broker.username, Some("findip".to_string()));
+        assert_eq!(broker.password, Some("hunter2".to_string()));
+        assert_eq!(broker.will, None);
+    }
+
+    #[test]
+    fn test_home_assistant_discovery_and_availability() {
+        let (port, broker) = start_broker();
+        let notifier = MqttNotifier::new("127.0.0.1".to_string(), port, "findip/ip".to_string())
+            .with_qos(QoS::AtMostOnce)
+            .with_home_assistant_discovery(DEFAULT_DISCOVERY_PREFIX.to_string())
+            .with_availability_topic("findip/availability".to_string());
+
+        notifier.notify_success(ip());
+
+        let published = wait_for(&broker, 3);
+        let discovery = published
+            .iter()
+            .find(|message| message.topic == notifier.discovery_topic().unwrap())
+            .unwrap();
+        let config: serde_json::Value = serde_json::from_str(&discovery.payload).unwrap();
+        assert!(discovery.retain);
+        assert_eq!(config["state_topic"], "findip/ip");
+        assert_eq!(config["availability_topic"], "findip/availability");
+        assert!(published.contains(&Published {
+            topic: "findip/availability".to_string(),
+            payload: PAYLOAD_ONLINE.to_string(),
+            qos: 0,
+            retain: true,
+        }));
+        assert_eq!(
+            broker.lock().unwrap().will,
+            Some((
+                "findip/availability".to_string(),
+                PAYLOAD_OFFLINE.to_string()
+            ))
+        );
+
+        drop(notifier);
+        let published = wait_for(&broker, 4);
+        assert_eq!(published[3].payload, PAYLOAD_OFFLINE);
+    }
+
+    #[test]
+    fn test_publish_waits_for_the_puback() {
+        let (port, broker) = start_broker();
+        let notifier = MqttNotifier::new("127.0.0.1".to_string(), port, "findip/ip".to_string());
+
+        notifier.publish(ip()).unwrap();
+
+        // Anything acknowledged has already reached the broker.
+        assert_eq!(broker.lock().unwrap().published.len(), 1);
+    }
+
+    #[test]
+    fn test_missing_puback_is_an_error() {
+        let (port, _broker) = start_broker_answering(true, false);
+        let notifier = MqttNotifier::new("127.0.0.1".to_string(), port, "findip/ip".to_string())
+            .with_timeout(Duration::from_millis(300));
+
+        let err = notifier.publish(ip()).unwrap_err();
+        assert!(err.to_string().contains("didn't acknowledge"));
+    }
+
+    #[test]
+    fn test_missing_connack_is_an_error() {
+        let (port, _broker) = start_broker_answering(false, true);
+        let notifier = MqttNotifier::new("127.0.0.1".to_string(), port, "findip/ip".to_string())
+            .with_timeout(Duration::from_millis(300));
+
+        let err = notifier.publish(ip()).unwrap_err();
+        assert!(err.to_string().contains("didn't accept the connection"));
+    }
+
+    #[test]
+    fn test_refused_connection_is_an_error() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let notifier = MqttNotifier::new("127.0.0.1".to_string(), port, "findip/ip".to_string());
+
+        let started = Instant::now();
+        assert!(notifier.publish(ip()).is_err());
+        assert!(started.elapsed() < DEFAULT_TIMEOUT);
+    }
+
+    #[test]
+    fn test_discovery_topic() {
+        let notifier = MqttNotifier::new("localhost".to_string(), 1883, "findip/ip".to_string())
+            .with_home_assistant_discovery("homeassistant/".to_string());
+
+        assert_eq!(
+            notifier.discovery_topic().unwrap(),
+            format!("homeassistant/sensor/{}/public_ip/config", node_id())
+        );
+        assert!(node_id()
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'));
+    }
+
+    #[test]
+    fn test_missing_ca_certificate_is_an_error() {
+        let notifier = MqttNotifier::new("localhost".to_string(), 8883, "findip/ip".to_string())
+            .with_tls(Some("/nonexistent/ca.pem".to_string()));
+
+        assert!(notifier.publish(ip()).is_err());
+    }
+}
@@ -0,0 +1,287 @@
+use std::net::IpAddr;
+
+use log::info;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dns::{fqdn, RecordType},
+    errors::{ErrorReason, IpError},
+    ip_event::IpEvent,
+    notifier::IpNotifier,
+    utils,
+};
+
+pub const DEFAULT_SERVER_ID: &str = "localhost";
+
+const API_KEY_HEADER: &str = "X-API-Key";
+
+#[derive(Debug, Serialize)]
+struct RrsetPatch {
+    rrsets: Vec<Rrset>,
+}
+
+#[derive(Debug, Serialize)]
+struct Rrset {
+    name: String,
+    #[serde(rename = "type")]
+    record_type: RecordType,
+    ttl: u32,
+    changetype: &'static str,
+    records: Vec<Record>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    comments: Option<Vec<Comment>>,
+}
+
+#[derive(Debug, Serialize)]
+struct Record {
+    content: String,
+    disabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Comment {
+    content: String,
+    account: String,
+    modified_at: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiError {
+    error: String,
+}
+
+pub struct PowerDnsNotifier {
+    api_url: String,
+    api_key: String,
+    server_id: String,
+    zone: String,
+    record_names: Vec<String>,
+    ttl: u32,
+    comment: Option<String>,
+    client: Client,
+}
+
+impl PowerDnsNotifier {
+    pub fn new(
+        api_url: String,
+        api_key: String,
+        zone: String,
+        record_names: Vec<String>,
+        ttl: u32,
+    ) -> PowerDnsNotifier {
+        PowerDnsNotifier {
+            api_url: api_url.trim_end_matches('/').to_string(),
+            api_key,
+            server_id: DEFAULT_SERVER_ID.to_string(),
+            zone,
+            record_names,
+            ttl,
+            comment: None,
+            client: Client::builder().build().unwrap(),
+        }
+    }
+
+    pub fn with_server_id(mut self, server_id: String) -> PowerDnsNotifier {
+        self.server_id = server_id;
+        self
+    }
+
+    pub fn with_comment(mut self, comment: String) -> PowerDnsNotifier {
+        self.comment = Some(comment);
+        self
+    }
+
+    fn zone_url(&self) -> String {
+        format!(
+            "{}/api/v1/servers/{}/zones/{}",
+            self.api_url,
+            self.server_id,
+            fqdn(&self.zone)
+        )
+    }
+
+    fn rrset_patch(&self, ip: IpAddr) -> RrsetPatch {
+        let comments = self.comment.as_ref().map(|comment| {
+            vec![Comment {
+                content: utils::replace_tokens(
+                    comment.clone(),
+                    IpEvent::new(ip, None).token_values(),
+                ),
+                account: "findip".to_string(),
+                modified_at: utils::get_time_in_millis() as u64 / 1000,
+            }]
+        });
+
+        RrsetPatch {
+            rrsets: self
+                .record_names
+                .iter()
+                .map(|record_name| Rrset {
+                    name: fqdn(record_name),
+                    record_type: RecordType::for_ip(ip),
+                    ttl: self.ttl,
+                    changetype: "REPLACE",
+                    records: vec![Record {
+                        content: ip.to_string(),
+                        disabled: false,
+                    }],
+                    comments: comments.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    pub async fn update_records(&self, ip: IpAddr) -> Result<(), IpError> {
+        let response = self
+            .client
+            .patch(self.zone_url())
+            .header(API_KEY_HEADER, &self.api_key)
+            .json(&self.rrset_patch(ip))
+            .send()
+            .await
+            .map_err(|e| dns_error(e.to_string()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let body = response.text().await.unwrap_or_default();
+        let reason = serde_json::from_str::<ApiError>(&body)
+            .map(|error| error.error)
+            .unwrap_or(body);
+        Err(dns_error(format!(
+            "PowerDNS returned {}: {}",
+            status, reason
+        )))
+    }
+}
+
+fn dns_error(context: String) -> IpError {
+    IpError::new(ErrorReason::DnsUpdateFailed(context))
+}
+
+impl IpNotifier for PowerDnsNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        match runtime.block_on(self.update_records(ip)) {
+            Ok(()) => info!(
+                "Replaced {} in {} with {}.",
+                self.record_names.join(", "),
+                self.zone,
+                ip
+            ),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use httpmock::{Method::PATCH, MockServer};
+    use serde_json::json;
+
+    use super::*;
+
+    fn notifier(server: &MockServer) -> PowerDnsNotifier {
+        PowerDnsNotifier::new(
+            server.base_url(),
+            "secret".to_string(),
+            "example.com".to_string(),
+            vec![
+                "home.example.com".to_string(),
+                "vpn.example.com.".to_string(),
+            ],
+            60,
+        )
+    }
+
+    fn update(notifier: &PowerDnsNotifier, ip: IpAddr) -> Result<(), IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.update_records(ip))
+    }
+
+    #[test]
+    fn test_replaces_rrsets() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(PATCH)
+                .path("/api/v1/servers/localhost/zones/example.com.")
+                .header("X-API-Key", "secret")
+                .json_body(json!({
+                    "rrsets": [
+                        {
+                            "name": "home.example.com.",
+                            "type": "A",
+                            "ttl": 60,
+                            "changetype": "REPLACE",
+                            "records": [{ "content": "203.0.113.7", "disabled": false }]
+                        },
+                        {
+                            "name": "vpn.example.com.",
+                            "type": "A",
+                            "ttl": 60,
+                            "changetype": "REPLACE",
+                            "records": [{ "content": "203.0.113.7", "disabled": false }]
+                        }
+                    ]
+                }));
+            then.status(204);
+        });
+
+        assert!(update(
+            &notifier(&server),
+            IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))
+        )
+        .is_ok());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_adds_comments_and_uses_server_id() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(PATCH)
+                .path("/api/v1/servers/ns1/zones/example.com.")
+                .body_contains(r#""type":"AAAA""#)
+                .body_contains(r#""content":"set to 2001:db8::7 by findip""#)
+                .body_contains(r#""account":"findip""#);
+            then.status(204);
+        });
+        let notifier = notifier(&server)
+            .with_server_id("ns1".to_string())
+            .with_comment("set to {{TOKEN_IP_ADDRESS}} by findip".to_string());
+
+        assert!(update(
+            &notifier,
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 7))
+        )
+        .is_ok());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_api_errors_are_reported() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(PATCH);
+            then.status(422).json_body(json!({
+                "error": "RRset home.example.com. IN A: Conflicts with pre-existing RRset"
+            }));
+        });
+
+        let err = update(
+            &notifier(&server),
+            IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)),
+        )
+        .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("Conflicts with pre-existing RRset"));
+    }
+}
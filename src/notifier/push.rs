@@ -0,0 +1,325 @@
+use std::{collections::HashMap, net::IpAddr};
+
+use log::debug;
+use reqwest::Client;
+use serde_json::json;
+
+use crate::{
+    config_file::SecretRef,
+    errors::{ErrorReason, IpError},
+    ip_event::{IpEvent, IpEventType},
+    notifier::{message, IpNotifier},
+    secret::resolve_secret,
+    utils,
+};
+
+pub enum PushService {
+    // Publishes to a topic URL like `https://ntfy.sh/mytopic`.
+    Ntfy {
+        topic_url: String,
+        tags: Vec<String>,
+        auth: Option<NtfyAuth>,
+    },
+    Gotify {
+        server_url: String,
+        app_token: SecretRef,
+    },
+}
+
+pub enum NtfyAuth {
+    Token(SecretRef),
+    Basic {
+        username: String,
+        password: SecretRef,
+    },
+}
+
+pub struct PushNotifier {
+    service: PushService,
+    priorities: HashMap<IpEventType, u8>,
+    title: Option<String>,
+    client: Client,
+}
+
+impl PushNotifier {
+    pub fn new(service: PushService) -> PushNotifier {
+        PushNotifier {
+            service,
+            priorities: HashMap::new(),
+            title: None,
+            client: Client::builder().build().unwrap(),
+        }
+    }
+
+    // Replaces the default priority of the given event types.
+    pub fn with_priorities(mut self, priorities: HashMap<IpEventType, u8>) -> PushNotifier {
+        self.priorities = priorities;
+        self
+    }
+
+    // Replaces the event's title, with the tokens in `title` filled in.
+    pub fn with_title(mut self, title: String) -> PushNotifier {
+        self.title = Some(title);
+        self
+    }
+
+    // On ntfy's scale of 1 (min) to 5 (urgent), or Gotify's of 0 to 10 where 8 and up pops up.
+    pub fn priority(&self, event_type: IpEventType) -> u8 {
+        if let Some(priority) = self.priorities.get(&event_type) {
+            return *priority;
+        }
+
+        let (ntfy, gotify) = match event_type {
+            IpEventType::IpUnchanged => (2, 2),
+            IpEventType::IpChanged => (3, 5),
+            IpEventType::IpConflict | IpEventType::QueryFailed | IpEventType::DnsDrift => (4, 8),
+        };
+        match self.service {
+            PushService::Ntfy { .. } => ntfy,
+            PushService::Gotify { .. } => gotify,
+        }
+    }
+
+    async fn push(&self, event: &IpEvent) -> Result<(), IpError> {
+        let title = match &self.title {
+            Some(title) => utils::replace_tokens(title.clone(), event.token_values()),
+            None => message::title(event),
+        };
+        let body = message_body(event);
+        let priority = self.priority(event.event_type);
+
+        let request = match &self.service {
+            PushService::Ntfy {
+                topic_url,
+                tags,
+                auth,
+            } => {
+                let mut tags = tags.clone();
+                if event.event_type.is_error() {
+                    tags.push("warning".to_string());
+                }
+                let request = self
+                    .client
+                    .post(topic_url)
+                    .header("Title", title)
+                    .header("Priority", priority.to_string())
+                    .body(body);
+                let request = if tags.is_empty() {
+                    request
+                } else {
+                    request.header("Tags", tags.join(","))
+                };
+                match auth {
+                    Some(NtfyAuth::Token(token)) => request.bearer_auth(resolve_secret(token)?),
+                    Some(NtfyAuth::Basic { username, password }) => {
+                        request.basic_auth(username, Some(resolve_secret(password)?))
+                    }
+                    None => request,
+                }
+            }
+            PushService::Gotify {
+                server_url,
+                app_token,
+            } => self
+                .client
+                .post(format!("{}/message", server_url.trim_end_matches('/')))
+                .header("X-Gotify-Key", resolve_secret(app_token)?)
+                .json(&json!({
+                    "title": title,
+                    "message": body,
+                    "priority": priority,
+                })),
+        };
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| message_error(e.to_string()))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(message_error(format!(
+                "the push service returned {}: {}",
+                status, body
+            )));
+        }
+        Ok(())
+    }
+}
+
+// The title already names the event, so the body is just the details.
+fn message_body(event: &IpEvent) -> String {
+    let mut lines: Vec<String> = message::facts(event)
+        .into_iter()
+        .map(|(label, value)| format!("{}: {}", label, value))
+        .collect();
+    if let Some(details) = &event.message {
+        lines.push(details.clone());
+    }
+    lines.join("\n")
+}
+
+fn message_error(context: String) -> IpError {
+    IpError::new(ErrorReason::MessageFailed(context))
+}
+
+impl IpNotifier for PushNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        self.notify_event(&IpEvent::new(ip, None));
+    }
+
+    fn notify_event(&self, event: &IpEvent) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        match runtime.block_on(self.push(event)) {
+            Ok(()) => debug!("Pushed the {} event.", event.event_type),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use httpmock::{Method::POST, MockServer};
+
+    use super::*;
+
+    fn conflict() -> IpEvent {
+        IpEvent::from_error(IpError::new(ErrorReason::IpConflict(vec![
+            "203.0.113.7".to_string(),
+            "198.51.100.1".to_string(),
+        ])))
+    }
+
+    fn push(notifier: &PushNotifier, event: &IpEvent) -> Result<(), IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.push(event))
+    }
+
+    fn ntfy(topic_url: String, auth: Option<NtfyAuth>) -> PushNotifier {
+        PushNotifier::new(PushService::Ntfy {
+            topic_url,
+            tags: vec!["globe_with_meridians".to_string()],
+            auth,
+        })
+    }
+
+    #[test]
+    fn test_priorities() {
+        let ntfy = ntfy("http://localhost/findip".to_string(), None)
+            .with_priorities(vec![(IpEventType::IpUnchanged, 1)].into_iter().collect());
+        let gotify = PushNotifier::new(PushService::Gotify {
+            server_url: "http://localhost".to_string(),
+            app_token: SecretRef::Value("token".to_string()),
+        });
+
+        assert_eq!(ntfy.priority(IpEventType::IpUnchanged), 1);
+        assert_eq!(ntfy.priority(IpEventType::IpChanged), 3);
+        assert_eq!(ntfy.priority(IpEventType::IpConflict), 4);
+        assert_eq!(gotify.priority(IpEventType::IpChanged), 5);
+        assert_eq!(gotify.priority(IpEventType::QueryFailed), 8);
+    }
+
+    #[test]
+    fn test_publishes_to_ntfy_topic() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/findip")
+                .header("Title", "IP services disagree about the public IP of nas")
+                .header("Priority", "4")
+                .header("Tags", "globe_with_meridians,warning")
+                .header("Authorization", "Bearer tk_secret")
+                .body_contains("Reported IPs: 203.0.113.7, 198.51.100.1");
+            then.status(200).json_body(json!({ "id": "abc" }));
+        });
+        let mut event = conflict();
+        event.hostname = "nas".to_string();
+        let notifier = ntfy(
+            server.url("/findip"),
+            Some(NtfyAuth::Token(SecretRef::Value("tk_secret".to_string()))),
+        );
+
+        assert!(push(&notifier, &event).is_ok());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_ntfy_title_with_tokens() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/findip")
+                .header("Title", "nas is now 203.0.113.7");
+            then.status(200);
+        });
+        let mut event = IpEvent::sample_changed();
+        event.hostname = "nas".to_string();
+        let notifier = ntfy(server.url("/findip"), None)
+            .with_title("{{TOKEN_HOSTNAME}} is now {{TOKEN_IP_ADDRESS}}".to_string());
+
+        assert!(push(&notifier, &event).is_ok());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_ntfy_basic_auth() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/findip")
+                .header("Authorization", "Basic cGhpbDpteXBhc3M=")
+                .header("Priority", "3");
+            then.status(200);
+        });
+        let notifier = ntfy(
+            server.url("/findip"),
+            Some(NtfyAuth::Basic {
+                username: "phil".to_string(),
+                password: SecretRef::Value("mypass".to_string()),
+            }),
+        );
+
+        assert!(push(&notifier, &IpEvent::sample_changed()).is_ok());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_sends_gotify_message() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/message")
+                .header("X-Gotify-Key", "AbCdEf")
+                .body_contains(r#""priority":5"#)
+                .body_contains("New IP: 203.0.113.7");
+            then.status(200).json_body(json!({ "id": 1 }));
+        });
+        let notifier = PushNotifier::new(PushService::Gotify {
+            server_url: server.base_url(),
+            app_token: SecretRef::Value("AbCdEf".to_string()),
+        });
+
+        assert!(push(&notifier, &IpEvent::sample_changed()).is_ok());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_rejected_push_is_an_error() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/message");
+            then.status(401)
+                .json_body(json!({ "error": "Unauthorized", "errorCode": 401 }));
+        });
+        let notifier = PushNotifier::new(PushService::Gotify {
+            server_url: server.base_url(),
+            app_token: SecretRef::Value("wrong".to_string()),
+        });
+
+        assert!(push(&notifier, &IpEvent::sample_changed()).is_err());
+    }
+}
@@ -0,0 +1,363 @@
+use std::{net::IpAddr, sync::Mutex};
+
+use log::{debug, error};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config_file::SecretRef,
+    errors::{ErrorReason, IpError},
+    ip_event::{IpEvent, IpEventType},
+    notifier::{message, IpNotifier},
+    secret::resolve_secret,
+    state::StateFile,
+};
+
+pub const PUSHOVER_API_BASE_URL: &str = "https://api.pushover.net/1";
+
+pub const PRIORITY_LOW: i8 = -1;
+pub const PRIORITY_NORMAL: i8 = 0;
+pub const PRIORITY_HIGH: i8 = 1;
+pub const PRIORITY_EMERGENCY: i8 = 2;
+
+// Pushover's limits for emergency notifications.
+const MIN_RETRY_SECONDS: u32 = 30;
+const MAX_EXPIRE_SECONDS: u32 = 10800;
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    status: i32,
+    #[serde(default)]
+    errors: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PushoverState {
+    query_failures: u32,
+}
+
+pub struct PushoverNotifier {
+    app_token: SecretRef,
+    user_key: SecretRef,
+    devices: Vec<String>,
+    // Consecutive query failures before they're sent as emergencies, never when `None`.
+    emergency_after_failures: Option<u32>,
+    retry_seconds: u32,
+    expire_seconds: u32,
+    status_url: Option<String>,
+    api_base_url: String,
+    state: Mutex<PushoverState>,
+    state_file: Option<StateFile>,
+    client: Client,
+}
+
+impl PushoverNotifier {
+    pub fn new(app_token: SecretRef, user_key: SecretRef) -> PushoverNotifier {
+        PushoverNotifier {
+            app_token,
+            user_key,
+            devices: vec![],
+            emergency_after_failures: None,
+            retry_seconds: 60,
+            expire_seconds: 3600,
+            status_url: None,
+            api_base_url: PUSHOVER_API_BASE_URL.to_string(),
+            state: Mutex::new(PushoverState::default()),
+            state_file: None,
+            client: Client::builder().build().unwrap(),
+        }
+    }
+
+    // Sends to every device of the user when empty.
+    pub fn with_devices(mut self, devices: Vec<String>) -> PushoverNotifier {
+        self.devices = devices;
+        self
+    }
+
+    // Emergencies are repeated every `retry_seconds` until acknowledged or `expire_seconds` pass.
+    pub fn with_emergency(
+        mut self,
+        after_failures: u32,
+        retry_seconds: u32,
+        expire_seconds: u32,
+    ) -> PushoverNotifier {
+        self.emergency_after_failures = Some(after_failures.max(1));
+        self.retry_seconds = retry_seconds.max(MIN_RETRY_SECONDS);
+        self.expire_seconds = expire_seconds.min(MAX_EXPIRE_SECONDS);
+        self
+    }
+
+    pub fn with_status_url(mut self, status_url: String) -> PushoverNotifier {
+        self.status_url = Some(status_url);
+        self
+    }
+
+    pub fn with_api_base_url(mut self, api_base_url: String) -> PushoverNotifier {
+        self.api_base_url = api_base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    // Keeps the run of query failures in `state_file`, since every run of `findip` sees at most
+    // one of them.
+    pub fn with_state_file(mut self, state_file: StateFile) -> PushoverNotifier {
+        self.state = Mutex::new(state_file.load());
+        self.state_file = Some(state_file);
+        self
+    }
+
+    // Counts the event towards the run of query failures and returns its priority.
+    pub fn priority(&self, event: &IpEvent) -> i8 {
+        let mut state = self.state.lock().unwrap();
+        let query_failures = match event.event_type {
+            IpEventType::QueryFailed => state.query_failures.saturating_add(1),
+            IpEventType::IpChanged | IpEventType::IpUnchanged => 0,
+            IpEventType::IpConflict | IpEventType::DnsDrift => state.query_failures,
+        };
+        if query_failures != state.query_failures {
+            state.query_failures = query_failures;
+            if let Some(err) = self.state_file.as_ref().and_then(|f| f.save(&*state).err()) {
+                error!("{}, the run of failed queries will start over.", err);
+            }
+        }
+
+        let emergency =
+            matches!(self.emergency_after_failures, Some(after) if query_failures >= after);
+
+        match event.event_type {
+            IpEventType::IpUnchanged => PRIORITY_LOW,
+            IpEventType::IpChanged => PRIORITY_NORMAL,
+            IpEventType::QueryFailed if emergency => PRIORITY_EMERGENCY,
+            IpEventType::IpConflict | IpEventType::QueryFailed | IpEventType::DnsDrift => {
+                PRIORITY_HIGH
+            }
+        }
+    }
+
+    async fn send(&self, event: &IpEvent) -> Result<(), IpError> {
+        let priority = self.priority(event);
+        let mut form = vec![
+            ("token", resolve_secret(&self.app_token)?),
+            ("user", resolve_secret(&self.user_key)?),
+            ("title", message::title(event)),
+            ("message", message_body(event)),
+            ("priority", priority.to_string()),
+            ("timestamp", event.timestamp.timestamp().to_string()),
+        ];
+        if !self.devices.is_empty() {
+            form.push(("device", self.devices.join(",")));
+        }
+        if priority == PRIORITY_EMERGENCY {
+            form.push(("retry", self.retry_seconds.to_string()));
+            form.push(("expire", self.expire_seconds.to_string()));
+        }
+        if let Some(status_url) = &self.status_url {
+            form.push(("url", status_url.clone()));
+            form.push(("url_title", "Status page".to_string()));
+        }
+
+        let response = self
+            .client
+            .post(format!("{}/messages.json", self.api_base_url))
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| message_error(e.to_string()))?;
+
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        match serde_json::from_str::<ApiResponse>(&body) {
+            Ok(response) if status.is_success() && response.status == 1 => Ok(()),
+            Ok(response) => Err(message_error(format!(
+                "Pushover refused the message: {}",
+                response.errors.join(", ")
+            ))),
+            Err(_) => Err(message_error(format!(
+                "Pushover returned {}: {}",
+                status, body
+            ))),
+        }
+    }
+}
+
+fn message_body(event: &IpEvent) -> String {
+    let mut lines: Vec<String> = message::facts(event)
+        .into_iter()
+        .filter(|(label, _)| *label != "Time")
+        .map(|(label, value)| format!("{}: {}", label, value))
+        .collect();
+    if let Some(details) = &event.message {
+        lines.push(details.clone());
+    }
+    lines.join("\n")
+}
+
+fn message_error(context: String) -> IpError {
+    IpError::new(ErrorReason::MessageFailed(context))
+}
+
+impl IpNotifier for PushoverNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        self.notify_event(&IpEvent::new(ip, None));
+    }
+
+    fn notify_event(&self, event: &IpEvent) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        match runtime.block_on(self.send(event)) {
+            Ok(()) => debug!("Sent the {} event to Pushover.", event.event_type),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use httpmock::{Method::POST, MockServer};
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::config_file::Notifier;
+
+    fn notifier(server: &MockServer) -> PushoverNotifier {
+        PushoverNotifier::new(
+            SecretRef::Value("azGDORePK8gMaC0QOYAMyEEuzJnyUi".to_string()),
+            SecretRef::Value("uQiRzpo4DXghDmr9QzzfQu27cmVRsG".to_string()),
+        )
+        .with_api_base_url(server.base_url())
+    }
+
+    fn send(notifier: &PushoverNotifier, event: &IpEvent) -> Result<(), IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.send(event))
+    }
+
+    #[test]
+    fn test_repeated_query_failures_become_emergencies() {
+        let notifier = PushoverNotifier::new(
+            SecretRef::Value("".to_string()),
+            SecretRef::Value("".to_string()),
+        )
+        .with_emergency(2, 10, 86400);
+
+        assert_eq!(notifier.priority(&IpEvent::sample_failed()), PRIORITY_HIGH);
+        assert_eq!(
+            notifier.priority(&IpEvent::sample_failed()),
+            PRIORITY_EMERGENCY
+        );
+        assert_eq!(
+            notifier.priority(&IpEvent::sample_failed()),
+            PRIORITY_EMERGENCY
+        );
+        assert_eq!(
+            notifier.priority(&IpEvent::sample_changed()),
+            PRIORITY_NORMAL
+        );
+        assert_eq!(notifier.priority(&IpEvent::sample_failed()), PRIORITY_HIGH);
+        assert_eq!(notifier.retry_seconds, MIN_RETRY_SECONDS);
+        assert_eq!(notifier.expire_seconds, MAX_EXPIRE_SECONDS);
+    }
+
+    #[test]
+    fn test_failures_are_counted_across_runs() {
+        let state_directory = tempdir().unwrap();
+        let config = Notifier::Pushover {
+            app_token: SecretRef::Value("".to_string()),
+            user_key: SecretRef::Value("".to_string()),
+            devices: vec![],
+            emergency_after_failures: Some(3),
+            retry_seconds: 60,
+            expire_seconds: 3600,
+            status_url: None,
+        };
+        let run = || {
+            PushoverNotifier::new(
+                SecretRef::Value("".to_string()),
+                SecretRef::Value("".to_string()),
+            )
+            .with_emergency(3, 60, 3600)
+            .with_state_file(StateFile::for_notifier(state_directory.path(), &config))
+        };
+
+        assert_eq!(run().priority(&IpEvent::sample_failed()), PRIORITY_HIGH);
+        assert_eq!(run().priority(&IpEvent::sample_failed()), PRIORITY_HIGH);
+        assert_eq!(
+            run().priority(&IpEvent::sample_failed()),
+            PRIORITY_EMERGENCY
+        );
+        assert_eq!(run().priority(&IpEvent::sample_changed()), PRIORITY_NORMAL);
+        assert_eq!(run().priority(&IpEvent::sample_failed()), PRIORITY_HIGH);
+    }
+
+    #[test]
+    fn test_without_emergencies() {
+        let notifier = PushoverNotifier::new(
+            SecretRef::Value("".to_string()),
+            SecretRef::Value("".to_string()),
+        );
+
+        for _ in 0..5 {
+            assert_eq!(notifier.priority(&IpEvent::sample_failed()), PRIORITY_HIGH);
+        }
+    }
+
+    #[test]
+    fn test_sends_message() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/messages.json")
+                .x_www_form_urlencoded_tuple("token", "azGDORePK8gMaC0QOYAMyEEuzJnyUi")
+                .x_www_form_urlencoded_tuple("user", "uQiRzpo4DXghDmr9QzzfQu27cmVRsG")
+                .x_www_form_urlencoded_tuple("priority", "0")
+                .x_www_form_urlencoded_tuple("device", "phone,tablet")
+                .x_www_form_urlencoded_tuple("url", "https://status.example.com")
+                .x_www_form_urlencoded_tuple("url_title", "Status page");
+            then.status(200).json_body(
+                json!({ "status": 1, "request": "647d2300-702c-4b38-8b2f-d56326ae460b" }),
+            );
+        });
+        let notifier = notifier(&server)
+            .with_devices(vec!["phone".to_string(), "tablet".to_string()])
+            .with_status_url("https://status.example.com".to_string());
+
+        assert!(send(&notifier, &IpEvent::sample_changed()).is_ok());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_emergencies_carry_retry_and_expire() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/messages.json")
+                .x_www_form_urlencoded_tuple("priority", "2")
+                .x_www_form_urlencoded_tuple("retry", "60")
+                .x_www_form_urlencoded_tuple("expire", "3600");
+            then.status(200)
+                .json_body(json!({ "status": 1, "receipt": "rLqVuqTRh62UzxtmqiaLzQmVcPgiCy" }));
+        });
+        let notifier = notifier(&server).with_emergency(1, 60, 3600);
+
+        assert!(send(&notifier, &IpEvent::sample_failed()).is_ok());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_refused_messages_are_errors() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/messages.json");
+            then.status(400).json_body(
+                json!({ "status": 0, "user": "invalid", "errors": ["user identifier is invalid"] }),
+            );
+        });
+
+        let err = send(&notifier(&server), &IpEvent::sample_changed()).unwrap_err();
+        assert!(err.to_string().contains("user identifier is invalid"));
+    }
+}
@@ -0,0 +1,371 @@
+use std::{net::IpAddr, time::Duration};
+
+use log::debug;
+use redis::{Client, ConnectionAddr, ConnectionInfo, Pipeline, RedisConnectionInfo};
+
+use crate::{
+    config_file::{RedisValueFormat, SecretRef},
+    errors::{ErrorReason, IpError},
+    ip_event::IpEvent,
+    notifier::IpNotifier,
+    secret::resolve_secret,
+};
+
+// Connects for every event, which is plenty for how often `findip` runs.
+pub struct RedisNotifier {
+    host: String,
+    port: u16,
+    key: String,
+    ttl: Option<Duration>,
+    value_format: RedisValueFormat,
+    channel: Option<String>,
+    username: Option<String>,
+    password: Option<SecretRef>,
+    tls: bool,
+    database: i64,
+    timeout: Duration,
+}
+
+impl RedisNotifier {
+    pub fn new(host: String, port: u16, key: String) -> RedisNotifier {
+        RedisNotifier {
+            host,
+            port,
+            key,
+            ttl: None,
+            value_format: RedisValueFormat::Ip,
+            channel: None,
+            username: None,
+            password: None,
+            tls: false,
+            database: 0,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    // The key expires unless it's set again within `ttl`.
+    pub fn with_ttl(mut self, ttl: Duration) -> RedisNotifier {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_value_format(mut self, value_format: RedisValueFormat) -> RedisNotifier {
+        self.value_format = value_format;
+        self
+    }
+
+    // Every event is published on `channel` as JSON, errors included.
+    pub fn with_channel(mut self, channel: String) -> RedisNotifier {
+        self.channel = Some(channel);
+        self
+    }
+
+    // Without a username the password is for the `default` user, as with `requirepass`.
+    pub fn with_credentials(
+        mut self,
+        username: Option<String>,
+        password: SecretRef,
+    ) -> RedisNotifier {
+        self.username = username;
+        self.password = Some(password);
+        self
+    }
+
+    pub fn with_tls(mut self) -> RedisNotifier {
+        self.tls = true;
+        self
+    }
+
+    pub fn with_database(mut self, database: i64) -> RedisNotifier {
+        self.database = database;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> RedisNotifier {
+        self.timeout = timeout;
+        self
+    }
+
+    fn connection_info(&self) -> Result<ConnectionInfo, IpError> {
+        let addr = if self.tls {
+            ConnectionAddr::TcpTls {
+                host: self.host.clone(),
+                port: self.port,
+                insecure: false,
+                tls_params: None,
+            }
+        } else {
+            ConnectionAddr::Tcp(self.host.clone(), self.port)
+        };
+        let password = match &self.password {
+            Some(password) => Some(resolve_secret(password)?),
+            None => None,
+        };
+
+        Ok(ConnectionInfo {
+            addr,
+            redis: RedisConnectionInfo {
+                db: self.database,
+                username: self.username.clone(),
+                password,
+            },
+        })
+    }
+
+    // Only events that know the current IP update the key.
+    pub fn pipeline(&self, event: &IpEvent) -> Pipeline {
+        let mut pipeline = redis::pipe();
+        if let (Some(ip), false) = (event.ip, event.event_type.is_error()) {
+            let value = match self.value_format {
+                RedisValueFormat::Ip => ip.to_string(),
+                RedisValueFormat::Json => serde_json::to_string(event).unwrap(),
+            };
+            let command = pipeline.cmd("SET").arg(&self.key).arg(value);
+            if let Some(ttl) = self.ttl {
+                command.arg("EX").arg(ttl.as_secs().max(1));
+            }
+            command.ignore();
+        }
+        if let Some(channel) = &self.channel {
+            pipeline
+                .cmd("PUBLISH")
+                .arg(channel)
+                .arg(serde_json::to_string(event).unwrap())
+                .ignore();
+        }
+        pipeline
+    }
+
+    pub fn send(&self, event: &IpEvent) -> Result<(), IpError> {
+        let pipeline = self.pipeline(event);
+        if pipeline.cmd_iter().next().is_none() {
+            return Ok(());
+        }
+
+        let mut connection = Client::open(self.connection_info()?)
+            .and_then(|client| client.get_connection_with_timeout(self.timeout))
+            .map_err(|e| message_error(format!("{}:{}", self.host, self.port), e))?;
+        connection
+            .set_read_timeout(Some(self.timeout))
+            .and_then(|()| connection.set_write_timeout(Some(self.timeout)))
+            .and_then(|()| pipeline.query::<()>(&mut connection))
+            .map_err(|e| message_error(format!("{}:{}", self.host, self.port), e))
+    }
+}
+
+fn message_error(server: String, err: redis::RedisError) -> IpError {
+    IpError::new(ErrorReason::MessageFailed(format!(
+        "Redis at {} failed: {}",
+        server, err
+    )))
+}
+
+impl IpNotifier for RedisNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        self.notify_event(&IpEvent::new(ip, None));
+    }
+
+    fn notify_event(&self, event: &IpEvent) {
+        match self.send(event) {
+            Ok(()) => debug!("Sent the {} event to Redis.", event.event_type),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::{Ipv4Addr, TcpListener},
+        process::{Child, Command, Stdio},
+        sync::{Arc, Mutex},
+        thread,
+        time::Instant,
+    };
+
+    use super::*;
+
+    // Just enough of a Redis server to accept one client and record its commands.
+    fn start_server() -> (u16, Arc<Mutex<Vec<Vec<String>>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let commands = Arc::new(Mutex::new(vec![]));
+        let received = commands.clone();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap_or(0) > 0 {
+                let count: usize = line.trim_end()[1..].parse().unwrap();
+                let mut command = vec![];
+                for _ in 0..count {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let length: usize = line.trim_end()[1..].parse().unwrap();
+                    let mut argument = vec![0u8; length + 2];
+                    reader.read_exact(&mut argument).unwrap();
+                    command.push(String::from_utf8_lossy(&argument[..length]).to_string());
+                }
+                let reply: &[u8] = if command[0] == "PUBLISH" {
+                    b":1\r\n"
+                } else {
+                    b"+OK\r\n"
+                };
+                writer.write_all(reply).unwrap();
+                // The client names itself with CLIENT SETINFO on connect, which isn't interesting here.
+                if command[0] != "CLIENT" {
+                    received.lock().unwrap().push(command);
+                }
+                line.clear();
+            }
+        });
+
+        (port, commands)
+    }
+
+    fn strings(command: &[&str]) -> Vec<String> {
+        command.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_sets_key_and_publishes() {
+        let (port, commands) = start_server();
+        let notifier = RedisNotifier::new("127.0.0.1".to_string(), port, "findip:ip".to_string())
+            .with_ttl(Duration::from_secs(900))
+            .with_channel("findip:events".to_string())
+            .with_credentials(
+                Some("findip".to_string()),
+                SecretRef::Value("hunter2".to_string()),
+            )
+            .with_database(2);
+        let event = IpEvent::sample_changed();
+
+        notifier.send(&event).unwrap();
+
+        let commands = commands.lock().unwrap();
+        assert_eq!(commands[0], strings(&["AUTH", "findip", "hunter2"]));
+        assert_eq!(commands[1], strings(&["SELECT", "2"]));
+        assert_eq!(
+            commands[2],
+            strings(&["SET", "findip:ip", "203.0.113.7", "EX", "900"])
+        );
+        assert_eq!(commands[3][..2], strings(&["PUBLISH", "findip:events"]));
+        assert_eq!(commands[3][2], serde_json::to_string(&event).unwrap());
+    }
+
+    #[test]
+    fn test_errors_are_only_published() {
+        let (port, commands) = start_server();
+        let notifier = RedisNotifier::new("127.0.0.1".to_string(), port, "findip:ip".to_string())
+            .with_channel("findip:events".to_string());
+
+        notifier.send(&IpEvent::sample_failed()).unwrap();
+
+        let commands = commands.lock().unwrap();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0][0], "PUBLISH");
+        assert!(commands[0][2].contains(r#""eventType":"query.failed""#));
+    }
+
+    #[test]
+    fn test_json_value() {
+        let notifier = RedisNotifier::new("localhost".to_string(), 6379, "findip:ip".to_string())
+            .with_value_format(RedisValueFormat::Json);
+        let event = IpEvent::sample_changed();
+
+        let packed = String::from_utf8(notifier.pipeline(&event).get_packed_pipeline()).unwrap();
+        assert!(packed.contains(&serde_json::to_string(&event).unwrap()));
+        assert!(!packed.contains("PUBLISH"));
+    }
+
+    #[test]
+    fn test_unreachable_server_is_an_error() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let notifier = RedisNotifier::new("127.0.0.1".to_string(), port, "findip:ip".to_string());
+
+        assert!(notifier.send(&IpEvent::sample_changed()).is_err());
+    }
+
+    struct RedisServer(Child);
+
+    impl Drop for RedisServer {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    // Starts a throwaway `redis-server` and waits for it to take connections.
+    fn start_redis_server(port: u16) -> RedisServer {
+        let child = Command::new("redis-server")
+            .args(&[
+                "--port",
+                &port.to_string(),
+                "--save",
+                "",
+                "--appendonly",
+                "no",
+            ])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("this test needs redis-server on the PATH");
+        let server = RedisServer(child);
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(5) {
+            if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                return server;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("redis-server didn't start listening on port {}", port);
+    }
+
+    // Runs against a real Redis. Needs `redis-server` on the PATH:
+    // `cargo test test_against_redis_server -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_against_redis_server() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let _server = start_redis_server(port);
+        let notifier = RedisNotifier::new("127.0.0.1".to_string(), port, "findip:ip".to_string())
+            .with_ttl(Duration::from_secs(900))
+            .with_channel("findip:events".to_string())
+            .with_database(1);
+        let mut connection = Client::open(format!("redis://127.0.0.1:{}/1", port))
+            .unwrap()
+            .get_connection()
+            .unwrap();
+        let mut pubsub = connection.as_pubsub();
+        pubsub.subscribe("findip:events").unwrap();
+
+        notifier.send(&IpEvent::sample_changed()).unwrap();
+
+        let message = pubsub.get_message().unwrap();
+        assert!(message
+            .get_payload::<String>()
+            .unwrap()
+            .contains(r#""ip":"203.0.113.7""#));
+        drop(pubsub);
+        let value: String = redis::cmd("GET")
+            .arg("findip:ip")
+            .query(&mut connection)
+            .unwrap();
+        let ttl: i64 = redis::cmd("TTL")
+            .arg("findip:ip")
+            .query(&mut connection)
+            .unwrap();
+        assert_eq!(value, "203.0.113.7");
+        assert!(ttl > 0 && ttl <= 900);
+    }
+}
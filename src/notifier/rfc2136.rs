@@ -0,0 +1,461 @@
+use std::{
+    net::{IpAddr, UdpSocket},
+    time::Duration,
+};
+
+use log::{info, warn};
+
+use crate::{
+    dns::{encode_name, fqdn, server_address, RecordType},
+    errors::{ErrorReason, IpError},
+    notifier::IpNotifier,
+    tsig::{self, TsigKey},
+    utils,
+};
+
+const OPCODE_UPDATE: u16 = 5;
+const CLASS_IN: u16 = 1;
+const CLASS_ANY: u16 = 255;
+const TYPE_SOA: u16 = 6;
+const MAX_MESSAGE_SIZE: usize = 4096;
+
+pub struct Rfc2136Notifier {
+    server: String,
+    zone: String,
+    record_names: Vec<String>,
+    ttl: u32,
+    key: TsigKey,
+    timeout: Duration,
+}
+
+impl Rfc2136Notifier {
+    pub fn new(
+        server: String,
+        zone: String,
+        record_names: Vec<String>,
+        ttl: u32,
+        key: TsigKey,
+    ) -> Rfc2136Notifier {
+        Rfc2136Notifier {
+            server,
+            zone,
+            record_names,
+            ttl,
+            key,
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Rfc2136Notifier {
+        self.timeout = timeout;
+        self
+    }
+
+    // Deletes the existing record set for every name and adds the new address in the same
+    // message, so the server applies all of it or none of it.
+    fn build_update(&self, id: u16, ip: IpAddr) -> Vec<u8> {
+        let record_type = RecordType::for_ip(ip);
+        let rdata = match ip {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        };
+
+        let mut message = Vec::with_capacity(512);
+        message.extend_from_slice(&id.to_be_bytes());
+        message.extend_from_slice(&(OPCODE_UPDATE << 11).to_be_bytes());
+        for count in [1, 0, 2 * self.record_names.len() as u16, 0].iter() {
+            message.extend_from_slice(&count.to_be_bytes());
+        }
+
+        message.extend(encode_name(&fqdn(&self.zone)));
+        message.extend_from_slice(&TYPE_SOA.to_be_bytes());
+        message.extend_from_slice(&CLASS_IN.to_be_bytes());
+
+        for record_name in self.record_names.iter() {
+            let name = encode_name(&fqdn(record_name));
+
+            message.extend_from_slice(&name);
+            message.extend_from_slice(&record_type.code().to_be_bytes());
+            message.extend_from_slice(&CLASS_ANY.to_be_bytes());
+            message.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+
+            message.extend_from_slice(&name);
+            message.extend_from_slice(&record_type.code().to_be_bytes());
+            message.extend_from_slice(&CLASS_IN.to_be_bytes());
+            message.extend_from_slice(&self.ttl.to_be_bytes());
+            message.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            message.extend_from_slice(&rdata);
+        }
+
+        message
+    }
+
+    pub fn update_records(&self, ip: IpAddr) -> Result<(), IpError> {
+        let random = uuid::Uuid::new_v4();
+        let id = u16::from_be_bytes([random.as_bytes()[0], random.as_bytes()[1]]);
+        let mut request = self.build_update(id, ip);
+        let request_mac = tsig::sign(
+            &self.key,
+            &mut request,
+            utils::get_time_in_millis() as u64 / 1000,
+        );
+
+        let response = self.exchange(id, &request)?;
+        let response_code = response[3] & 0x0F;
+        // Anyone can send an answer with any response code, so it only counts once the
+        // signature shows it came from the server.
+        if let Err(err) = tsig::verify_response(&self.key, &response, &request_mac) {
+            warn!(
+                "{} answered the update with an unverified {}.",
+                self.server,
+                response_code_name(response_code)
+            );
+            return Err(err);
+        }
+        if response_code != 0 {
+            return Err(dns_error(format!(
+                "{} answered the update with {}",
+                self.server,
+                response_code_name(response_code)
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn exchange(&self, id: u16, request: &[u8]) -> Result<Vec<u8>, IpError> {
+        let server = server_address(&self.server)?;
+        let bind_address = if server.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let io_error = |e: std::io::Error| dns_error(format!("{}: {}", self.server, e));
+
+        let socket = UdpSocket::bind(bind_address).map_err(io_error)?;
+        socket
+            .set_read_timeout(Some(self.timeout))
+            .map_err(io_error)?;
+        socket.connect(server).map_err(io_error)?;
+        socket.send(request).map_err(io_error)?;
+
+        let mut buffer = vec![0u8; MAX_MESSAGE_SIZE];
+        loop {
+            let length = socket.recv(&mut buffer).map_err(io_error)?;
+            // Anything that isn't the answer to this request is dropped.
+            if length >= 12 && buffer[..2] == id.to_be_bytes() && buffer[2] & 0x80 != 0 {
+                buffer.truncate(length);
+                return Ok(buffer);
+            }
+        }
+    }
+}
+
+fn dns_error(context: String) -> IpError {
+    IpError::new(ErrorReason::DnsUpdateFailed(context))
+}
+
+fn response_code_name(response_code: u8) -> String {
+    match response_code {
+        0 => "NOERROR".to_string(),
+        1 => "FORMERR".to_string(),
+        2 => "SERVFAIL".to_string(),
+        3 => "NXDOMAIN".to_string(),
+        4 => "NOTIMP".to_string(),
+        5 => "REFUSED".to_string(),
+        6 => "YXDOMAIN".to_string(),
+        7 => "YXRRSET".to_string(),
+        8 => "NXRRSET".to_string(),
+        9 => "NOTAUTH".to_string(),
+        10 => "NOTZONE".to_string(),
+        other => format!("RCODE {}", other),
+    }
+}
+
+impl IpNotifier for Rfc2136Notifier {
+    fn notify_success(&self, ip: IpAddr) {
+        match self.update_records(ip) {
+            Ok(()) => info!(
+                "{} updated {} to {}.",
+                self.server,
+                self.record_names.join(", "),
+                ip
+            ),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        net::{Ipv4Addr, Ipv6Addr},
+        sync::{Arc, Mutex},
+        thread,
+    };
+
+    use trust_dns_proto::{
+        op::{Message, MessageType, OpCode, ResponseCode},
+        rr::{
+            dnssec::rdata::{
+                tsig::{make_tsig_record, message_tbs, signed_bitmessage_to_buf, TsigAlgorithm},
+                DNSSECRData, TSIG,
+            },
+            DNSClass, Name, RData, RecordType as ProtoRecordType,
+        },
+    };
+
+    use super::*;
+
+    const SECRET: &[u8] = b"secret secret secret secret secr";
+
+    type Zone = Arc<Mutex<HashMap<(String, ProtoRecordType), Vec<String>>>>;
+
+    // A tiny authoritative server for example.com. that accepts TSIG signed updates, checks the
+    // signature with trust-dns and signs its answers the same way.
+    fn start_server(zone: Zone, requests: usize) -> String {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+
+        thread::spawn(move || {
+            let key_name = Name::from_ascii("findip-key.").unwrap();
+            let mut buffer = vec![0u8; MAX_MESSAGE_SIZE];
+
+            for _ in 0..requests {
+                let (length, peer) = socket.recv_from(&mut buffer).unwrap();
+                let request = &buffer[..length];
+                let message = Message::from_vec(request).unwrap();
+
+                let mut response = Message::new();
+                response
+                    .set_id(message.id())
+                    .set_message_type(MessageType::Response)
+                    .set_op_code(OpCode::Update);
+
+                let (tbs, record) = signed_bitmessage_to_buf(None, request, true).unwrap();
+                let request_tsig = match record.data() {
+                    Some(RData::DNSSEC(DNSSECRData::TSIG(tsig))) => tsig.clone(),
+                    _ => panic!("the update was not signed"),
+                };
+                if record.name() != &key_name
+                    || TsigAlgorithm::HmacSha256
+                        .verify_mac(SECRET, &tbs, request_tsig.mac())
+                        .is_err()
+                {
+                    response.set_response_code(ResponseCode::NotAuth);
+                    socket.send_to(&response.to_vec().unwrap(), peer).unwrap();
+                    continue;
+                }
+
+                assert_eq!(message.queries()[0].name().to_ascii(), "example.com.");
+                let mut zone = zone.lock().unwrap();
+                for update in message.name_servers() {
+                    let key = (update.name().to_ascii(), update.rr_type());
+                    match update.dns_class() {
+                        DNSClass::ANY => {
+                            zone.remove(&key);
+                        }
+                        _ => zone.entry(key).or_default().push(match update.data() {
+                            Some(RData::A(ip)) => ip.to_string(),
+                            Some(RData::AAAA(ip)) => ip.to_string(),
+                            other => panic!("unexpected record data {:?}", other),
+                        }),
+                    }
+                }
+
+                let pre_tsig = TSIG::new(
+                    TsigAlgorithm::HmacSha256,
+                    request_tsig.time(),
+                    request_tsig.fudge(),
+                    vec![],
+                    message.id(),
+                    0,
+                    vec![],
+                );
+                let tbs =
+                    message_tbs(Some(request_tsig.mac()), &response, &pre_tsig, &key_name).unwrap();
+                let mac = TsigAlgorithm::HmacSha256.mac_data(SECRET, &tbs).unwrap();
+                response.add_tsig(make_tsig_record(key_name.clone(), pre_tsig.set_mac(mac)));
+                socket.send_to(&response.to_vec().unwrap(), peer).unwrap();
+            }
+        });
+
+        address
+    }
+
+    fn notifier(server: String, secret: &[u8]) -> Rfc2136Notifier {
+        Rfc2136Notifier::new(
+            server,
+            "example.com".to_string(),
+            vec![
+                "home.example.com".to_string(),
+                "vpn.example.com.".to_string(),
+            ],
+            60,
+            TsigKey::new("findip-key".to_string(), secret.to_vec()),
+        )
+    }
+
+    #[test]
+    fn test_replaces_records() {
+        let zone = Zone::default();
+        zone.lock().unwrap().insert(
+            ("home.example.com.".to_string(), ProtoRecordType::A),
+            vec!["198.51.100.1".to_string(), "198.51.100.2".to_string()],
+        );
+        let server = start_server(zone.clone(), 2);
+        let notifier = notifier(server, SECRET);
+
+        notifier
+            .update_records(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)))
+            .unwrap();
+        notifier
+            .update_records(IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 7)))
+            .unwrap();
+
+        let zone = zone.lock().unwrap();
+        for name in ["home.example.com.", "vpn.example.com."].iter() {
+            assert_eq!(
+                zone[&(name.to_string(), ProtoRecordType::A)],
+                vec!["203.0.113.7".to_string()]
+            );
+            assert_eq!(
+                zone[&(name.to_string(), ProtoRecordType::AAAA)],
+                vec!["2001:db8::7".to_string()]
+            );
+        }
+    }
+
+    #[test]
+    fn test_wrong_key_is_an_error() {
+        let server = start_server(Zone::default(), 1);
+        let notifier = notifier(server, b"not the right secret");
+
+        let err = notifier
+            .update_records(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)))
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("TSIG: the response was not signed"));
+    }
+
+    #[test]
+    fn test_unsigned_success_is_not_trusted() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let mut buffer = vec![0u8; MAX_MESSAGE_SIZE];
+            let (length, peer) = socket.recv_from(&mut buffer).unwrap();
+            let request = Message::from_vec(&buffer[..length]).unwrap();
+            let mut response = Message::new();
+            response
+                .set_id(request.id())
+                .set_message_type(MessageType::Response)
+                .set_op_code(OpCode::Update)
+                .set_response_code(ResponseCode::NoError);
+            socket.send_to(&response.to_vec().unwrap(), peer).unwrap();
+        });
+
+        assert!(notifier(address, SECRET)
+            .update_records(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)))
+            .is_err());
+    }
+
+    #[test]
+    fn test_unreachable_server_times_out() {
+        // Bound but never answered.
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let notifier = notifier(socket.local_addr().unwrap().to_string(), SECRET)
+            .with_timeout(Duration::from_millis(100));
+
+        assert!(notifier
+            .update_records(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)))
+            .is_err());
+    }
+
+    // Runs against a real BIND. Needs `named` on the PATH:
+    // `cargo test test_updates_bind -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_updates_bind() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = |name: &str| directory.path().join(name).to_str().unwrap().to_string();
+        let port = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        std::fs::write(
+            path("findip-key.conf"),
+            format!(
+                "key \"findip-key\" {{\n\talgorithm hmac-sha256;\n\tsecret \"{}\";\n}};\n",
+                base64::encode(SECRET)
+            ),
+        )
+        .unwrap();
+        std::fs::write(
+            path("example.com.zone"),
+            "$TTL 300\n\
+             @ IN SOA ns1.example.com. hostmaster.example.com. 1 3600 600 86400 300\n\
+             @ IN NS ns1.example.com.\n\
+             ns1 IN A 127.0.0.1\n\
+             home IN A 198.51.100.1\n\
+             home IN A 198.51.100.2\n",
+        )
+        .unwrap();
+        std::fs::write(
+            path("named.conf"),
+            format!(
+                "options {{ directory \"{0}\"; pid-file \"{0}/named.pid\"; \
+                 listen-on port {1} {{ 127.0.0.1; }}; listen-on-v6 {{ none; }}; \
+                 recursion no; dnssec-validation no; }};\n\
+                 include \"{0}/findip-key.conf\";\n\
+                 zone \"example.com\" {{ type primary; file \"{0}/example.com.zone\"; \
+                 allow-update {{ key \"findip-key\"; }}; }};\n",
+                directory.path().to_str().unwrap(),
+                port
+            ),
+        )
+        .unwrap();
+
+        let mut named = std::process::Command::new("named")
+            .args(&["-g", "-c", &path("named.conf")])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .spawn()
+            .expect("this test needs BIND's named on the PATH");
+        let server = format!("127.0.0.1:{}", port);
+        let address = server_address(&server).unwrap();
+        let lookup = |name: &str, record_type: RecordType| {
+            crate::dns::resolve(address, name, record_type, Duration::from_millis(200))
+        };
+        let started = std::time::Instant::now();
+        while lookup("home.example.com.", RecordType::A).is_err() {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "named did not start"
+            );
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        let key = TsigKey::from_key_file(&path("findip-key.conf")).unwrap();
+        let result = Rfc2136Notifier::new(
+            server,
+            "example.com".to_string(),
+            vec!["home.example.com".to_string()],
+            60,
+            key,
+        )
+        .update_records(IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)));
+        let addresses = lookup("home.example.com.", RecordType::A);
+        let _ = named.kill();
+        let _ = named.wait();
+
+        result.unwrap();
+        assert_eq!(
+            addresses.unwrap(),
+            vec![IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))]
+        );
+    }
+}
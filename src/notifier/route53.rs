@@ -0,0 +1,375 @@
+use std::{net::IpAddr, time::Duration};
+
+use log::{debug, info};
+use rusoto_core::Region;
+use rusoto_route53::{
+    Change, ChangeBatch, ChangeInfo, ChangeResourceRecordSetsRequest, GetChangeRequest,
+    ResourceRecord, ResourceRecordSet, Route53, Route53Client,
+};
+use tokio::time::{sleep, Instant};
+
+use crate::{
+    dns::RecordType,
+    errors::{ErrorReason, IpError},
+    notifier::IpNotifier,
+    sdk::{get_route53_client, CustomStsProvider},
+};
+
+const IN_SYNC_STATUS: &str = "INSYNC";
+const SYNC_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct Route53Notifier {
+    access_key_id: String,
+    secret_access_key: String,
+    assume_role_arn: String,
+    region: Region,
+    hosted_zone_id: String,
+    record_names: Vec<String>,
+    ttl: u32,
+    sync_timeout: Option<Duration>,
+    sync_poll_interval: Duration,
+}
+
+impl Route53Notifier {
+    pub fn new(
+        access_key_id: String,
+        secret_access_key: String,
+        assume_role_arn: String,
+        region: Region,
+        hosted_zone_id: String,
+        record_names: Vec<String>,
+        ttl: u32,
+    ) -> Route53Notifier {
+        Route53Notifier {
+            access_key_id,
+            secret_access_key,
+            assume_role_arn,
+            region,
+            hosted_zone_id,
+            record_names,
+            ttl,
+            sync_timeout: None,
+            sync_poll_interval: SYNC_POLL_INTERVAL,
+        }
+    }
+
+    // Waits up to `timeout` for the change to reach every Route 53 name server.
+    pub fn with_wait_for_sync(mut self, timeout: Duration) -> Route53Notifier {
+        self.sync_timeout = Some(timeout);
+        self
+    }
+
+    // Only the Route 53 calls go to `endpoint`, the role is still assumed through the regular
+    // STS endpoint for the region.
+    pub fn with_endpoint(mut self, endpoint: String) -> Route53Notifier {
+        self.region = Region::Custom {
+            name: self.region.name().to_string(),
+            endpoint,
+        };
+        self
+    }
+
+    fn client(&self) -> Route53Client {
+        let sts_region = match &self.region {
+            Region::Custom { name, .. } => name.parse::<Region>().unwrap_or(Region::UsEast1),
+            region => region.clone(),
+        };
+        let credentials_provider = CustomStsProvider::new(
+            self.access_key_id.clone(),
+            self.secret_access_key.clone(),
+            self.assume_role_arn.clone(),
+            None,
+            sts_region,
+        );
+
+        get_route53_client(credentials_provider, self.region.clone())
+    }
+
+    pub async fn update_records(&self, client: &Route53Client, ip: IpAddr) -> Result<(), IpError> {
+        let record_type = RecordType::for_ip(ip);
+        let changes = self
+            .record_names
+            .iter()
+            .map(|record_name| Change {
+                action: "UPSERT".to_string(),
+                resource_record_set: ResourceRecordSet {
+                    name: record_name.clone(),
+                    type_: record_type.to_string(),
+                    ttl: Some(i64::from(self.ttl)),
+                    resource_records: Some(vec![ResourceRecord {
+                        value: ip.to_string(),
+                    }]),
+                    ..Default::default()
+                },
+            })
+            .collect();
+
+        let response = client
+            .change_resource_record_sets(ChangeResourceRecordSetsRequest {
+                hosted_zone_id: self.hosted_zone_id.clone(),
+                change_batch: ChangeBatch {
+                    changes,
+                    comment: Some(format!("findip update to {}", ip)),
+                },
+            })
+            .await
+            .map_err(|e| dns_error(e.to_string()))?;
+        info!(
+            "Submitted {} {} for {}, change {} is {}.",
+            record_type,
+            ip,
+            self.record_names.join(", "),
+            response.change_info.id,
+            response.change_info.status
+        );
+
+        match self.sync_timeout {
+            Some(timeout) => {
+                self.wait_for_sync(client, response.change_info, timeout)
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    async fn wait_for_sync(
+        &self,
+        client: &Route53Client,
+        mut change_info: ChangeInfo,
+        timeout: Duration,
+    ) -> Result<(), IpError> {
+        let deadline = Instant::now() + timeout;
+
+        while change_info.status != IN_SYNC_STATUS {
+            change_info = client
+                .get_change(GetChangeRequest {
+                    id: change_info.id.trim_start_matches("/change/").to_string(),
+                })
+                .await
+                .map_err(|e| dns_error(e.to_string()))?
+                .change_info;
+            debug!("Change {} is {}.", change_info.id, change_info.status);
+            if change_info.status == IN_SYNC_STATUS {
+                break;
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(dns_error(format!(
+                    "change {} was still {} after {}s",
+                    change_info.id,
+                    change_info.status,
+                    timeout.as_secs_f64()
+                )));
+            }
+            // A timeout shorter than the poll interval still gets a last look at the deadline.
+            sleep(self.sync_poll_interval.min(deadline - now)).await;
+        }
+
+        Ok(())
+    }
+}
+
+fn dns_error(context: String) -> IpError {
+    IpError::new(ErrorReason::DnsUpdateFailed(context))
+}
+
+impl IpNotifier for Route53Notifier {
+    fn notify_success(&self, ip: IpAddr) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let client = self.client();
+
+        match runtime.block_on(self.update_records(&client, ip)) {
+            Ok(()) => debug!("Route 53 records are up to date."),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, sync::Arc, thread};
+
+    use httpmock::{
+        Method::{GET, POST},
+        MockServer,
+    };
+    use rusoto_credential::StaticProvider;
+
+    use super::*;
+
+    fn change_info_xml(root: &str, status: &str) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<{0} xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+  <ChangeInfo>
+    <Id>/change/C2682N5HXP0BZ4</Id>
+    <Status>{1}</Status>
+    <SubmittedAt>2021-08-01T00:00:00.000Z</SubmittedAt>
+  </ChangeInfo>
+</{0}>"#,
+            root, status
+        )
+    }
+
+    fn setup(server: &MockServer) -> (Route53Notifier, Route53Client) {
+        let notifier = Route53Notifier::new(
+            "AKID".to_string(),
+            "secret".to_string(),
+            "arn:aws:iam::123456789012:role/findip".to_string(),
+            Region::UsEast1,
+            "Z1PA6795UKMFR9".to_string(),
+            vec!["home.example.com".to_string()],
+            60,
+        )
+        .with_endpoint(server.base_url());
+        let client = Route53Client::new_with(
+            Arc::new(rusoto_core::HttpClient::new().unwrap()),
+            StaticProvider::new_minimal("AKID".to_string(), "secret".to_string()),
+            notifier.region.clone(),
+        );
+
+        (notifier, client)
+    }
+
+    fn update(notifier: &Route53Notifier, client: &Route53Client) -> Result<(), IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.update_records(client, IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))))
+    }
+
+    #[test]
+    fn test_upserts_records() {
+        let server = MockServer::start();
+        let change = server.mock(|when, then| {
+            when.method(POST)
+                .path("/2013-04-01/hostedzone/Z1PA6795UKMFR9/rrset/")
+                .body_contains("<Action>UPSERT</Action>")
+                .body_contains("<Name>home.example.com</Name>")
+                .body_contains("<Type>A</Type>")
+                .body_contains("<TTL>60</TTL>")
+                .body_contains("<Value>203.0.113.7</Value>");
+            then.status(200).body(change_info_xml(
+                "ChangeResourceRecordSetsResponse",
+                "PENDING",
+            ));
+        });
+        let get_change = server.mock(|when, then| {
+            when.method(GET);
+            then.status(200);
+        });
+        let (notifier, client) = setup(&server);
+
+        assert!(update(&notifier, &client).is_ok());
+        change.assert();
+        get_change.assert_hits(0);
+    }
+
+    #[test]
+    fn test_waits_for_change_to_be_in_sync() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST);
+            then.status(200).body(change_info_xml(
+                "ChangeResourceRecordSetsResponse",
+                "PENDING",
+            ));
+        });
+        let get_change = server.mock(|when, then| {
+            when.method(GET).path("/2013-04-01/change/C2682N5HXP0BZ4");
+            then.status(200)
+                .body(change_info_xml("GetChangeResponse", "INSYNC"));
+        });
+        let (mut notifier, client) = setup(&server);
+        notifier = notifier.with_wait_for_sync(Duration::from_secs(5));
+        notifier.sync_poll_interval = Duration::from_millis(10);
+
+        assert!(update(&notifier, &client).is_ok());
+        get_change.assert();
+    }
+
+    #[test]
+    fn test_wait_for_sync_times_out() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST);
+            then.status(200).body(change_info_xml(
+                "ChangeResourceRecordSetsResponse",
+                "PENDING",
+            ));
+        });
+        server.mock(|when, then| {
+            when.method(GET);
+            then.status(200)
+                .body(change_info_xml("GetChangeResponse", "PENDING"));
+        });
+        let (mut notifier, client) = setup(&server);
+        notifier = notifier.with_wait_for_sync(Duration::from_millis(50));
+        notifier.sync_poll_interval = Duration::from_millis(10);
+
+        let err = update(&notifier, &client).unwrap_err();
+        assert!(err.to_string().contains("still PENDING"));
+    }
+
+    #[test]
+    fn test_timeout_shorter_than_poll_interval_still_polls() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST);
+            then.status(200).body(change_info_xml(
+                "ChangeResourceRecordSetsResponse",
+                "PENDING",
+            ));
+        });
+        let mut pending = server.mock(|when, then| {
+            when.method(GET).path("/2013-04-01/change/C2682N5HXP0BZ4");
+            then.status(200)
+                .body(change_info_xml("GetChangeResponse", "PENDING"));
+        });
+        let (notifier, client) = setup(&server);
+        // The default poll interval is longer than this, so the second look is at the deadline.
+        let notifier = notifier.with_wait_for_sync(Duration::from_secs(1));
+
+        thread::scope(|scope| {
+            let server = &server;
+            let in_sync = scope.spawn(move || {
+                while pending.hits() == 0 {
+                    thread::sleep(Duration::from_millis(10));
+                }
+                pending.delete();
+                server.mock(|when, then| {
+                    when.method(GET).path("/2013-04-01/change/C2682N5HXP0BZ4");
+                    then.status(200)
+                        .body(change_info_xml("GetChangeResponse", "INSYNC"));
+                })
+            });
+
+            assert!(update(&notifier, &client).is_ok());
+            in_sync.join().unwrap().assert();
+        });
+    }
+
+    #[test]
+    fn test_api_errors_are_reported() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST);
+            then.status(400).body(
+                r#"<?xml version="1.0"?>
+<ErrorResponse xmlns="https://route53.amazonaws.com/doc/2013-04-01/">
+  <Error>
+    <Type>Sender</Type>
+    <Code>InvalidChangeBatch</Code>
+    <Message>Invalid request</Message>
+  </Error>
+</ErrorResponse>"#,
+            );
+        });
+        let (notifier, client) = setup(&server);
+
+        assert!(matches!(
+            update(&notifier, &client).unwrap_err().reason(),
+            ErrorReason::DnsUpdateFailed(_)
+        ));
+    }
+}
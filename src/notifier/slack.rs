@@ -0,0 +1,263 @@
+use std::net::IpAddr;
+
+use log::debug;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    config_file::SecretRef,
+    errors::{ErrorReason, IpError},
+    ip_event::IpEvent,
+    notifier::{message, IpNotifier},
+    secret::resolve_secret,
+};
+
+pub const SLACK_API_BASE_URL: &str = "https://slack.com/api";
+
+pub enum SlackTarget {
+    Webhook(String),
+    Bot { token: SecretRef, channel: String },
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    ok: bool,
+    error: Option<String>,
+}
+
+pub struct SlackNotifier {
+    target: SlackTarget,
+    api_base_url: String,
+    client: Client,
+}
+
+impl SlackNotifier {
+    pub fn new(target: SlackTarget) -> SlackNotifier {
+        SlackNotifier {
+            target,
+            api_base_url: SLACK_API_BASE_URL.to_string(),
+            client: Client::builder().build().unwrap(),
+        }
+    }
+
+    pub fn with_api_base_url(mut self, api_base_url: String) -> SlackNotifier {
+        self.api_base_url = api_base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn blocks(event: &IpEvent) -> Value {
+        let title = message::escape_html(&message::title(event));
+        let fields: Vec<Value> = message::facts(event)
+            .into_iter()
+            .map(|(label, value)| {
+                let value = match label {
+                    "Time" => slack_date(event),
+                    "Host" => message::escape_html(&value),
+                    _ => format!("`{}`", message::escape_html(&value)),
+                };
+                json!({ "type": "mrkdwn", "text": format!("*{}*\n{}", label, value) })
+            })
+            .collect();
+
+        if !event.event_type.is_error() {
+            return json!([
+                { "type": "header", "text": { "type": "plain_text", "text": message::title(event) } },
+                { "type": "section", "fields": fields }
+            ]);
+        }
+
+        // Errors lead with what went wrong and keep the details below it.
+        let mut blocks = vec![json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": format!(":warning: *{}*", title) }
+        })];
+        if let Some(details) = &event.message {
+            blocks.push(json!({
+                "type": "section",
+                "text": { "type": "mrkdwn", "text": format!("```{}```", message::escape_html(details)) }
+            }));
+        }
+        blocks.push(json!({ "type": "divider" }));
+        blocks.push(json!({ "type": "section", "fields": fields }));
+        Value::Array(blocks)
+    }
+
+    async fn post(&self, event: &IpEvent) -> Result<(), IpError> {
+        let mut payload = json!({
+            "text": message::title(event),
+            "blocks": SlackNotifier::blocks(event),
+        });
+
+        let request = match &self.target {
+            SlackTarget::Webhook(url) => self.client.post(url),
+            SlackTarget::Bot { token, channel } => {
+                payload["channel"] = json!(channel);
+                self.client
+                    .post(format!("{}/chat.postMessage", self.api_base_url))
+                    .bearer_auth(resolve_secret(token)?)
+            }
+        };
+        let response = request
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| message_error(e.to_string()))?;
+
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        if !status.is_success() {
+            return Err(message_error(format!(
+                "Slack returned {}: {}",
+                status, body
+            )));
+        }
+
+        // The Web API answers 200 even when it refuses the message.
+        match (&self.target, serde_json::from_str::<ApiResponse>(&body)) {
+            (SlackTarget::Bot { .. }, Ok(response)) if !response.ok => Err(message_error(format!(
+                "Slack refused the message: {}",
+                response.error.unwrap_or_default()
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Shown in the reader's time zone, with the UTC time for clients that can't.
+fn slack_date(event: &IpEvent) -> String {
+    format!(
+        "<!date^{}^{{date_short_pretty}} {{time_secs}}|{}>",
+        event.timestamp.timestamp(),
+        message::timestamp(event)
+    )
+}
+
+fn message_error(context: String) -> IpError {
+    IpError::new(ErrorReason::MessageFailed(context))
+}
+
+impl IpNotifier for SlackNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        self.notify_event(&IpEvent::new(ip, None));
+    }
+
+    fn notify_event(&self, event: &IpEvent) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        match runtime.block_on(self.post(event)) {
+            Ok(()) => debug!("Posted the {} event to Slack.", event.event_type),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use httpmock::{Method::POST, MockServer};
+
+    use super::*;
+
+    fn conflict() -> IpEvent {
+        IpEvent::from_error(IpError::new(ErrorReason::IpConflict(vec![
+            "203.0.113.7".to_string(),
+            "198.51.100.1".to_string(),
+        ])))
+    }
+
+    fn post(notifier: &SlackNotifier, event: &IpEvent) -> Result<(), IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.post(event))
+    }
+
+    #[test]
+    fn test_change_blocks() {
+        let blocks = SlackNotifier::blocks(&IpEvent::sample_changed());
+
+        assert_eq!(blocks[0]["type"], "header");
+        let fields = blocks[1]["fields"].as_array().unwrap();
+        assert_eq!(fields[1]["text"], "*New IP*\n`203.0.113.7`");
+        assert_eq!(fields[2]["text"], "*Previous IP*\n`198.51.100.1`");
+        assert!(fields[3]["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("*Time*\n<!date^"));
+    }
+
+    #[test]
+    fn test_error_blocks() {
+        let blocks = SlackNotifier::blocks(&conflict());
+
+        assert!(blocks[0]["text"]["text"]
+            .as_str()
+            .unwrap()
+            .starts_with(":warning: *IP services disagree"));
+        assert!(blocks[1]["text"]["text"]
+            .as_str()
+            .unwrap()
+            .starts_with("```"));
+        assert_eq!(blocks[2]["type"], "divider");
+        assert_eq!(
+            blocks[3]["fields"][1]["text"],
+            "*Reported IPs*\n`203.0.113.7, 198.51.100.1`"
+        );
+    }
+
+    #[test]
+    fn test_posts_to_webhook() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/services/T000/B000/XXXX")
+                .body_contains(r#""text":"Public IP of"#)
+                .body_contains(r#""blocks":["#);
+            then.status(200).body("ok");
+        });
+        let notifier =
+            SlackNotifier::new(SlackTarget::Webhook(server.url("/services/T000/B000/XXXX")));
+
+        assert!(post(&notifier, &IpEvent::sample_changed()).is_ok());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_posts_with_bot_token() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/chat.postMessage")
+                .header("Authorization", "Bearer xoxb-token")
+                .body_contains(r##""channel":"#ops""##);
+            then.status(200).json_body(json!({ "ok": true }));
+        });
+        let notifier = SlackNotifier::new(SlackTarget::Bot {
+            token: SecretRef::Value("xoxb-token".to_string()),
+            channel: "#ops".to_string(),
+        })
+        .with_api_base_url(server.base_url());
+
+        assert!(post(&notifier, &IpEvent::sample_changed()).is_ok());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_refused_messages_are_errors() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/chat.postMessage");
+            then.status(200)
+                .json_body(json!({ "ok": false, "error": "channel_not_found" }));
+        });
+        let notifier = SlackNotifier::new(SlackTarget::Bot {
+            token: SecretRef::Value("xoxb-token".to_string()),
+            channel: "#nope".to_string(),
+        })
+        .with_api_base_url(server.base_url());
+
+        let err = post(&notifier, &conflict()).unwrap_err();
+        assert!(err.to_string().contains("channel_not_found"));
+    }
+}
@@ -0,0 +1,253 @@
+use std::{collections::HashMap, fs, net::IpAddr};
+
+use log::debug;
+use reqwest::Client;
+use serde_json::{json, Value};
+
+use crate::{
+    errors::{ErrorReason, IpError},
+    ip_event::{IpEvent, IpEventType},
+    notifier::{message, IpNotifier},
+    utils,
+};
+
+pub const ADAPTIVE_CARD_CONTENT_TYPE: &str = "application/vnd.microsoft.card.adaptive";
+
+pub struct TeamsNotifier {
+    webhook_url: String,
+    templates: HashMap<IpEventType, String>,
+    sources: Vec<String>,
+    client: Client,
+}
+
+impl TeamsNotifier {
+    pub fn new(webhook_url: String) -> TeamsNotifier {
+        TeamsNotifier {
+            webhook_url,
+            templates: HashMap::new(),
+            sources: vec![],
+            client: Client::builder().build().unwrap(),
+        }
+    }
+
+    // Paths of Adaptive Card JSON files that replace the built in card for an event type.
+    pub fn with_templates(mut self, templates: HashMap<IpEventType, String>) -> TeamsNotifier {
+        self.templates = templates;
+        self
+    }
+
+    // The IP services that were asked, shown on every card.
+    pub fn with_sources(mut self, sources: Vec<String>) -> TeamsNotifier {
+        self.sources = sources;
+        self
+    }
+
+    pub fn card(&self, event: &IpEvent) -> Result<Value, IpError> {
+        match self.templates.get(&event.event_type) {
+            Some(template_path) => render_template(template_path, event),
+            None => Ok(self.default_card(event)),
+        }
+    }
+
+    fn default_card(&self, event: &IpEvent) -> Value {
+        let mut facts: Vec<Value> = message::facts(event)
+            .into_iter()
+            .map(|(label, value)| json!({ "title": label, "value": value }))
+            .collect();
+        if !self.sources.is_empty() {
+            facts.push(json!({ "title": "Sources", "value": self.sources.join(", ") }));
+        }
+
+        let mut body = vec![json!({
+            "type": "TextBlock",
+            "text": message::title(event),
+            "size": "Medium",
+            "weight": "Bolder",
+            "color": if event.event_type.is_error() { "Attention" } else { "Default" },
+            "wrap": true,
+        })];
+        if let Some(details) = &event.message {
+            body.push(json!({
+                "type": "TextBlock",
+                "text": details,
+                "fontType": "Monospace",
+                "wrap": true,
+            }));
+        }
+        body.push(json!({ "type": "FactSet", "facts": facts }));
+
+        json!({
+            "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+            "type": "AdaptiveCard",
+            "version": "1.4",
+            "body": body,
+        })
+    }
+
+    async fn post(&self, event: &IpEvent) -> Result<(), IpError> {
+        let payload = json!({
+            "type": "message",
+            "attachments": [{
+                "contentType": ADAPTIVE_CARD_CONTENT_TYPE,
+                "contentUrl": null,
+                "content": self.card(event)?,
+            }],
+        });
+
+        let response = self
+            .client
+            .post(&self.webhook_url)
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| message_error(e.to_string()))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(message_error(format!(
+                "Teams returned {}: {}",
+                status, body
+            )));
+        }
+        Ok(())
+    }
+}
+
+// Token values are JSON escaped, so a template can put them inside any string.
+fn render_template(template_path: &str, event: &IpEvent) -> Result<Value, IpError> {
+    let template = fs::read_to_string(template_path)
+        .map_err(|_| IpError::new(ErrorReason::FileOpenFailed(template_path.to_string())))?;
+    let token_values = event
+        .token_values()
+        .into_iter()
+        .map(|(token, value)| {
+            let escaped = Value::String(value).to_string();
+            (token, escaped[1..escaped.len() - 1].to_string())
+        })
+        .collect();
+
+    serde_json::from_str(&utils::replace_tokens(template, token_values)).map_err(|e| {
+        message_error(format!(
+            "{} is not a valid Adaptive Card: {}",
+            template_path, e
+        ))
+    })
+}
+
+fn message_error(context: String) -> IpError {
+    IpError::new(ErrorReason::MessageFailed(context))
+}
+
+impl IpNotifier for TeamsNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        self.notify_event(&IpEvent::new(ip, None));
+    }
+
+    fn notify_event(&self, event: &IpEvent) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        match runtime.block_on(self.post(event)) {
+            Ok(()) => debug!("Posted the {} event to Teams.", event.event_type),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use httpmock::{Method::POST, MockServer};
+    use tempfile::tempdir;
+
+    use super::*;
+
+    fn post(notifier: &TeamsNotifier, event: &IpEvent) -> Result<(), IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.post(event))
+    }
+
+    #[test]
+    fn test_default_card() {
+        let notifier = TeamsNotifier::new("http://localhost".to_string()).with_sources(vec![
+            "https://api.ipify.org".to_string(),
+            "https://ifconfig.me/ip".to_string(),
+        ]);
+        let card = notifier.card(&IpEvent::sample_changed()).unwrap();
+        let facts = card["body"][1]["facts"].as_array().unwrap();
+
+        assert_eq!(card["type"], "AdaptiveCard");
+        assert_eq!(
+            facts[1],
+            json!({ "title": "New IP", "value": "203.0.113.7" })
+        );
+        assert_eq!(
+            facts[2],
+            json!({ "title": "Previous IP", "value": "198.51.100.1" })
+        );
+        assert_eq!(
+            facts[4],
+            json!({ "title": "Sources", "value": "https://api.ipify.org, https://ifconfig.me/ip" })
+        );
+    }
+
+    #[test]
+    fn test_template_for_event_type() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("changed.json");
+        fs::write(
+            &path,
+            r#"{ "type": "AdaptiveCard", "version": "1.4", "body": [{ "type": "TextBlock", "text": "Now {{TOKEN_IP_ADDRESS}} on \"{{TOKEN_HOSTNAME}}\"" }] }"#,
+        )
+        .unwrap();
+        let notifier = TeamsNotifier::new("http://localhost".to_string()).with_templates(
+            vec![(IpEventType::IpChanged, path.to_str().unwrap().to_string())]
+                .into_iter()
+                .collect(),
+        );
+        let mut event = IpEvent::sample_changed();
+        event.hostname = "nas \"one\"".to_string();
+
+        let card = notifier.card(&event).unwrap();
+        assert_eq!(
+            card["body"][0]["text"],
+            "Now 203.0.113.7 on \"nas \"one\"\""
+        );
+
+        // Other event types keep the built in card.
+        let unchanged = IpEvent::new(event.ip.unwrap(), event.ip);
+        assert!(notifier.card(&unchanged).unwrap()["body"][1]["facts"].is_array());
+    }
+
+    #[test]
+    fn test_invalid_template_is_an_error() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("broken.json");
+        fs::write(&path, "{ not json").unwrap();
+        let notifier = TeamsNotifier::new("http://localhost".to_string()).with_templates(
+            vec![(IpEventType::IpChanged, path.to_str().unwrap().to_string())]
+                .into_iter()
+                .collect(),
+        );
+
+        assert!(notifier.card(&IpEvent::sample_changed()).is_err());
+    }
+
+    #[test]
+    fn test_posts_to_webhook() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/workflows/abc/triggers/manual/paths/invoke")
+                .body_contains(r#""contentType":"application/vnd.microsoft.card.adaptive""#);
+            then.status(202);
+        });
+        let notifier =
+            TeamsNotifier::new(server.url("/workflows/abc/triggers/manual/paths/invoke"));
+
+        assert!(post(&notifier, &IpEvent::sample_changed()).is_ok());
+        mock.assert();
+    }
+}
@@ -0,0 +1,303 @@
+use std::net::IpAddr;
+
+use log::debug;
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    config_file::{SecretRef, TelegramParseMode},
+    errors::{ErrorReason, IpError},
+    ip_event::IpEvent,
+    notifier::{message, IpNotifier},
+    secret::resolve_secret,
+};
+
+pub const TELEGRAM_API_BASE_URL: &str = "https://api.telegram.org";
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    ok: bool,
+    description: Option<String>,
+}
+
+pub struct TelegramNotifier {
+    bot_token: SecretRef,
+    chat_ids: Vec<String>,
+    parse_mode: TelegramParseMode,
+    api_base_url: String,
+    client: Client,
+}
+
+impl TelegramNotifier {
+    pub fn new(bot_token: SecretRef, chat_ids: Vec<String>) -> TelegramNotifier {
+        TelegramNotifier {
+            bot_token,
+            chat_ids,
+            parse_mode: TelegramParseMode::Html,
+            api_base_url: TELEGRAM_API_BASE_URL.to_string(),
+            client: Client::builder().build().unwrap(),
+        }
+    }
+
+    pub fn with_parse_mode(mut self, parse_mode: TelegramParseMode) -> TelegramNotifier {
+        self.parse_mode = parse_mode;
+        self
+    }
+
+    pub fn with_api_base_url(mut self, api_base_url: String) -> TelegramNotifier {
+        self.api_base_url = api_base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn text(&self, event: &IpEvent) -> String {
+        let facts = message::facts(event);
+        match self.parse_mode {
+            TelegramParseMode::Html => {
+                let mut lines = vec![format!(
+                    "<b>{}</b>",
+                    message::escape_html(&message::title(event))
+                )];
+                lines.extend(facts.into_iter().map(|(label, value)| {
+                    format!(
+                        "<b>{}:</b> <code>{}</code>",
+                        label,
+                        message::escape_html(&value)
+                    )
+                }));
+                if let Some(details) = &event.message {
+                    lines.push(format!("<pre>{}</pre>", message::escape_html(details)));
+                }
+                lines.join("\n")
+            }
+            TelegramParseMode::MarkdownV2 => {
+                let mut lines = vec![format!("*{}*", escape_markdown(&message::title(event)))];
+                lines.extend(facts.into_iter().map(|(label, value)| {
+                    format!(
+                        "*{}:* `{}`",
+                        escape_markdown(label),
+                        escape_markdown_code(&value)
+                    )
+                }));
+                if let Some(details) = &event.message {
+                    lines.push(format!("```\n{}\n```", escape_markdown_code(details)));
+                }
+                lines.join("\n")
+            }
+        }
+    }
+
+    async fn send(&self, event: &IpEvent) -> Result<(), IpError> {
+        let url = format!(
+            "{}/bot{}/sendMessage",
+            self.api_base_url,
+            resolve_secret(&self.bot_token)?
+        );
+        let text = self.text(event);
+        let parse_mode = match self.parse_mode {
+            TelegramParseMode::Html => "HTML",
+            TelegramParseMode::MarkdownV2 => "MarkdownV2",
+        };
+
+        // Every chat is tried, the first failure is what gets reported.
+        let mut result = Ok(());
+        for chat_id in self.chat_ids.iter() {
+            let sent = self.send_to(&url, chat_id, &text, parse_mode, event).await;
+            if result.is_ok() {
+                result = sent;
+            }
+        }
+        result
+    }
+
+    async fn send_to(
+        &self,
+        url: &str,
+        chat_id: &str,
+        text: &str,
+        parse_mode: &str,
+        event: &IpEvent,
+    ) -> Result<(), IpError> {
+        let payload = json!({
+            "chat_id": chat_id,
+            "text": text,
+            "parse_mode": parse_mode,
+            "disable_notification": !event.event_type.is_error(),
+        });
+
+        // The URL holds the bot token, so it's left out of any error.
+        let response = self
+            .client
+            .post(url)
+            .json(&payload)
+            .send()
+            .await
+            .map_err(|e| message_error(e.without_url().to_string()))?;
+
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        match serde_json::from_str::<ApiResponse>(&body) {
+            Ok(response) if response.ok => Ok(()),
+            Ok(response) => Err(message_error(format!(
+                "Telegram refused the message for chat {}: {}",
+                chat_id,
+                response.description.unwrap_or_default()
+            ))),
+            Err(_) => Err(message_error(format!(
+                "Telegram returned {} for chat {}: {}",
+                status, chat_id, body
+            ))),
+        }
+    }
+}
+
+// Outside of code, MarkdownV2 reserves all of these, including the dots in every IPv4 address.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "_*[]()~`>#+-=|{}.!\\".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn escape_markdown_code(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('`', "\\`")
+}
+
+fn message_error(context: String) -> IpError {
+    IpError::new(ErrorReason::MessageFailed(context))
+}
+
+impl IpNotifier for TelegramNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        self.notify_event(&IpEvent::new(ip, None));
+    }
+
+    fn notify_event(&self, event: &IpEvent) {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        match runtime.block_on(self.send(event)) {
+            Ok(()) => debug!("Sent the {} event to Telegram.", event.event_type),
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use httpmock::{Method::POST, MockServer};
+
+    use super::*;
+
+    fn changed() -> IpEvent {
+        let mut event = IpEvent::sample_changed();
+        event.hostname = "nas-1.home".to_string();
+        event
+    }
+
+    fn notifier(server: &MockServer, chat_ids: Vec<&str>) -> TelegramNotifier {
+        TelegramNotifier::new(
+            SecretRef::Value("123:abc".to_string()),
+            chat_ids.into_iter().map(String::from).collect(),
+        )
+        .with_api_base_url(server.base_url())
+    }
+
+    fn send(notifier: &TelegramNotifier, event: &IpEvent) -> Result<(), IpError> {
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(notifier.send(event))
+    }
+
+    #[test]
+    fn test_markdown_escaping() {
+        assert_eq!(
+            escape_markdown("Public IP of nas-1.home changed"),
+            "Public IP of nas\\-1\\.home changed"
+        );
+        assert_eq!(escape_markdown_code("a`b\\c"), "a\\`b\\\\c");
+
+        let text = TelegramNotifier::new(SecretRef::Value("".to_string()), vec![])
+            .with_parse_mode(TelegramParseMode::MarkdownV2)
+            .text(&changed());
+        assert!(text.starts_with("*Public IP of nas\\-1\\.home changed*\n*Host:* `nas-1.home`\n*New IP:* `203.0.113.7`\n"));
+    }
+
+    #[test]
+    fn test_html_escaping() {
+        let mut event = changed();
+        event.hostname = "<nas> & co".to_string();
+
+        let text = TelegramNotifier::new(SecretRef::Value("".to_string()), vec![]).text(&event);
+        assert!(text.starts_with(
+            "<b>Public IP of &lt;nas&gt; &amp; co changed</b>\n<b>Host:</b> <code>&lt;nas&gt; &amp; co</code>\n"
+        ));
+    }
+
+    #[test]
+    fn test_sends_to_every_chat() {
+        let server = MockServer::start();
+        let first = server.mock(|when, then| {
+            when.method(POST)
+                .path("/bot123:abc/sendMessage")
+                .body_contains(r#""chat_id":"-1001234567890""#)
+                .body_contains(r#""disable_notification":true"#)
+                .body_contains(r#""parse_mode":"HTML""#);
+            then.status(200)
+                .json_body(json!({ "ok": true, "result": {} }));
+        });
+        let second = server.mock(|when, then| {
+            when.method(POST)
+                .path("/bot123:abc/sendMessage")
+                .body_contains(r#""chat_id":"@findip_alerts""#);
+            then.status(200)
+                .json_body(json!({ "ok": true, "result": {} }));
+        });
+
+        let notifier = notifier(&server, vec!["-1001234567890", "@findip_alerts"]);
+        assert!(send(&notifier, &changed()).is_ok());
+        first.assert();
+        second.assert();
+    }
+
+    #[test]
+    fn test_errors_are_not_silent() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST)
+                .path("/bot123:abc/sendMessage")
+                .body_contains(r#""disable_notification":false"#);
+            then.status(200)
+                .json_body(json!({ "ok": true, "result": {} }));
+        });
+        let event = IpEvent::from_error(IpError::new(ErrorReason::IpParseFailed(
+            "garbage".to_string(),
+        )));
+
+        assert!(send(&notifier(&server, vec!["42"]), &event).is_ok());
+        mock.assert();
+    }
+
+    #[test]
+    fn test_refused_messages_are_errors() {
+        let server = MockServer::start();
+        let mock = server.mock(|when, then| {
+            when.method(POST).path("/bot123:abc/sendMessage");
+            then.status(400).json_body(
+                json!({ "ok": false, "error_code": 400, "description": "Bad Request: chat not found" }),
+            );
+        });
+
+        let err = send(&notifier(&server, vec!["1", "2"]), &changed()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("chat 1: Bad Request: chat not found"));
+        assert!(!err.to_string().contains("123:abc"));
+        mock.assert_hits(2);
+    }
+}
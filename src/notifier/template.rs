@@ -0,0 +1,191 @@
+use std::{fs, net::IpAddr, path::Path};
+
+use log::{debug, info};
+
+use crate::{
+    command::CommandSpec,
+    errors::{ErrorReason, IpError},
+    ip_event::IpEvent,
+    notifier::{dispatch_event, file::write_atomically, IpNotifier},
+    utils,
+};
+
+pub struct TemplateNotifier {
+    template_path: String,
+    destination_path: String,
+    permissions: Option<u32>,
+    post_render_command: Option<CommandSpec>,
+}
+
+impl TemplateNotifier {
+    pub fn new(template_path: String, destination_path: String) -> TemplateNotifier {
+        TemplateNotifier {
+            template_path,
+            destination_path,
+            permissions: None,
+            post_render_command: None,
+        }
+    }
+
+    pub fn with_permissions(mut self, permissions: u32) -> TemplateNotifier {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    pub fn with_post_render_command(mut self, command: CommandSpec) -> TemplateNotifier {
+        self.post_render_command = Some(command);
+        self
+    }
+
+    fn render(&self, event: &IpEvent) -> Result<String, IpError> {
+        let template = fs::read_to_string(&self.template_path)
+            .map_err(|_| IpError::new(ErrorReason::FileOpenFailed(self.template_path.clone())))?;
+
+        Ok(utils::replace_tokens(template, event.token_values()))
+    }
+
+    // Returns whether the destination was written to.
+    fn render_to_destination(&self, event: &IpEvent) -> Result<bool, IpError> {
+        let rendered = self.render(event)?;
+
+        if let Ok(current) = fs::read_to_string(&self.destination_path) {
+            if current == rendered {
+                return Ok(false);
+            }
+        }
+
+        write_atomically(
+            Path::new(&self.destination_path),
+            rendered.as_bytes(),
+            self.permissions,
+        )
+        .map_err(|_| IpError::new(ErrorReason::FileWriteFailed(self.destination_path.clone())))?;
+
+        Ok(true)
+    }
+}
+
+impl IpNotifier for TemplateNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        self.notify_event(&IpEvent::new(ip, None));
+    }
+
+    fn notify_event(&self, event: &IpEvent) {
+        if event.error.is_some() || event.ip.is_none() {
+            return dispatch_event(self, event);
+        }
+
+        match self.render_to_destination(event) {
+            Ok(false) => debug!(
+                "{} is already up to date, skipping the write.",
+                self.destination_path
+            ),
+            Ok(true) => {
+                info!(
+                    "Rendered {} to {}.",
+                    self.template_path, self.destination_path
+                );
+                if let Some(command) = &self.post_render_command {
+                    match command.run() {
+                        Ok(output) => debug!("Post render command finished. Output: {}", output),
+                        Err(err) => IpNotifier::notify_error(self, err),
+                    }
+                }
+            }
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::Ipv4Addr, time::Duration};
+
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    fn ip(last_octet: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(127, 0, 0, last_octet))
+    }
+
+    fn setup(template: &str) -> (TempDir, String, String) {
+        let dir = tempdir().unwrap();
+        let template_path = dir.path().join("allow.conf.tmpl");
+        let destination_path = dir.path().join("allow.conf");
+        fs::write(&template_path, template).unwrap();
+
+        (
+            dir,
+            template_path.to_str().unwrap().to_string(),
+            destination_path.to_str().unwrap().to_string(),
+        )
+    }
+
+    #[test]
+    fn test_renders_template_to_destination() {
+        let (_dir, template_path, destination_path) = setup("allow {{TOKEN_IP_ADDRESS}};\n");
+
+        TemplateNotifier::new(template_path, destination_path.clone()).notify_success(ip(1));
+        assert_eq!(
+            fs::read_to_string(destination_path).unwrap(),
+            "allow 127.0.0.1;\n"
+        );
+    }
+
+    #[test]
+    fn test_skips_write_when_content_is_unchanged() {
+        let (_dir, template_path, destination_path) = setup("allow {{TOKEN_IP_ADDRESS}};\n");
+        let notifier = TemplateNotifier::new(template_path, destination_path);
+
+        assert!(notifier
+            .render_to_destination(&IpEvent::new(ip(1), None))
+            .unwrap());
+        assert!(!notifier
+            .render_to_destination(&IpEvent::new(ip(1), Some(ip(1))))
+            .unwrap());
+        assert!(notifier
+            .render_to_destination(&IpEvent::new(ip(2), Some(ip(1))))
+            .unwrap());
+    }
+
+    #[test]
+    fn test_missing_template_is_an_error() {
+        let dir = tempdir().unwrap();
+        let notifier = TemplateNotifier::new(
+            dir.path()
+                .join("missing.tmpl")
+                .to_str()
+                .unwrap()
+                .to_string(),
+            dir.path().join("out").to_str().unwrap().to_string(),
+        );
+
+        assert!(notifier
+            .render_to_destination(&IpEvent::new(ip(1), None))
+            .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_post_render_command_only_runs_after_a_write() {
+        let (dir, template_path, destination_path) = setup("allow {{TOKEN_IP_ADDRESS}};\n");
+        let marker = dir.path().join("reloads");
+        let command = CommandSpec::new(
+            "sh".to_string(),
+            vec![
+                "-c".to_string(),
+                format!("echo reload >> {}", marker.to_str().unwrap()),
+            ],
+            Duration::from_secs(5),
+        );
+        let notifier = TemplateNotifier::new(template_path, destination_path)
+            .with_post_render_command(command);
+
+        notifier.notify_event(&IpEvent::new(ip(1), None));
+        notifier.notify_event(&IpEvent::new(ip(1), Some(ip(1))));
+        notifier.notify_event(&IpEvent::new(ip(2), Some(ip(1))));
+
+        assert_eq!(fs::read_to_string(marker).unwrap(), "reload\nreload\n");
+    }
+}
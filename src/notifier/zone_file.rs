@@ -0,0 +1,200 @@
+use std::{fs, net::IpAddr, path::Path, time::Duration};
+
+use chrono::Local;
+use log::{debug, info};
+
+use crate::{
+    command::CommandSpec,
+    config_file::SerialFormat,
+    errors::{ErrorReason, IpError},
+    notifier::{file::write_atomically, IpNotifier},
+    zone_file,
+};
+
+pub struct ZoneFileNotifier {
+    zone_file_path: String,
+    zone: String,
+    record_names: Vec<String>,
+    serial_format: SerialFormat,
+    reload_command: Option<CommandSpec>,
+}
+
+impl ZoneFileNotifier {
+    pub fn new(
+        zone_file_path: String,
+        zone: String,
+        record_names: Vec<String>,
+    ) -> ZoneFileNotifier {
+        ZoneFileNotifier {
+            zone_file_path,
+            zone,
+            record_names,
+            serial_format: SerialFormat::Date,
+            reload_command: None,
+        }
+    }
+
+    pub fn with_serial_format(mut self, serial_format: SerialFormat) -> ZoneFileNotifier {
+        self.serial_format = serial_format;
+        self
+    }
+
+    // Runs after the zone file was changed, see `rndc_reload`.
+    pub fn with_reload_command(mut self, command: CommandSpec) -> ZoneFileNotifier {
+        self.reload_command = Some(command);
+        self
+    }
+
+    pub fn rndc_reload(&self, timeout: Duration) -> CommandSpec {
+        CommandSpec::new(
+            "rndc".to_string(),
+            vec![
+                "reload".to_string(),
+                self.zone.trim_end_matches('.').to_string(),
+            ],
+            timeout,
+        )
+    }
+
+    // Returns whether the zone file was written to.
+    pub fn update_zone_file(&self, ip: IpAddr) -> Result<bool, IpError> {
+        let contents = fs::read_to_string(&self.zone_file_path)
+            .map_err(|_| IpError::new(ErrorReason::FileOpenFailed(self.zone_file_path.clone())))?;
+
+        let updated = zone_file::update_addresses(
+            &contents,
+            &self.zone,
+            &self.record_names,
+            ip,
+            self.serial_format,
+            Local::now().date_naive(),
+        )
+        .map_err(|context| {
+            IpError::new(ErrorReason::DnsUpdateFailed(format!(
+                "{}: {}",
+                self.zone_file_path, context
+            )))
+        })?;
+
+        match updated {
+            Some(updated) => {
+                write_atomically(Path::new(&self.zone_file_path), updated.as_bytes(), None)
+                    .map_err(|_| {
+                        IpError::new(ErrorReason::FileWriteFailed(self.zone_file_path.clone()))
+                    })?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+impl IpNotifier for ZoneFileNotifier {
+    fn notify_success(&self, ip: IpAddr) {
+        match self.update_zone_file(ip) {
+            Ok(false) => debug!(
+                "{} already points at {}, skipping the write.",
+                self.zone_file_path, ip
+            ),
+            Ok(true) => {
+                info!("Updated {} to {}.", self.zone_file_path, ip);
+                if let Some(command) = &self.reload_command {
+                    match command.run() {
+                        Ok(output) => debug!("Reload command finished. Output: {}", output),
+                        Err(err) => IpNotifier::notify_error(self, err),
+                    }
+                }
+            }
+            Err(err) => IpNotifier::notify_error(self, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use tempfile::{tempdir, TempDir};
+
+    use super::*;
+
+    const ZONE: &str = "$TTL 3600
+@ IN SOA ns1.example.com. hostmaster.example.com. ( 7 3600 900 1209600 300 )
+home IN A 198.51.100.1 ; keep this comment
+";
+
+    fn setup() -> (TempDir, String) {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("db.example.com");
+        fs::write(&path, ZONE).unwrap();
+
+        (dir, path.to_str().unwrap().to_string())
+    }
+
+    fn notifier(path: &str) -> ZoneFileNotifier {
+        ZoneFileNotifier::new(
+            path.to_string(),
+            "example.com".to_string(),
+            vec!["home.example.com".to_string()],
+        )
+        .with_serial_format(SerialFormat::Counter)
+    }
+
+    fn ip() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7))
+    }
+
+    #[test]
+    fn test_updates_zone_file() {
+        let (_dir, path) = setup();
+
+        assert!(notifier(&path).update_zone_file(ip()).unwrap());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            ZONE.replace("( 7 ", "( 8 ")
+                .replace("198.51.100.1", "203.0.113.7")
+        );
+        assert!(!notifier(&path).update_zone_file(ip()).unwrap());
+    }
+
+    #[test]
+    fn test_reload_command_runs_after_a_change() {
+        let (dir, path) = setup();
+        let marker = dir.path().join("reloaded");
+        let notifier = notifier(&path).with_reload_command(CommandSpec::new(
+            "touch".to_string(),
+            vec![marker.to_str().unwrap().to_string()],
+            Duration::from_secs(5),
+        ));
+
+        notifier.notify_success(ip());
+        assert!(marker.exists());
+
+        fs::remove_file(&marker).unwrap();
+        notifier.notify_success(ip());
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn test_rndc_reload_command() {
+        let command = ZoneFileNotifier::new(
+            "db.example.com".to_string(),
+            "example.com.".to_string(),
+            vec![],
+        )
+        .rndc_reload(Duration::from_secs(30));
+
+        assert_eq!(command.program, "rndc");
+        assert_eq!(command.args, vec!["reload", "example.com"]);
+    }
+
+    #[test]
+    fn test_missing_zone_file_is_an_error() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("missing");
+
+        assert!(notifier(path.to_str().unwrap())
+            .update_zone_file(ip())
+            .is_err());
+    }
+}
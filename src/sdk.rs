@@ -3,6 +3,7 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use rusoto_core::Region;
 use rusoto_credential::{AwsCredentials, CredentialsError, ProvideAwsCredentials, StaticProvider};
+use rusoto_route53::Route53Client;
 use rusoto_s3::S3Client;
 use rusoto_sts::{AssumeRoleRequest, Sts, StsClient};
 
@@ -75,3 +76,13 @@ where
 
     S3Client::new_with(arced_client, credential_provider, region)
 }
+
+pub fn get_route53_client<P>(credential_provider: P, region: Region) -> Route53Client
+where
+    P: ProvideAwsCredentials + Sync + Send + 'static,
+{
+    let http_client = rusoto_core::HttpClient::new().unwrap();
+    let arced_client = Arc::new(http_client);
+
+    Route53Client::new_with(arced_client, credential_provider, region)
+}
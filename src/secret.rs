@@ -0,0 +1,60 @@
+use std::{env, fs};
+
+use crate::{
+    config_file::SecretRef,
+    errors::{ErrorReason, IpError},
+};
+
+// Looked up every time it's needed so a rotated secret is picked up without a restart.
+pub fn resolve_secret(secret: &SecretRef) -> Result<String, IpError> {
+    match secret {
+        SecretRef::Value(value) => Ok(value.clone()),
+        SecretRef::Env { env: name } => env::var(name).map_err(|_| {
+            IpError::new(ErrorReason::InvalidInput(format!(
+                "the environment variable {} is not set",
+                name
+            )))
+        }),
+        SecretRef::File { file } => fs::read_to_string(file)
+            .map(|contents| contents.trim_end_matches(&['\r', '\n'][..]).to_string())
+            .map_err(|_| IpError::new(ErrorReason::FileOpenFailed(file.clone()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    #[test]
+    fn test_resolve_value() {
+        let secret = SecretRef::Value("shhh".to_string());
+        assert_eq!(resolve_secret(&secret).unwrap(), "shhh");
+    }
+
+    #[test]
+    fn test_resolve_env() {
+        env::set_var("FINDIP_TEST_SECRET", "from env");
+        let secret = SecretRef::Env {
+            env: "FINDIP_TEST_SECRET".to_string(),
+        };
+        assert_eq!(resolve_secret(&secret).unwrap(), "from env");
+
+        let missing = SecretRef::Env {
+            env: "FINDIP_TEST_MISSING_SECRET".to_string(),
+        };
+        assert!(resolve_secret(&missing).is_err());
+    }
+
+    #[test]
+    fn test_resolve_file_strips_trailing_newline() {
+        let file = NamedTempFile::new().unwrap();
+        fs::write(file.path(), "from file\n").unwrap();
+        let secret = SecretRef::File {
+            file: file.path().to_str().unwrap().to_string(),
+        };
+
+        assert_eq!(resolve_secret(&secret).unwrap(), "from file");
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config_file::Notifier,
+    errors::{ErrorReason, IpError},
+    notifier::file::write_atomically,
+};
+
+// State a notifier keeps between runs, since `findip` runs once per process. The file is named
+// after a hash of the notifier's config, so any change to the config starts from a clean state.
+#[derive(Debug, Clone)]
+pub struct StateFile {
+    path: PathBuf,
+    prefix: String,
+}
+
+impl StateFile {
+    pub fn for_notifier(directory: &Path, notifier: &Notifier) -> StateFile {
+        // Going through a JSON value sorts map keys, so the hash doesn't change between runs.
+        let config = serde_json::to_value(notifier).unwrap();
+        let prefix = format!(
+            "{}-",
+            config["notifierType"]
+                .as_str()
+                .unwrap_or("notifier")
+                .to_lowercase()
+        );
+        let hash: String = Sha256::digest(config.to_string().as_bytes())
+            .iter()
+            .take(8)
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        StateFile {
+            path: directory.join(format!("{}{}.json", prefix, hash)),
+            prefix,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Starts from the default state when there's nothing saved for this config, removing whatever
+    // was saved for an earlier version of it.
+    pub fn load<T: DeserializeOwned + Default>(&self) -> T {
+        match fs::read(&self.path) {
+            Ok(contents) => serde_json::from_slice(&contents).unwrap_or_else(|e| {
+                warn!(
+                    "Ignoring the unreadable state in {}: {}",
+                    self.path.display(),
+                    e
+                );
+                T::default()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.remove_stale();
+                T::default()
+            }
+            Err(e) => {
+                warn!("Failed to read the state in {}: {}", self.path.display(), e);
+                T::default()
+            }
+        }
+    }
+
+    pub fn save<T: Serialize>(&self, state: &T) -> Result<(), IpError> {
+        let write_failed = |_| {
+            IpError::new(ErrorReason::FileWriteFailed(
+                self.path.display().to_string(),
+            ))
+        };
+
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory).map_err(write_failed)?;
+        }
+        write_atomically(&self.path, &serde_json::to_vec(state).unwrap(), None)
+            .map_err(write_failed)
+    }
+
+    fn remove_stale(&self) {
+        let entries = match self.path.parent().map(fs::read_dir) {
+            Some(Ok(entries)) => entries,
+            _ => return,
+        };
+
+        for path in entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+        {
+            let name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or("");
+            if name.starts_with(&self.prefix) && name.ends_with(".json") {
+                debug!(
+                    "Removing the state left by an earlier config in {}.",
+                    path.display()
+                );
+                let _ = fs::remove_file(&path);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::config_file::SecretRef;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Counter {
+        count: u32,
+    }
+
+    fn notifier(url: &str) -> Notifier {
+        Notifier::Dyndns2 {
+            url: url.to_string(),
+            username: "user".to_string(),
+            password: SecretRef::Value("pass".to_string()),
+            hostnames: vec!["home.example.com".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let directory = tempdir().unwrap();
+        let state_file = StateFile::for_notifier(directory.path(), &notifier("https://a"));
+
+        state_file.save(&Counter { count: 3 }).unwrap();
+
+        let reloaded = StateFile::for_notifier(directory.path(), &notifier("https://a"));
+        assert_eq!(reloaded.path(), state_file.path());
+        assert_eq!(reloaded.load::<Counter>(), Counter { count: 3 });
+    }
+
+    #[test]
+    fn test_changed_config_starts_clean() {
+        let directory = tempdir().unwrap();
+        let old = StateFile::for_notifier(directory.path(), &notifier("https://a"));
+        old.save(&Counter { count: 3 }).unwrap();
+
+        let new = StateFile::for_notifier(directory.path(), &notifier("https://b"));
+
+        assert_ne!(new.path(), old.path());
+        assert!(new
+            .path()
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("dyndns2-"));
+        assert_eq!(new.load::<Counter>(), Counter::default());
+        assert!(!old.path().exists());
+    }
+
+    #[test]
+    fn test_unreadable_state_is_ignored() {
+        let directory = tempdir().unwrap();
+        let state_file = StateFile::for_notifier(directory.path(), &notifier("https://a"));
+        fs::write(state_file.path(), "not json").unwrap();
+
+        assert_eq!(state_file.load::<Counter>(), Counter::default());
+    }
+}
@@ -0,0 +1,484 @@
+use std::{fs, sync::Arc, time::SystemTime};
+
+use reqwest::{tls, Certificate, Client, ClientBuilder, Identity};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier},
+    version::{TLS12, TLS13},
+    ClientConfig, PrivateKey, RootCertStore, ServerName, SupportedProtocolVersion,
+};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config_file::{TlsConfig, TlsVersion},
+    errors::{ErrorReason, IpError},
+};
+
+const SPKI_PIN_PREFIX: &str = "sha256/";
+
+pub fn build_client(tls_config: Option<&TlsConfig>) -> Result<Client, IpError> {
+    let builder = match tls_config {
+        Some(config) if needs_rustls(config) => {
+            Client::builder().use_preconfigured_tls(rustls_config(config)?)
+        }
+        Some(config) => configure_tls(Client::builder(), config)?,
+        None => Client::builder(),
+    };
+
+    builder
+        .build()
+        .map_err(|e| IpError::new(ErrorReason::TlsConfigFailed(e.to_string())))
+}
+
+// The native TLS backend can neither check pins during the handshake nor require TLS 1.3, so
+// clients that need either are built on rustls instead.
+fn needs_rustls(config: &TlsConfig) -> bool {
+    !config.spki_pins.is_empty() || config.min_tls_version == Some(TlsVersion::Tls13)
+}
+
+fn configure_tls(builder: ClientBuilder, config: &TlsConfig) -> Result<ClientBuilder, IpError> {
+    let mut builder = builder;
+
+    if let Some(identity) = load_identity(config)? {
+        builder = builder.identity(identity);
+    }
+
+    for ca_path in config.ca_certificates.iter() {
+        let pem = read_tls_file(ca_path)?;
+        let certificate = Certificate::from_pem(&pem)
+            .map_err(|e| tls_config_error(format!("{}: {}", ca_path, e)))?;
+        builder = builder.add_root_certificate(certificate);
+    }
+
+    if let Some(version) = config.min_tls_version.and_then(to_reqwest_version) {
+        builder = builder.min_tls_version(version);
+    }
+
+    Ok(builder)
+}
+
+fn rustls_config(config: &TlsConfig) -> Result<ClientConfig, IpError> {
+    if config.client_pkcs12.is_some() {
+        return Err(tls_config_error(
+            "clientPkcs12 can't be combined with spkiPins or a minTlsVersion of 1.3, use clientCertificate and clientKey instead".to_string(),
+        ));
+    }
+
+    let mut roots = RootCertStore::empty();
+    // Like the native backend, system certificates that can't be parsed are skipped.
+    if let Ok(certificates) = rustls_native_certs::load_native_certs() {
+        roots.add_parsable_certificates(
+            &certificates
+                .into_iter()
+                .map(|certificate| certificate.0)
+                .collect::<Vec<Vec<u8>>>(),
+        );
+    }
+    for ca_path in config.ca_certificates.iter() {
+        for certificate in read_pem_certificates(ca_path)? {
+            roots
+                .add(&certificate)
+                .map_err(|e| tls_config_error(format!("{}: {}", ca_path, e)))?;
+        }
+    }
+
+    // rustls doesn't speak anything older than TLS 1.2 to begin with.
+    let versions: &[&SupportedProtocolVersion] = match config.min_tls_version {
+        Some(TlsVersion::Tls13) => &[&TLS13],
+        _ => &[&TLS12, &TLS13],
+    };
+    let builder = ClientConfig::builder()
+        .with_safe_default_cipher_suites()
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(versions)
+        .map_err(|e| tls_config_error(e.to_string()))?
+        .with_custom_certificate_verifier(Arc::new(PinningVerifier {
+            inner: WebPkiVerifier::new(roots, None),
+            pins: config.spki_pins.clone(),
+        }));
+
+    match (&config.client_certificate, &config.client_key) {
+        (Some(cert_path), Some(key_path)) => builder
+            .with_client_auth_cert(
+                read_pem_certificates(cert_path)?,
+                read_pem_private_key(key_path)?,
+            )
+            .map_err(|e| tls_config_error(format!("{}: {}", cert_path, e))),
+        (None, None) => Ok(builder.with_no_client_auth()),
+        _ => Err(tls_config_error(
+            "clientCertificate and clientKey must be provided together".to_string(),
+        )),
+    }
+}
+
+// Verifies the chain and hostname as usual and then requires the server's key to be pinned,
+// all during the handshake, so nothing is ever sent to a server that doesn't match.
+struct PinningVerifier {
+    inner: WebPkiVerifier,
+    pins: Vec<String>,
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &rustls::Certificate,
+        intermediates: &[rustls::Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            scts,
+            ocsp_response,
+            now,
+        )?;
+        verify_spki_pins(&self.pins, Some(&end_entity.0))
+            .map_err(|e| rustls::Error::General(e.to_string()))?;
+        Ok(verified)
+    }
+}
+
+fn load_identity(config: &TlsConfig) -> Result<Option<Identity>, IpError> {
+    if let Some(pkcs12_path) = &config.client_pkcs12 {
+        let der = read_tls_file(pkcs12_path)?;
+        let password = config.client_pkcs12_password.clone().unwrap_or_default();
+
+        return Identity::from_pkcs12_der(&der, &password)
+            .map(Some)
+            .map_err(|e| tls_config_error(format!("{}: {}", pkcs12_path, e)));
+    }
+
+    match (&config.client_certificate, &config.client_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let cert = read_tls_file(cert_path)?;
+            let key = read_tls_file(key_path)?;
+
+            Identity::from_pkcs8_pem(&cert, &key)
+                .map(Some)
+                .map_err(|e| tls_config_error(format!("{}: {}", cert_path, e)))
+        }
+        (None, None) => Ok(None),
+        _ => Err(tls_config_error(
+            "clientCertificate and clientKey must be provided together".to_string(),
+        )),
+    }
+}
+
+fn read_tls_file(path: &str) -> Result<Vec<u8>, IpError> {
+    fs::read(path).map_err(|_| IpError::new(ErrorReason::FileOpenFailed(path.to_string())))
+}
+
+fn read_pem_certificates(path: &str) -> Result<Vec<rustls::Certificate>, IpError> {
+    let pem = read_tls_file(path)?;
+    let certificates = rustls_pemfile::certs(&mut pem.as_slice())
+        .map_err(|e| tls_config_error(format!("{}: {}", path, e)))?;
+    if certificates.is_empty() {
+        return Err(tls_config_error(format!("{}: no certificates found", path)));
+    }
+    Ok(certificates.into_iter().map(rustls::Certificate).collect())
+}
+
+fn read_pem_private_key(path: &str) -> Result<PrivateKey, IpError> {
+    let pem = read_tls_file(path)?;
+    rustls_pemfile::pkcs8_private_keys(&mut pem.as_slice())
+        .map_err(|e| tls_config_error(format!("{}: {}", path, e)))?
+        .into_iter()
+        .next()
+        .map(PrivateKey)
+        .ok_or_else(|| tls_config_error(format!("{}: no PKCS#8 private key found", path)))
+}
+
+fn tls_config_error(context: String) -> IpError {
+    IpError::new(ErrorReason::TlsConfigFailed(context))
+}
+
+// TLS 1.3 never gets here, see `needs_rustls`.
+fn to_reqwest_version(version: TlsVersion) -> Option<tls::Version> {
+    match version {
+        TlsVersion::Tls10 => Some(tls::Version::TLS_1_0),
+        TlsVersion::Tls11 => Some(tls::Version::TLS_1_1),
+        TlsVersion::Tls12 => Some(tls::Version::TLS_1_2),
+        TlsVersion::Tls13 => None,
+    }
+}
+
+pub fn verify_spki_pins(pins: &[String], peer_certificate: Option<&[u8]>) -> Result<(), IpError> {
+    if pins.is_empty() {
+        return Ok(());
+    }
+
+    let fingerprint = peer_certificate.and_then(spki_fingerprint).ok_or_else(|| {
+        IpError::new(ErrorReason::CertificatePinMismatch(
+            "the server did not present a certificate".to_string(),
+        ))
+    })?;
+
+    if pins
+        .iter()
+        .any(|pin| pin.trim_start_matches(SPKI_PIN_PREFIX) == fingerprint)
+    {
+        Ok(())
+    } else {
+        Err(IpError::new(ErrorReason::CertificatePinMismatch(format!(
+            "{}{} is not one of the configured pins",
+            SPKI_PIN_PREFIX, fingerprint
+        ))))
+    }
+}
+
+pub fn spki_fingerprint(certificate_der: &[u8]) -> Option<String> {
+    let spki = find_subject_public_key_info(certificate_der)?;
+    Some(base64::encode(Sha256::digest(spki)))
+}
+
+// Certificate ::= SEQUENCE { tbsCertificate, signatureAlgorithm, signatureValue }
+// TBSCertificate ::= SEQUENCE { [0] version OPTIONAL, serialNumber, signature, issuer,
+//                               validity, subject, subjectPublicKeyInfo, ... }
+fn find_subject_public_key_info(certificate_der: &[u8]) -> Option<&[u8]> {
+    let (certificate, _, _) = read_der_element(certificate_der)?;
+    let (tbs_certificate, _, _) = read_der_element(certificate)?;
+
+    let mut remaining = tbs_certificate;
+    let mut fields = Vec::new();
+    while !remaining.is_empty() {
+        let (_, element, rest) = read_der_element(remaining)?;
+        fields.push(element);
+        remaining = rest;
+    }
+
+    let offset = match fields.first() {
+        Some(field) if field[0] == 0xa0 => 1,
+        _ => 0,
+    };
+
+    fields.get(offset + 5).copied()
+}
+
+// Returns the contents of the first DER element, the whole encoded element and whatever follows it.
+fn read_der_element(input: &[u8]) -> Option<(&[u8], &[u8], &[u8])> {
+    let first_length_byte = *input.get(1)?;
+    let (length, header_length) = if first_length_byte < 0x80 {
+        (first_length_byte as usize, 2)
+    } else {
+        let length_bytes = (first_length_byte & 0x7f) as usize;
+        if length_bytes == 0 || length_bytes > 4 {
+            return None;
+        }
+        let length = input
+            .get(2..2 + length_bytes)?
+            .iter()
+            .fold(0usize, |acc, byte| (acc << 8) | *byte as usize);
+        (length, 2 + length_bytes)
+    };
+
+    let end = header_length.checked_add(length)?;
+    let contents = input.get(header_length..end)?;
+    Some((contents, &input[..end], &input[end..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::Mutex,
+        thread,
+    };
+
+    use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+    use super::*;
+
+    const TEST_CERTIFICATE: &[u8] = include_bytes!("../testfiles/tls/client.der");
+    const TEST_PIN: &str = "ZTFyAK+VQCmYIpf6EYe+t/R868KQ/f9hQnC+J8KphTE=";
+    const SERVER_PIN: &str = "ALMEQPXKKeNgEZ9f0muyudWr85N0uRH8TKqtS73e82k=";
+
+    fn test_tls_config() -> TlsConfig {
+        TlsConfig {
+            client_certificate: None,
+            client_key: None,
+            client_pkcs12: None,
+            client_pkcs12_password: None,
+            ca_certificates: vec![],
+            spki_pins: vec![],
+            min_tls_version: None,
+        }
+    }
+
+    fn pinned_tls_config(pin: &str) -> TlsConfig {
+        TlsConfig {
+            ca_certificates: vec!["testfiles/tls/ca.pem".to_string()],
+            spki_pins: vec![format!("sha256/{}", pin)],
+            ..test_tls_config()
+        }
+    }
+
+    // Serves a single HTTPS request with the certificate for `localhost` and `127.0.0.1` that
+    // `ca.pem` signed, and returns everything the client managed to send.
+    fn start_server(versions: &[&'static SupportedProtocolVersion]) -> (u16, Arc<Mutex<Vec<u8>>>) {
+        let config = ServerConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(versions)
+            .unwrap()
+            .with_no_client_auth()
+            .with_single_cert(
+                read_pem_certificates("testfiles/tls/server.pem").unwrap(),
+                read_pem_private_key("testfiles/tls/server.key").unwrap(),
+            )
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let received = Arc::new(Mutex::new(vec![]));
+        let request = received.clone();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let connection = ServerConnection::new(Arc::new(config)).unwrap();
+            let mut stream = StreamOwned::new(connection, stream);
+            let mut buffer = [0u8; 1024];
+            while let Ok(read) = stream.read(&mut buffer) {
+                if read == 0 {
+                    break;
+                }
+                let mut request = request.lock().unwrap();
+                request.extend_from_slice(&buffer[..read]);
+                if request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    let _ = stream.write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n");
+                    break;
+                }
+            }
+        });
+
+        (port, received)
+    }
+
+    fn get(tls_config: &TlsConfig, port: u16) -> Result<reqwest::StatusCode, reqwest::Error> {
+        let client = build_client(Some(tls_config)).unwrap();
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(
+                client
+                    .get(format!("https://127.0.0.1:{}/secret", port))
+                    .send(),
+            )
+            .map(|response| response.status())
+    }
+
+    #[test]
+    fn test_pinned_server_gets_the_request() {
+        let (port, received) = start_server(&[&TLS12, &TLS13]);
+
+        assert_eq!(get(&pinned_tls_config(SERVER_PIN), port).unwrap(), 200);
+        assert!(received.lock().unwrap().starts_with(b"GET /secret"));
+    }
+
+    #[test]
+    fn test_pin_mismatch_fails_the_handshake() {
+        let (port, received) = start_server(&[&TLS12, &TLS13]);
+
+        assert!(get(&pinned_tls_config(TEST_PIN), port).is_err());
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_min_tls_13_refuses_tls_12_servers() {
+        let (port, received) = start_server(&[&TLS12]);
+        let tls_config = TlsConfig {
+            ca_certificates: vec!["testfiles/tls/ca.pem".to_string()],
+            min_tls_version: Some(TlsVersion::Tls13),
+            ..test_tls_config()
+        };
+
+        assert!(get(&tls_config, port).is_err());
+        assert!(received.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_pins_with_pkcs12_is_an_error() {
+        let tls_config = TlsConfig {
+            client_pkcs12: Some("testfiles/tls/client.p12".to_string()),
+            ..pinned_tls_config(SERVER_PIN)
+        };
+
+        assert!(build_client(Some(&tls_config)).is_err());
+    }
+
+    #[test]
+    fn test_spki_fingerprint_matches_openssl() {
+        assert_eq!(
+            spki_fingerprint(TEST_CERTIFICATE),
+            Some(TEST_PIN.to_string())
+        );
+    }
+
+    #[test]
+    fn test_spki_fingerprint_with_garbage_input() {
+        assert_eq!(spki_fingerprint(&[0x30, 0x82, 0xff]), None);
+    }
+
+    #[test]
+    fn test_verify_spki_pins_with_no_pins() {
+        assert!(verify_spki_pins(&[], None).is_ok());
+    }
+
+    #[test]
+    fn test_verify_spki_pins_with_matching_pin() {
+        let pins = vec![format!("sha256/{}", TEST_PIN)];
+        assert!(verify_spki_pins(&pins, Some(TEST_CERTIFICATE)).is_ok());
+    }
+
+    #[test]
+    fn test_verify_spki_pins_with_unprefixed_pin() {
+        let pins = vec![TEST_PIN.to_string()];
+        assert!(verify_spki_pins(&pins, Some(TEST_CERTIFICATE)).is_ok());
+    }
+
+    #[test]
+    fn test_verify_spki_pins_with_mismatched_pin() {
+        let pins = vec!["sha256/AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_string()];
+        assert!(verify_spki_pins(&pins, Some(TEST_CERTIFICATE)).is_err());
+    }
+
+    #[test]
+    fn test_verify_spki_pins_without_peer_certificate() {
+        let pins = vec![TEST_PIN.to_string()];
+        assert!(verify_spki_pins(&pins, None).is_err());
+    }
+
+    #[test]
+    fn test_build_client_with_certificates() {
+        let tls_config = TlsConfig {
+            client_certificate: Some("testfiles/tls/client.pem".to_string()),
+            client_key: Some("testfiles/tls/client.key".to_string()),
+            ca_certificates: vec!["testfiles/tls/client.pem".to_string()],
+            min_tls_version: Some(TlsVersion::Tls12),
+            ..test_tls_config()
+        };
+
+        assert!(build_client(Some(&tls_config)).is_ok());
+    }
+
+    #[test]
+    fn test_build_client_with_certificate_but_no_key() {
+        let tls_config = TlsConfig {
+            client_certificate: Some("testfiles/tls/client.pem".to_string()),
+            ..test_tls_config()
+        };
+
+        assert!(build_client(Some(&tls_config)).is_err());
+    }
+
+    #[test]
+    fn test_build_client_with_missing_ca_file() {
+        let tls_config = TlsConfig {
+            ca_certificates: vec!["testfiles/tls/missing.pem".to_string()],
+            ..test_tls_config()
+        };
+
+        assert!(build_client(Some(&tls_config)).is_err());
+    }
+}
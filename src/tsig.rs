@@ -0,0 +1,355 @@
+use std::fs;
+
+use hmac::{Hmac, Mac, NewMac};
+use sha2::Sha256;
+
+use crate::{
+    dns::{encode_name, skip_name},
+    errors::{ErrorReason, IpError},
+    utils,
+};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const HMAC_SHA256: &str = "hmac-sha256";
+
+const TSIG_TYPE: u16 = 250;
+const CLASS_ANY: u16 = 255;
+const FUDGE_SECONDS: u16 = 300;
+const HEADER_LENGTH: usize = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TsigKey {
+    pub name: String,
+    pub secret: Vec<u8>,
+}
+
+impl TsigKey {
+    pub fn new(name: String, secret: Vec<u8>) -> TsigKey {
+        TsigKey { name, secret }
+    }
+
+    pub fn from_key_file(path: &str) -> Result<TsigKey, IpError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|_| IpError::new(ErrorReason::FileOpenFailed(path.to_string())))?;
+
+        TsigKey::parse(&contents).map_err(|context| {
+            IpError::new(ErrorReason::InvalidInput(format!("{}: {}", path, context)))
+        })
+    }
+
+    // Reads the `key "name" { algorithm ...; secret "..."; };` files that tsig-keygen and
+    // keymgr generate.
+    fn parse(contents: &str) -> Result<TsigKey, String> {
+        let without_comments: String = contents
+            .lines()
+            .map(|line| line.split('#').next().unwrap().split("//").next().unwrap())
+            .collect::<Vec<&str>>()
+            .join("\n");
+        let spaced = without_comments
+            .replace('{', " { ")
+            .replace('}', " } ")
+            .replace(';', " ; ");
+        let mut tokens = spaced.split_whitespace();
+
+        let (mut name, mut algorithm, mut secret) = (None, None, None);
+        while let Some(token) = tokens.next() {
+            let field = match token {
+                "key" => &mut name,
+                "algorithm" => &mut algorithm,
+                "secret" => &mut secret,
+                _ => continue,
+            };
+            *field = tokens.next().map(|value| value.trim_matches('"'));
+        }
+
+        let name = name.ok_or("no key name was found")?;
+        match algorithm {
+            Some(algorithm) if algorithm.eq_ignore_ascii_case(HMAC_SHA256) => {}
+            Some(algorithm) => return Err(format!("unsupported algorithm {}", algorithm)),
+            None => return Err("no algorithm was found".to_string()),
+        }
+        let secret = base64::decode(secret.ok_or("no secret was found")?)
+            .map_err(|e| format!("the secret is not valid base64: {}", e))?;
+
+        Ok(TsigKey::new(name.to_string(), secret))
+    }
+}
+
+// Appends a TSIG record to `message` and returns the MAC, which is needed to verify the response.
+pub fn sign(key: &TsigKey, message: &mut Vec<u8>, time_signed: u64) -> Vec<u8> {
+    let original_id = [message[0], message[1]];
+    let mac = message_mac(key, None, message, time_signed, FUDGE_SECONDS, 0, &[])
+        .finalize()
+        .into_bytes()
+        .to_vec();
+
+    let mut rdata = encode_name(HMAC_SHA256);
+    rdata.extend_from_slice(&time_signed.to_be_bytes()[2..]);
+    rdata.extend_from_slice(&FUDGE_SECONDS.to_be_bytes());
+    rdata.extend_from_slice(&(mac.len() as u16).to_be_bytes());
+    rdata.extend_from_slice(&mac);
+    rdata.extend_from_slice(&original_id);
+    rdata.extend_from_slice(&[0, 0, 0, 0]);
+
+    message.extend(encode_name(&key.name.to_lowercase()));
+    message.extend_from_slice(&TSIG_TYPE.to_be_bytes());
+    message.extend_from_slice(&CLASS_ANY.to_be_bytes());
+    message.extend_from_slice(&0u32.to_be_bytes());
+    message.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+    message.extend(rdata);
+    let count = additional_count(message);
+    set_additional_count(message, count + 1);
+
+    mac
+}
+
+pub fn verify_response(key: &TsigKey, response: &[u8], request_mac: &[u8]) -> Result<(), IpError> {
+    let tsig = find_tsig(response).ok_or_else(|| tsig_error("the response was not signed"))?;
+
+    if tsig.error != 0 {
+        return Err(tsig_error(&format!(
+            "the server rejected the signature with {}",
+            tsig_error_name(tsig.error)
+        )));
+    }
+
+    let now = utils::get_time_in_millis() as u64 / 1000;
+    if now.max(tsig.time_signed) - now.min(tsig.time_signed) > u64::from(tsig.fudge) {
+        return Err(tsig_error(
+            "the response signature is outside the allowed time window",
+        ));
+    }
+
+    let mut unsigned = response[..tsig.offset].to_vec();
+    unsigned[..2].copy_from_slice(&tsig.original_id);
+    let count = additional_count(&unsigned);
+    set_additional_count(&mut unsigned, count - 1);
+
+    message_mac(
+        key,
+        Some(request_mac),
+        &unsigned,
+        tsig.time_signed,
+        tsig.fudge,
+        tsig.error,
+        &tsig.other,
+    )
+    .verify(&tsig.mac)
+    .map_err(|_| tsig_error("the response signature did not match"))
+}
+
+fn message_mac(
+    key: &TsigKey,
+    request_mac: Option<&[u8]>,
+    message: &[u8],
+    time_signed: u64,
+    fudge: u16,
+    error: u16,
+    other: &[u8],
+) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(&key.secret).unwrap();
+
+    if let Some(request_mac) = request_mac {
+        mac.update(&(request_mac.len() as u16).to_be_bytes());
+        mac.update(request_mac);
+    }
+    mac.update(message);
+    mac.update(&encode_name(&key.name.to_lowercase()));
+    mac.update(&CLASS_ANY.to_be_bytes());
+    mac.update(&0u32.to_be_bytes());
+    mac.update(&encode_name(HMAC_SHA256));
+    mac.update(&time_signed.to_be_bytes()[2..]);
+    mac.update(&fudge.to_be_bytes());
+    mac.update(&error.to_be_bytes());
+    mac.update(&(other.len() as u16).to_be_bytes());
+    mac.update(other);
+
+    mac
+}
+
+fn tsig_error(context: &str) -> IpError {
+    IpError::new(ErrorReason::DnsUpdateFailed(format!("TSIG: {}", context)))
+}
+
+fn tsig_error_name(error: u16) -> String {
+    match error {
+        16 => "BADSIG".to_string(),
+        17 => "BADKEY".to_string(),
+        18 => "BADTIME".to_string(),
+        22 => "BADTRUNC".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn additional_count(message: &[u8]) -> u16 {
+    u16::from_be_bytes([message[10], message[11]])
+}
+
+fn set_additional_count(message: &mut [u8], count: u16) {
+    message[10..12].copy_from_slice(&count.to_be_bytes());
+}
+
+struct Tsig {
+    offset: usize,
+    time_signed: u64,
+    fudge: u16,
+    mac: Vec<u8>,
+    original_id: [u8; 2],
+    error: u16,
+    other: Vec<u8>,
+}
+
+// The TSIG record is always the last one in the additional section.
+fn find_tsig(message: &[u8]) -> Option<Tsig> {
+    if message.len() < HEADER_LENGTH || additional_count(message) == 0 {
+        return None;
+    }
+
+    let count = |index: usize| u16::from_be_bytes([message[index], message[index + 1]]) as usize;
+    let mut position = HEADER_LENGTH;
+    for _ in 0..count(4) {
+        position = skip_name(message, position)? + 4;
+    }
+    for _ in 0..(count(6) + count(8) + count(10) - 1) {
+        position = skip_record(message, position)?;
+    }
+
+    let offset = position;
+    let mut reader = Reader::new(message, skip_name(message, position)?);
+    if reader.u16()? != TSIG_TYPE {
+        return None;
+    }
+    // Class, TTL and RDATA length, then the algorithm name.
+    reader.bytes(8)?;
+    reader.position = skip_name(message, reader.position)?;
+    let time_signed = u64::from_be_bytes([
+        0,
+        0,
+        reader.byte()?,
+        reader.byte()?,
+        reader.byte()?,
+        reader.byte()?,
+        reader.byte()?,
+        reader.byte()?,
+    ]);
+    let fudge = reader.u16()?;
+    let mac_length = reader.u16()? as usize;
+    let mac = reader.bytes(mac_length)?.to_vec();
+    let original_id = [reader.byte()?, reader.byte()?];
+    let error = reader.u16()?;
+    let other_length = reader.u16()? as usize;
+    let other = reader.bytes(other_length)?.to_vec();
+
+    Some(Tsig {
+        offset,
+        time_signed,
+        fudge,
+        mac,
+        original_id,
+        error,
+        other,
+    })
+}
+
+fn skip_record(message: &[u8], position: usize) -> Option<usize> {
+    let rdata_length_at = skip_name(message, position)? + 8;
+    let rdata_length = u16::from_be_bytes([
+        *message.get(rdata_length_at)?,
+        *message.get(rdata_length_at + 1)?,
+    ]);
+    Some(rdata_length_at + 2 + rdata_length as usize)
+}
+
+struct Reader<'a> {
+    message: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(message: &'a [u8], position: usize) -> Reader<'a> {
+        Reader { message, position }
+    }
+
+    fn bytes(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self.message.get(self.position..self.position + length)?;
+        self.position += length;
+        Some(bytes)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_FILE: &str = r#"
+# generated by tsig-keygen
+key "findip-key" {
+	algorithm hmac-sha256;
+	secret "c2VjcmV0IHNlY3JldCBzZWNyZXQgc2VjcmV0IHNlY3I=";
+};
+"#;
+
+    fn key() -> TsigKey {
+        TsigKey::parse(KEY_FILE).unwrap()
+    }
+
+    fn unsigned_message() -> Vec<u8> {
+        let mut message = vec![0x12, 0x34, 0x28, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        message.extend(encode_name("example.com"));
+        message.extend_from_slice(&[0, 6, 0, 1]);
+        message
+    }
+
+    #[test]
+    fn test_parse_key_file() {
+        let key = key();
+
+        assert_eq!(key.name, "findip-key");
+        assert_eq!(key.secret, b"secret secret secret secret secr".to_vec());
+    }
+
+    #[test]
+    fn test_parse_key_file_with_unsupported_algorithm() {
+        let contents = KEY_FILE.replace("hmac-sha256", "hmac-md5");
+        assert!(TsigKey::parse(&contents)
+            .unwrap_err()
+            .contains("unsupported algorithm"));
+    }
+
+    #[test]
+    fn test_parse_key_file_without_secret() {
+        assert!(TsigKey::parse("key \"findip-key\" { algorithm hmac-sha256; };").is_err());
+    }
+
+    #[test]
+    fn test_sign_appends_tsig_record() {
+        let mut message = unsigned_message();
+        let unsigned_length = message.len();
+        let mac = sign(&key(), &mut message, 1_600_000_000);
+        let tsig = find_tsig(&message).unwrap();
+
+        assert_eq!(additional_count(&message), 1);
+        assert_eq!(tsig.offset, unsigned_length);
+        assert_eq!(tsig.mac, mac);
+        assert_eq!(tsig.mac.len(), 32);
+        assert_eq!(tsig.time_signed, 1_600_000_000);
+        assert_eq!(tsig.original_id, [0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_verify_response_without_signature() {
+        let mut response = unsigned_message();
+        response[2] |= 0x80;
+
+        assert!(verify_response(&key(), &response, &[0u8; 32]).is_err());
+    }
+}
@@ -7,6 +7,11 @@ use std::{
 use chrono::prelude::*;
 use clap::ArgMatches;
 
+pub const TOKEN_IP_ADDRESS: &str = "{{TOKEN_IP_ADDRESS}}";
+pub const TOKEN_PREVIOUS_IP_ADDRESS: &str = "{{TOKEN_PREVIOUS_IP_ADDRESS}}";
+pub const TOKEN_HOSTNAME: &str = "{{TOKEN_HOSTNAME}}";
+pub const TOKEN_TIMESTAMP: &str = "{{TOKEN_TIMESTAMP}}";
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum VerbosityLevel {
     Standard,
@@ -33,6 +38,13 @@ pub fn parse_into_utc(expiration_str: String) -> DateTime<Utc> {
     )
 }
 
+pub fn get_hostname() -> String {
+    hostname::get()
+        .ok()
+        .and_then(|name| name.into_string().ok())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
 pub fn replace_tokens(original: String, token_value_map: HashMap<String, String>) -> String {
     let result = original.clone();
 
@@ -0,0 +1,438 @@
+use std::{collections::HashSet, net::IpAddr};
+
+use chrono::NaiveDate;
+
+use crate::{
+    config_file::SerialFormat,
+    dns::{fqdn, RecordType},
+};
+
+#[derive(Debug, Clone)]
+struct Token {
+    line: usize,
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+// A directive or a record, which can span several lines inside parentheses.
+#[derive(Debug)]
+struct Entry {
+    first_line: usize,
+    last_line: usize,
+    // Records starting with whitespace belong to the owner of the record before them.
+    inherits_owner: bool,
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug)]
+struct Edit {
+    line: usize,
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+// Points the A or AAAA records (depending on `ip`) of `names` at `ip` and bumps the SOA serial,
+// changing nothing else in `contents`. Returns `None` when the records already point at `ip`.
+// When a name has several records of the type, only the first is changed.
+pub fn update_addresses(
+    contents: &str,
+    zone: &str,
+    names: &[String],
+    ip: IpAddr,
+    serial_format: SerialFormat,
+    today: NaiveDate,
+) -> Result<Option<String>, String> {
+    let lines: Vec<&str> = contents.split_inclusive('\n').collect();
+    let entries = parse_entries(&lines)?;
+    let record_type = RecordType::for_ip(ip);
+    let wanted: HashSet<String> = names.iter().map(|name| fqdn(name).to_lowercase()).collect();
+
+    let mut origin = fqdn(zone).to_lowercase();
+    let mut owner = origin.clone();
+    let mut serial = None;
+    let mut found = HashSet::new();
+    let mut edits = Vec::new();
+    let mut changed = false;
+
+    for entry in entries.iter() {
+        let mut tokens = entry.tokens.iter().peekable();
+        if !entry.inherits_owner {
+            let first = tokens.next().unwrap();
+            if first.text.starts_with('$') {
+                if first.text.eq_ignore_ascii_case("$ORIGIN") {
+                    let name = tokens.next().ok_or("$ORIGIN without a name")?;
+                    origin = absolute_name(&name.text, &origin);
+                }
+                continue;
+            }
+            owner = absolute_name(&first.text, &origin);
+        }
+
+        // The TTL and class are both optional and can come in either order.
+        for _ in 0..2 {
+            if let Some(token) = tokens.peek() {
+                if is_ttl(&token.text) || is_class(&token.text) {
+                    tokens.next();
+                }
+            }
+        }
+        let entry_type = match tokens.next() {
+            Some(token) => token.text.to_uppercase(),
+            None => return Err(format!("line {} has no record type", entry.first_line + 1)),
+        };
+        let rdata: Vec<&Token> = tokens.collect();
+
+        if entry_type == "SOA" && serial.is_none() {
+            serial = Some(rdata.get(2).cloned().ok_or_else(|| {
+                format!(
+                    "the SOA record on line {} has no serial",
+                    entry.first_line + 1
+                )
+            })?);
+        }
+
+        let is_wanted = entry_type == record_type.as_str() && wanted.contains(&owner);
+        if is_wanted && !found.contains(&owner) {
+            found.insert(owner.clone());
+            let address = rdata.first().ok_or_else(|| {
+                format!("the record on line {} has no address", entry.first_line + 1)
+            })?;
+            if address.text.parse::<IpAddr>().ok() != Some(ip) {
+                edits.push(Edit {
+                    line: address.line,
+                    start: address.start,
+                    end: address.end,
+                    text: ip.to_string(),
+                });
+                changed = true;
+            }
+        }
+    }
+
+    let mut missing: Vec<String> = wanted.difference(&found).cloned().collect();
+    if !missing.is_empty() {
+        missing.sort();
+        return Err(format!(
+            "there is no {} record for {}",
+            record_type,
+            missing.join(", ")
+        ));
+    }
+    if !changed {
+        return Ok(None);
+    }
+
+    let serial = serial.ok_or("the zone has no SOA record")?;
+    let current: u32 = serial
+        .text
+        .parse()
+        .map_err(|_| format!("the SOA serial {} is not a number", serial.text))?;
+    edits.push(Edit {
+        line: serial.line,
+        start: serial.start,
+        end: serial.end,
+        text: next_serial(current, serial_format, today).to_string(),
+    });
+
+    Ok(Some(apply_edits(&lines, edits)))
+}
+
+pub fn next_serial(current: u32, serial_format: SerialFormat, today: NaiveDate) -> u32 {
+    match serial_format {
+        // YYYYMMDDnn, falling back to counting once the day's 100 changes are used up.
+        SerialFormat::Date => {
+            let first_of_today = today.format("%Y%m%d00").to_string().parse().unwrap_or(0);
+            current.wrapping_add(1).max(first_of_today)
+        }
+        SerialFormat::Counter => current.wrapping_add(1),
+    }
+}
+
+fn parse_entries(lines: &[&str]) -> Result<Vec<Entry>, String> {
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut depth = 0;
+
+    for (number, line) in lines.iter().enumerate() {
+        let inside_parentheses = depth > 0;
+        let tokens = tokenize(number, line, &mut depth)?;
+
+        match entries.last_mut() {
+            Some(entry) if inside_parentheses => {
+                entry.last_line = number;
+                entry.tokens.extend(tokens);
+            }
+            _ if tokens.is_empty() => {}
+            _ => entries.push(Entry {
+                first_line: number,
+                last_line: number,
+                inherits_owner: line.starts_with([' ', '\t']),
+                tokens,
+            }),
+        }
+    }
+
+    if depth != 0 {
+        return Err("unbalanced parentheses".to_string());
+    }
+    Ok(entries)
+}
+
+// Splits a line into tokens, skipping comments. Parentheses only let an entry span lines, so
+// they are counted in `depth` instead of being returned.
+fn tokenize(number: usize, line: &str, depth: &mut usize) -> Result<Vec<Token>, String> {
+    let bytes = line.as_bytes();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+
+    while position < bytes.len() {
+        match bytes[position] {
+            b' ' | b'\t' | b'\r' | b'\n' => position += 1,
+            b';' => break,
+            b'(' => {
+                *depth += 1;
+                position += 1;
+            }
+            b')' => {
+                *depth = depth
+                    .checked_sub(1)
+                    .ok_or_else(|| format!("unbalanced parentheses on line {}", number + 1))?;
+                position += 1;
+            }
+            b'"' => {
+                let start = position;
+                position += 1;
+                while position < bytes.len() && bytes[position] != b'"' {
+                    position += if bytes[position] == b'\\' { 2 } else { 1 };
+                }
+                position = (position + 1).min(bytes.len());
+                tokens.push(token(number, line, start, position));
+            }
+            _ => {
+                let start = position;
+                while position < bytes.len()
+                    && !matches!(
+                        bytes[position],
+                        b' ' | b'\t' | b'\r' | b'\n' | b';' | b'(' | b')'
+                    )
+                {
+                    position += 1;
+                }
+                tokens.push(token(number, line, start, position));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn token(line: usize, text: &str, start: usize, end: usize) -> Token {
+    Token {
+        line,
+        start,
+        end,
+        text: text[start..end].to_string(),
+    }
+}
+
+fn absolute_name(name: &str, origin: &str) -> String {
+    if name == "@" {
+        origin.to_string()
+    } else if name.ends_with('.') {
+        name.to_lowercase()
+    } else {
+        format!("{}.{}", name, origin).to_lowercase()
+    }
+}
+
+fn is_ttl(token: &str) -> bool {
+    token.starts_with(|c: char| c.is_ascii_digit())
+}
+
+fn is_class(token: &str) -> bool {
+    ["IN", "CH", "HS", "CS"]
+        .iter()
+        .any(|class| token.eq_ignore_ascii_case(class))
+}
+
+fn apply_edits(lines: &[&str], mut edits: Vec<Edit>) -> String {
+    let mut lines: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+
+    // Replacements further along a line go first so the earlier offsets stay valid.
+    edits.sort_by_key(|edit| (edit.line, usize::MAX - edit.start));
+    for edit in edits.into_iter() {
+        lines[edit.line].replace_range(edit.start..edit.end, &edit.text);
+    }
+
+    lines.concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    const ZONE: &str = "$ORIGIN example.com.
+$TTL 3600
+; the zone for example.com
+@\tIN\tSOA\tns1.example.com. hostmaster.example.com. (
+\t\t2024010101 ; serial
+\t\t3600       ; refresh
+\t\t900        ; retry
+\t\t1209600    ; expire
+\t\t300 )      ; minimum
+\t\tIN\tNS\tns1.example.com.
+ns1\t\tIN\tA\t192.0.2.53
+home\t300\tIN\tA\t198.51.100.1 ; updated by findip
+\t\tIN\tAAAA\t2001:db8::1
+\t\tIN\tTXT\t\"home; sweet home\"
+vpn.example.com. IN 60 A 198.51.100.1
+";
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+    }
+
+    fn names() -> Vec<String> {
+        vec![
+            "home.example.com".to_string(),
+            "vpn.example.com.".to_string(),
+        ]
+    }
+
+    fn update(
+        contents: &str,
+        ip: IpAddr,
+        serial_format: SerialFormat,
+    ) -> Result<Option<String>, String> {
+        update_addresses(
+            contents,
+            "example.com",
+            &names(),
+            ip,
+            serial_format,
+            today(),
+        )
+    }
+
+    #[test]
+    fn test_replaces_addresses_and_bumps_serial() {
+        let updated = update(
+            ZONE,
+            IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)),
+            SerialFormat::Date,
+        )
+        .unwrap()
+        .unwrap();
+
+        let expected = ZONE
+            .replace("2024010101 ; serial", "2024010102 ; serial")
+            .replace("198.51.100.1 ; updated", "203.0.113.7 ; updated")
+            .replace("IN 60 A 198.51.100.1", "IN 60 A 203.0.113.7");
+        assert_eq!(updated, expected);
+    }
+
+    #[test]
+    fn test_replaces_aaaa_records_for_ipv6() {
+        let mut contents = ZONE.to_string();
+        contents.push_str("vpn\tIN\tAAAA\t2001:db8::1\n");
+
+        let updated = update(
+            &contents,
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 7)),
+            SerialFormat::Counter,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            updated,
+            contents
+                .replace("2024010101 ; serial", "2024010102 ; serial")
+                .replace("IN\tAAAA\t2001:db8::1\n", "IN\tAAAA\t2001:db8::7\n")
+        );
+    }
+
+    #[test]
+    fn test_unchanged_zone_is_left_alone() {
+        let current = ZONE.replace("198.51.100.1", "203.0.113.7");
+
+        assert_eq!(
+            update(
+                &current,
+                IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)),
+                SerialFormat::Date
+            ),
+            Ok(None)
+        );
+    }
+
+    #[test]
+    fn test_missing_records_are_an_error() {
+        let err = update(
+            ZONE,
+            IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 7)),
+            SerialFormat::Date,
+        )
+        .unwrap_err();
+
+        assert_eq!(err, "there is no AAAA record for vpn.example.com.");
+    }
+
+    #[test]
+    fn test_only_the_first_record_is_changed() {
+        let contents = "@ IN SOA ns1 hostmaster 7 3600 900 1209600 300
+home IN A 198.51.100.1
+home IN A 198.51.100.2 ; the backup line, keep it
+     IN MX 10 mail
+vpn IN A 198.51.100.1
+";
+
+        let updated = update(
+            contents,
+            IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)),
+            SerialFormat::Counter,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(
+            updated,
+            "@ IN SOA ns1 hostmaster 8 3600 900 1209600 300
+home IN A 203.0.113.7
+home IN A 198.51.100.2 ; the backup line, keep it
+     IN MX 10 mail
+vpn IN A 203.0.113.7
+"
+        );
+    }
+
+    #[test]
+    fn test_next_serial() {
+        assert_eq!(
+            next_serial(2023123105, SerialFormat::Date, today()),
+            2024010100
+        );
+        assert_eq!(
+            next_serial(2024010100, SerialFormat::Date, today()),
+            2024010101
+        );
+        assert_eq!(
+            next_serial(2024010199, SerialFormat::Date, today()),
+            2024010200
+        );
+        assert_eq!(next_serial(41, SerialFormat::Counter, today()), 42);
+    }
+
+    #[test]
+    fn test_unbalanced_parentheses_are_an_error() {
+        assert!(update(
+            "@ IN SOA ns1 hostmaster ( 7 3600\n",
+            IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7)),
+            SerialFormat::Date
+        )
+        .is_err());
+    }
+}